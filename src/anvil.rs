@@ -0,0 +1,424 @@
+use crate::block::Block;
+use crate::chunk_manager::ChunkSource;
+use crate::nbt::{read_nbt, read_nbt_file, Tag};
+use crate::world::{BlockPos, Chunk, ChunkPos, SECTIONS_PER_CHUNK, SECTION_SIZE};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+const SECTOR_SIZE: u64 = 4096;
+const REGION_SIZE: i32 = 32;
+
+// From 1.16 block state entries no longer span two longs.
+const PADDED_BLOCK_STATES_VERSION: i32 = 2527;
+// 1.18 moved sections out of the Level compound.
+const FLAT_CHUNK_VERSION: i32 = 2844;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// An open `.mca` region file, holding 32 by 32 chunks.
+struct RegionFile {
+    file: File,
+    path: PathBuf,
+    // Position of the region, in regions.
+    x: i32,
+    z: i32,
+    // Where each chunk is: the offset in sectors in the top three bytes
+    // and the length in sectors in the bottom one.
+    locations: Vec<u32>,
+}
+
+impl RegionFile {
+    fn open(path: &Path, x: i32, z: i32) -> io::Result<RegionFile> {
+        let mut file = File::open(path)?;
+        let mut header = vec![0; SECTOR_SIZE as usize];
+        file.read_exact(&mut header)?;
+        let locations = header
+            .chunks(4)
+            .map(|entry| u32::from_be_bytes(entry.try_into().unwrap()))
+            .collect();
+        Ok(RegionFile {
+            file,
+            path: path.to_owned(),
+            x,
+            z,
+            locations,
+        })
+    }
+
+    /// Reads the NBT of a chunk, given its position within the region.
+    /// Returns None if the chunk hasn't been generated.
+    fn read_chunk(&mut self, x: i32, z: i32) -> io::Result<Option<Tag>> {
+        let location = self.locations[(x + z * REGION_SIZE) as usize];
+        if location == 0 {
+            return Ok(None);
+        }
+
+        self.file
+            .seek(SeekFrom::Start((location >> 8) as u64 * SECTOR_SIZE))?;
+        let mut header = [0; 5];
+        self.file.read_exact(&mut header)?;
+        let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let compression = header[4];
+        if length == 0 || length > (location & 0xff) as usize * SECTOR_SIZE as usize {
+            return Err(invalid_data("chunk is longer than its sectors"));
+        }
+
+        // Chunks too big for the region are kept in a file of their own.
+        let compressed = if compression & 0x80 != 0 {
+            let chunk_x = x + self.x * REGION_SIZE;
+            let chunk_z = z + self.z * REGION_SIZE;
+            let path = self
+                .path
+                .with_file_name(format!("c.{}.{}.mcc", chunk_x, chunk_z));
+            fs::read(path)?
+        } else {
+            let mut compressed = vec![0; length - 1];
+            self.file.read_exact(&mut compressed)?;
+            compressed
+        };
+
+        let mut data = Vec::new();
+        match compression & 0x7f {
+            1 => {
+                GzDecoder::new(&compressed[..]).read_to_end(&mut data)?;
+            }
+            2 => {
+                ZlibDecoder::new(&compressed[..]).read_to_end(&mut data)?;
+            }
+            3 => data = compressed,
+            _ => return Err(invalid_data("unknown chunk compression")),
+        }
+        let (_, tag) = read_nbt(&data)?;
+        Ok(Some(tag))
+    }
+}
+
+/// Loads chunks from the region files of a world save on disk.
+pub struct AnvilSource {
+    region_directory: PathBuf,
+    // Region files opened so far, or None for regions that don't exist.
+    regions: HashMap<(i32, i32), Option<RegionFile>>,
+}
+
+impl AnvilSource {
+    /// Opens either a world directory or the region directory inside it.
+    pub fn open(path: &Path) -> io::Result<AnvilSource> {
+        let region_directory = if path.join("region").is_dir() {
+            path.join("region")
+        } else {
+            path.to_owned()
+        };
+        if !region_directory.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no region directory found",
+            ));
+        }
+        Ok(AnvilSource {
+            region_directory,
+            regions: HashMap::new(),
+        })
+    }
+
+    fn read_chunk(&mut self, pos: ChunkPos) -> io::Result<Option<Chunk>> {
+        let region_x = pos.x.div_euclid(REGION_SIZE);
+        let region_z = pos.z.div_euclid(REGION_SIZE);
+        let directory = &self.region_directory;
+        let region = self.regions.entry((region_x, region_z)).or_insert_with(|| {
+            let path = directory.join(format!("r.{}.{}.mca", region_x, region_z));
+            RegionFile::open(&path, region_x, region_z).ok()
+        });
+        let region = match region {
+            Some(region) => region,
+            None => return Ok(None),
+        };
+
+        let tag =
+            region.read_chunk(pos.x.rem_euclid(REGION_SIZE), pos.z.rem_euclid(REGION_SIZE))?;
+        match tag {
+            Some(tag) => decode_chunk(&tag).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl ChunkSource for AnvilSource {
+    fn load_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        match self.read_chunk(pos) {
+            Ok(chunk) => chunk,
+            Err(error) => {
+                eprintln!("Unable to load chunk {}, {}: {}", pos.x, pos.z, error);
+                None
+            }
+        }
+    }
+}
+
+/// Reads the spawn point out of a world's `level.dat`, if it has one.
+pub fn read_spawn(world_directory: &Path) -> Option<BlockPos> {
+    let (_, root) = read_nbt_file(&world_directory.join("level.dat")).ok()?;
+    let data = root.get("Data")?;
+    Some(BlockPos::new(
+        data.get("SpawnX")?.as_i32()?,
+        data.get("SpawnY")?.as_i32()?,
+        data.get("SpawnZ")?.as_i32()?,
+    ))
+}
+
+fn decode_chunk(root: &Tag) -> io::Result<Chunk> {
+    let data_version = root.get("DataVersion").and_then(Tag::as_i32).unwrap_or(0);
+    let sections = if data_version >= FLAT_CHUNK_VERSION {
+        root.get("sections")
+    } else {
+        root.get("Level").and_then(|level| level.get("Sections"))
+    };
+    let sections = sections
+        .and_then(Tag::as_list)
+        .ok_or_else(|| invalid_data("chunk has no sections"))?;
+
+    let mut chunk = Chunk::new();
+    for section in sections {
+        let section_y = match section.get("Y").and_then(Tag::as_i32) {
+            Some(y) => y as i8 as i32,
+            None => continue,
+        };
+        // Our world only goes from 0 to 255.
+        if section_y < 0 || section_y >= SECTIONS_PER_CHUNK as i32 {
+            continue;
+        }
+
+        let (palette, states) = if data_version >= FLAT_CHUNK_VERSION {
+            let block_states = section.get("block_states");
+            (
+                block_states.and_then(|states| states.get("palette")),
+                block_states.and_then(|states| states.get("data")),
+            )
+        } else {
+            (section.get("Palette"), section.get("BlockStates"))
+        };
+        if let Some(palette) = palette.and_then(Tag::as_list) {
+            let palette: Vec<Block> = palette.iter().map(palette_block).collect();
+            let states = states.and_then(Tag::as_long_array).unwrap_or(&[]);
+            let spanning = data_version < PADDED_BLOCK_STATES_VERSION;
+            let indices = unpack_block_states(states, palette.len(), spanning)
+                .ok_or_else(|| invalid_data("block states don't match the palette"))?;
+
+            for (i, index) in indices.iter().enumerate() {
+                let block = palette
+                    .get(*index as usize)
+                    .copied()
+                    .ok_or_else(|| invalid_data("block state missing from the palette"))?;
+                if !block.is_air() {
+                    let i = i as i32;
+                    chunk.set_block(
+                        i & 15,
+                        section_y * SECTION_SIZE + (i >> 8),
+                        (i >> 4) & 15,
+                        block,
+                    );
+                }
+            }
+        }
+
+        let light = |name| {
+            section
+                .get(name)
+                .and_then(Tag::as_bytes)
+                .filter(|light| light.len() == 2048)
+        };
+        let (sky_light, block_light) = (light("SkyLight"), light("BlockLight"));
+        if sky_light.is_some() || block_light.is_some() {
+            chunk.set_section_light(section_y, sky_light, block_light);
+        }
+    }
+
+    let block_entities = if data_version >= FLAT_CHUNK_VERSION {
+        root.get("block_entities")
+    } else {
+        root.get("Level")
+            .and_then(|level| level.get("TileEntities"))
+    };
+    for data in block_entities.and_then(Tag::as_list).unwrap_or(&[]) {
+        chunk.insert_block_entity(data.clone());
+    }
+    Ok(chunk)
+}
+
+/// Builds the block for a palette entry, a compound of a name and its
+/// properties.
+fn palette_block(entry: &Tag) -> Block {
+    let name = entry
+        .get("Name")
+        .and_then(Tag::as_str)
+        .unwrap_or("minecraft:air");
+    let properties: Vec<String> = entry
+        .get("Properties")
+        .and_then(Tag::as_compound)
+        .map(|properties| {
+            properties
+                .iter()
+                .filter_map(|(key, value)| Some(format!("{}={}", key, value.as_str()?)))
+                .collect()
+        })
+        .unwrap_or_default();
+    if properties.is_empty() {
+        Block::from_state(name)
+    } else {
+        Block::from_state(&format!("{}[{}]", name, properties.join(",")))
+    }
+}
+
+/// Unpacks the palette indices of a section's 4096 blocks. Before 1.16 an
+/// index could be split across two longs, after it the leftover bits of
+/// each long are padding.
+fn unpack_block_states(states: &[i64], palette_size: usize, spanning: bool) -> Option<Vec<u16>> {
+    let count = (SECTION_SIZE * SECTION_SIZE * SECTION_SIZE) as usize;
+    // A single entry palette needs no data at all.
+    if palette_size <= 1 {
+        return Some(vec![0; count]);
+    }
+
+    let mut bits = 4;
+    while (1 << bits) < palette_size {
+        bits += 1;
+    }
+    let mask = (1u64 << bits) - 1;
+
+    let mut indices = Vec::with_capacity(count);
+    if spanning {
+        if states.len() * 64 < count * bits {
+            return None;
+        }
+        for i in 0..count {
+            let bit = i * bits;
+            let (long, offset) = (bit / 64, bit % 64);
+            let mut value = states[long] as u64 >> offset;
+            if offset + bits > 64 {
+                value |= (states[long + 1] as u64) << (64 - offset);
+            }
+            indices.push((value & mask) as u16);
+        }
+    } else {
+        let per_long = 64 / bits;
+        if states.len() * per_long < count {
+            return None;
+        }
+        for i in 0..count {
+            let value = states[i / per_long] as u64 >> ((i % per_long) * bits);
+            indices.push((value & mask) as u16);
+        }
+    }
+    Some(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs palette indices into `longs` longs the way a chunk section
+    /// stores them.
+    fn pack(indices: &[u16], bits: usize, spanning: bool, longs: usize) -> Vec<i64> {
+        let mut states = vec![0u64; longs];
+        if spanning {
+            for (i, index) in indices.iter().enumerate() {
+                let bit = i * bits;
+                let (long, offset) = (bit / 64, bit % 64);
+                states[long] |= (*index as u64) << offset;
+                if offset + bits > 64 {
+                    states[long + 1] |= (*index as u64) >> (64 - offset);
+                }
+            }
+        } else {
+            let per_long = 64 / bits;
+            for (i, index) in indices.iter().enumerate() {
+                states[i / per_long] |= (*index as u64) << ((i % per_long) * bits);
+            }
+        }
+        states.into_iter().map(|long| long as i64).collect()
+    }
+
+    // Five bits per block, so that spanning entries cross longs and padded
+    // ones leave four bits of each long unused. That takes 320 longs
+    // spanning and 342 padded.
+    fn known_indices() -> Vec<u16> {
+        (0..4096).map(|i| ((i * 7) % 17) as u16).collect()
+    }
+
+    #[test]
+    fn unpacks_indices_spanning_longs() {
+        let indices = known_indices();
+        let states = pack(&indices, 5, true, 320);
+        assert_eq!(unpack_block_states(&states, 17, true), Some(indices));
+    }
+
+    #[test]
+    fn unpacks_indices_padded_to_longs() {
+        let indices = known_indices();
+        let states = pack(&indices, 5, false, 342);
+        assert_eq!(unpack_block_states(&states, 17, false), Some(indices));
+        // Too short for the padded layout.
+        assert_eq!(unpack_block_states(&states[..320], 17, false), None);
+    }
+
+    #[test]
+    fn single_entry_palette_needs_no_data() {
+        assert_eq!(unpack_block_states(&[], 1, false), Some(vec![0; 4096]));
+    }
+
+    #[test]
+    fn data_version_picks_the_layout() {
+        let indices = known_indices();
+        let palette: Vec<Tag> = (0..17)
+            .map(|i| {
+                let mut entry = HashMap::new();
+                let name = if i == 0 {
+                    "minecraft:air".to_owned()
+                } else {
+                    format!("minecraft:test_{}", i)
+                };
+                entry.insert("Name".to_owned(), Tag::String(name));
+                Tag::Compound(entry)
+            })
+            .collect();
+        let chunk = |data_version: i32, spanning: bool| {
+            let mut section = HashMap::new();
+            section.insert("Y".to_owned(), Tag::Byte(0));
+            section.insert("Palette".to_owned(), Tag::List(palette.clone()));
+            let longs = if spanning { 320 } else { 342 };
+            section.insert(
+                "BlockStates".to_owned(),
+                Tag::LongArray(pack(&indices, 5, spanning, longs)),
+            );
+            let mut level = HashMap::new();
+            level.insert(
+                "Sections".to_owned(),
+                Tag::List(vec![Tag::Compound(section)]),
+            );
+            let mut root = HashMap::new();
+            root.insert("DataVersion".to_owned(), Tag::Int(data_version));
+            root.insert("Level".to_owned(), Tag::Compound(level));
+            decode_chunk(&Tag::Compound(root)).unwrap()
+        };
+
+        let blocks = |chunk: &Chunk| -> Vec<Block> {
+            (0..4096)
+                .map(|i| chunk.get_block(i & 15, i >> 8, (i >> 4) & 15))
+                .collect()
+        };
+        let expected: Vec<Block> = indices
+            .iter()
+            .map(|index| palette_block(&palette[*index as usize]))
+            .collect();
+        assert_eq!(
+            blocks(&chunk(PADDED_BLOCK_STATES_VERSION - 1, true)),
+            expected
+        );
+        assert_eq!(blocks(&chunk(PADDED_BLOCK_STATES_VERSION, false)), expected);
+    }
+}
@@ -0,0 +1,30 @@
+use std::env;
+use std::str::FromStr;
+
+/// The value following `name` on the command line, e.g. the file in
+/// `--replay <file>`.
+pub fn argument(name: &str) -> Option<String> {
+    let mut args = env::args().skip_while(|arg| arg != name);
+    args.next()?;
+    Some(
+        args.next()
+            .unwrap_or_else(|| panic!("Missing value after '{}'", name)),
+    )
+}
+
+/// Parses `count` values separated by `separator`, e.g. `1,2,3`.
+pub fn parse_list<T: FromStr>(
+    value: &str,
+    separator: char,
+    count: usize,
+) -> Result<Vec<T>, String> {
+    let values: Vec<T> = value
+        .split(separator)
+        .map(|part| part.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid value '{}'", value))?;
+    if values.len() != count {
+        return Err(format!("Invalid value '{}'", value));
+    }
+    Ok(values)
+}
@@ -0,0 +1,648 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Down,
+    Up,
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::Down,
+        Direction::Up,
+        Direction::North,
+        Direction::South,
+        Direction::West,
+        Direction::East,
+    ];
+
+    /// Parses a direction as named in block state properties.
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "down" => Some(Direction::Down),
+            "up" => Some(Direction::Up),
+            "north" => Some(Direction::North),
+            "south" => Some(Direction::South),
+            "west" => Some(Direction::West),
+            "east" => Some(Direction::East),
+            _ => None,
+        }
+    }
+
+    pub fn offset(self) -> (i32, i32, i32) {
+        match self {
+            Direction::Down => (0, -1, 0),
+            Direction::Up => (0, 1, 0),
+            Direction::North => (0, 0, -1),
+            Direction::South => (0, 0, 1),
+            Direction::West => (-1, 0, 0),
+            Direction::East => (1, 0, 0),
+        }
+    }
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Down => Direction::Up,
+            Direction::Up => Direction::Down,
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+            Direction::East => Direction::West,
+        }
+    }
+}
+
+/// Which render pass a block is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderLayer {
+    Opaque,
+    /// Drawn without culling, with fragments below the alpha cutoff
+    /// discarded.
+    Cutout,
+    /// Blended, and drawn back-to-front after everything else.
+    Translucent,
+}
+
+// `RedstoneBlock` is the name of the block in the game, not a repeat of
+// the enum's.
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Block {
+    #[default]
+    Air,
+    Stone,
+    Glass,
+    Water,
+    RedstoneBlock,
+    RedstoneLamp {
+        lit: bool,
+    },
+    RedstoneWire {
+        power: u8,
+    },
+    RedstoneTorch {
+        lit: bool,
+    },
+    /// Any block we don't model individually, identified by its
+    /// interned block state. These are drawn as plain boxes, roughly the
+    /// size of the block.
+    Other(u16),
+}
+
+impl Block {
+    /// Parses a block state such as `minecraft:redstone_wire[power=15]`.
+    /// Blocks we don't model are kept as `Other`, so that their state can
+    /// still be told apart from other blocks.
+    pub fn from_state(state: &str) -> Block {
+        let (name, properties) = parse_state(state);
+        let property = |key: &str| {
+            properties
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+
+        match name.as_str() {
+            "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air" => Block::Air,
+            "minecraft:stone" => Block::Stone,
+            "minecraft:glass" => Block::Glass,
+            "minecraft:water" => Block::Water,
+            "minecraft:redstone_block" => Block::RedstoneBlock,
+            "minecraft:redstone_lamp" => Block::RedstoneLamp {
+                lit: property("lit") == Some("true"),
+            },
+            "minecraft:redstone_wire" => Block::RedstoneWire {
+                power: property("power")
+                    .and_then(|power| power.parse().ok())
+                    .unwrap_or(0u8)
+                    .min(15),
+            },
+            "minecraft:redstone_torch" => Block::RedstoneTorch {
+                lit: property("lit") != Some("false"),
+            },
+            _ => Block::Other(intern_state(&format_state(&name, &properties))),
+        }
+    }
+
+    /// The block state this block was made from, in the form accepted by
+    /// `from_state`.
+    pub fn state(self) -> String {
+        match self {
+            Block::Air => "minecraft:air".to_owned(),
+            Block::Stone => "minecraft:stone".to_owned(),
+            Block::Glass => "minecraft:glass".to_owned(),
+            Block::Water => "minecraft:water".to_owned(),
+            Block::RedstoneBlock => "minecraft:redstone_block".to_owned(),
+            Block::RedstoneLamp { lit } => format!("minecraft:redstone_lamp[lit={}]", lit),
+            Block::RedstoneWire { power } => format!("minecraft:redstone_wire[power={}]", power),
+            Block::RedstoneTorch { lit } => format!("minecraft:redstone_torch[lit={}]", lit),
+            Block::Other(id) => OTHER_STATES.read().unwrap().states[id as usize].clone(),
+        }
+    }
+
+    /// The namespaced name of the block and its properties, sorted by key.
+    pub fn name_and_properties(self) -> (String, Vec<(String, String)>) {
+        parse_state(&self.state())
+    }
+
+    /// The same block with one property of its state changed or added.
+    pub fn with_property(self, key: &str, value: &str) -> Block {
+        let (name, mut properties) = self.name_and_properties();
+        match properties.iter_mut().find(|(k, _)| k == key) {
+            Some(property) => property.1 = value.to_owned(),
+            None => {
+                properties.push((key.to_owned(), value.to_owned()));
+                properties.sort();
+            }
+        }
+        Block::from_state(&format_state(&name, &properties))
+    }
+
+    pub fn is_air(self) -> bool {
+        self == Block::Air
+    }
+
+    /// Whether the block fills its whole cell. Only full cubes can hide
+    /// the faces of their neighbours.
+    pub fn is_full_cube(self) -> bool {
+        match self {
+            Block::Air | Block::RedstoneWire { .. } | Block::RedstoneTorch { .. } => false,
+            Block::Other(id) => other_kind(id).bounds.is_none(),
+            _ => true,
+        }
+    }
+
+    /// Whether players collide with the block.
+    pub fn is_solid(self) -> bool {
+        match self {
+            Block::Water => false,
+            Block::Other(id) => other_kind(id).solid,
+            _ => self.is_full_cube(),
+        }
+    }
+
+    /// Whether the block completely hides whatever is behind it.
+    pub fn is_opaque(self) -> bool {
+        match self {
+            Block::Glass | Block::Water => false,
+            Block::Other(id) => {
+                let kind = other_kind(id);
+                kind.bounds.is_none() && kind.layer == RenderLayer::Opaque
+            }
+            _ => self.is_full_cube(),
+        }
+    }
+
+    pub fn render_layer(self) -> RenderLayer {
+        match self {
+            Block::Glass | Block::Water => RenderLayer::Translucent,
+            Block::RedstoneWire { .. } | Block::RedstoneTorch { .. } => RenderLayer::Cutout,
+            Block::Other(id) => other_kind(id).layer,
+            _ => RenderLayer::Opaque,
+        }
+    }
+
+    /// The box a block we don't model is drawn as when it isn't a full
+    /// cube, relative to the corner of its cell.
+    pub fn partial_bounds(self) -> Option<([f32; 3], [f32; 3])> {
+        match self {
+            Block::Other(id) => {
+                let (min, max) = other_kind(id).bounds?;
+                Some((min.map(|v| v as f32 / 16.0), max.map(|v| v as f32 / 16.0)))
+            }
+            _ => None,
+        }
+    }
+
+    /// How slippery the block is to walk on. Players keep more of their
+    /// speed on slippery blocks, but take longer to get up to it.
+    pub fn slipperiness(self) -> f32 {
+        match self {
+            Block::Other(id) => other_kind(id).slipperiness,
+            _ => DEFAULT_SLIPPERINESS,
+        }
+    }
+
+    pub fn alpha(self) -> f32 {
+        match self {
+            Block::Glass => 0.3,
+            Block::Water => 0.6,
+            Block::Other(id) => other_kind(id).alpha,
+            _ => 1.0,
+        }
+    }
+
+    pub fn color(self) -> [f32; 3] {
+        match self {
+            Block::Air => [0.0, 0.0, 0.0],
+            Block::Stone => [0.5, 0.5, 0.5],
+            Block::Glass => [0.8, 0.9, 0.95],
+            Block::Water => [0.2, 0.3, 0.9],
+            Block::RedstoneBlock => [0.7, 0.05, 0.0],
+            Block::RedstoneLamp { lit: false } => [0.45, 0.28, 0.15],
+            Block::RedstoneLamp { lit: true } => [0.95, 0.8, 0.5],
+            Block::RedstoneWire { power } => {
+                // Vanilla scales the red channel with the signal strength,
+                // keeping unpowered wire a dark red.
+                let strength = power as f32 / 15.0;
+                [0.3 + strength * 0.7, 0.0, 0.0]
+            }
+            Block::RedstoneTorch { lit: false } => [0.35, 0.1, 0.05],
+            Block::RedstoneTorch { lit: true } => [1.0, 0.2, 0.1],
+            Block::Other(id) => OTHER_STATES.read().unwrap().colors[id as usize],
+        }
+    }
+}
+
+lazy_static! {
+    // Interned states of the blocks we don't model. A block's id is its
+    // index here, so that `Block` can stay Copy.
+    static ref OTHER_STATES: RwLock<OtherStates> = RwLock::new(OtherStates {
+        states: Vec::new(),
+        colors: Vec::new(),
+        kinds: Vec::new(),
+        ids: HashMap::new(),
+    });
+}
+
+struct OtherStates {
+    states: Vec<String>,
+    colors: Vec<[f32; 3]>,
+    kinds: Vec<OtherKind>,
+    ids: HashMap<String, u16>,
+}
+
+const DEFAULT_SLIPPERINESS: f32 = 0.6;
+
+/// How a block we don't model looks and behaves, worked out from its
+/// state once, when it is interned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct OtherKind {
+    /// The box the block is drawn as, in sixteenths of a block, or None
+    /// for a full cube.
+    bounds: Option<([u8; 3], [u8; 3])>,
+    layer: RenderLayer,
+    solid: bool,
+    alpha: f32,
+    slipperiness: f32,
+}
+
+fn other_kind(id: u16) -> OtherKind {
+    OTHER_STATES.read().unwrap().kinds[id as usize]
+}
+
+fn intern_state(state: &str) -> u16 {
+    if let Some(id) = OTHER_STATES.read().unwrap().ids.get(state) {
+        return *id;
+    }
+
+    let mut other_states = OTHER_STATES.write().unwrap();
+    if let Some(id) = other_states.ids.get(state) {
+        return *id;
+    }
+    // Running out of ids would take tens of thousands of distinct states,
+    // past that they all share the last one.
+    if other_states.states.len() > u16::MAX as usize {
+        return u16::MAX;
+    }
+
+    let id = other_states.states.len() as u16;
+    let (name, properties) = parse_state(state);
+    other_states.states.push(state.to_owned());
+    other_states.colors.push(name_color(&name));
+    other_states.kinds.push(classify(&name, &properties));
+    other_states.ids.insert(state.to_owned(), id);
+    id
+}
+
+// Plants and the like, which are drawn as a small box that can be walked
+// through.
+const PLANTS: &[&str] = &[
+    "grass",
+    "fern",
+    "dead_bush",
+    "tall_grass",
+    "large_fern",
+    "dandelion",
+    "poppy",
+    "blue_orchid",
+    "allium",
+    "azure_bluet",
+    "oxeye_daisy",
+    "cornflower",
+    "lily_of_the_valley",
+    "wither_rose",
+    "sunflower",
+    "lilac",
+    "rose_bush",
+    "peony",
+    "sugar_cane",
+    "wheat",
+    "carrots",
+    "potatoes",
+    "beetroots",
+    "melon_stem",
+    "pumpkin_stem",
+    "attached_melon_stem",
+    "attached_pumpkin_stem",
+    "nether_wart",
+    "sweet_berry_bush",
+    "seagrass",
+    "tall_seagrass",
+    "kelp",
+    "kelp_plant",
+    "vine",
+    "cobweb",
+    "nether_sprouts",
+    "fire",
+    "soul_fire",
+];
+
+// Blocks that are nearly, but not quite, full cubes.
+const BOXY_BLOCKS: &[&str] = &[
+    "cactus",
+    "hopper",
+    "cauldron",
+    "brewing_stand",
+    "enchanting_table",
+    "lectern",
+    "cake",
+    "campfire",
+    "soul_campfire",
+    "bell",
+    "scaffolding",
+    "farmland",
+    "grass_path",
+    "stonecutter",
+    "composter",
+    "end_portal_frame",
+];
+
+/// Sorts a block we don't model by its name and properties. The shapes
+/// are single boxes, so stairs, fences and the like are only roughly
+/// their real size, but they no longer hide the faces next to them.
+fn classify(name: &str, properties: &[(String, String)]) -> OtherKind {
+    let property = |key: &str| {
+        properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+    let name = name.trim_start_matches("minecraft:");
+    let is_plant = name.ends_with("_sapling")
+        || name.ends_with("_tulip")
+        || name.ends_with("_mushroom")
+        || name.ends_with("_coral")
+        || name.ends_with("_coral_fan")
+        || name.ends_with("_fungus")
+        || name.ends_with("_roots")
+        || name.ends_with("_vines")
+        || name.ends_with("_vines_plant")
+        || PLANTS.contains(&name);
+
+    let mut kind = OtherKind {
+        bounds: None,
+        layer: RenderLayer::Opaque,
+        solid: true,
+        alpha: 1.0,
+        slipperiness: DEFAULT_SLIPPERINESS,
+    };
+    let flat = |height: u8| Some(([0, 0, 0], [16, height, 16]));
+
+    if is_plant {
+        kind.bounds = Some(([2, 0, 2], [14, 13, 14]));
+        kind.layer = RenderLayer::Cutout;
+        kind.solid = false;
+    } else if name.ends_with("_slab") {
+        kind.bounds = match property("type") {
+            Some("double") => None,
+            Some("top") => Some(([0, 8, 0], [16, 16, 16])),
+            _ => flat(8),
+        };
+    } else if name.ends_with("torch") {
+        kind.bounds = Some(([7, 0, 7], [9, 10, 9]));
+        kind.layer = RenderLayer::Cutout;
+        kind.solid = false;
+    } else if name.ends_with("_pane") || name == "iron_bars" || name == "chain" {
+        kind.bounds = Some(([7, 0, 7], [9, 16, 9]));
+        kind.layer = RenderLayer::Cutout;
+    } else if name.ends_with("_fence") || name.ends_with("_fence_gate") || name == "end_rod" {
+        kind.bounds = Some(([6, 0, 6], [10, 16, 10]));
+    } else if name.ends_with("_wall") {
+        kind.bounds = Some(([4, 0, 4], [12, 16, 12]));
+    } else if name.ends_with("rail") {
+        kind.bounds = flat(1);
+        kind.layer = RenderLayer::Cutout;
+        kind.solid = false;
+    } else if name.ends_with("_carpet") || name == "lily_pad" {
+        kind.bounds = flat(1);
+    } else if name.ends_with("_pressure_plate") || name == "tripwire" {
+        kind.bounds = Some(([1, 0, 1], [15, 1, 15]));
+        kind.layer = RenderLayer::Cutout;
+        kind.solid = false;
+    } else if name == "repeater" || name == "comparator" {
+        kind.bounds = flat(2);
+        kind.layer = RenderLayer::Cutout;
+    } else if name == "daylight_detector" {
+        kind.bounds = flat(6);
+    } else if name == "snow" {
+        let layers = property("layers").and_then(|layers| layers.parse().ok());
+        kind.bounds = flat(layers.unwrap_or(1u8).clamp(1, 8) * 2);
+    } else if name.ends_with("_button") {
+        kind.bounds = Some(([5, 0, 6], [11, 2, 10]));
+        kind.solid = false;
+    } else if name == "lever" || name == "tripwire_hook" {
+        kind.bounds = Some(([5, 0, 4], [11, 6, 12]));
+        kind.layer = RenderLayer::Cutout;
+        kind.solid = false;
+    } else if name.ends_with("_sign") || name.ends_with("_banner") {
+        kind.bounds = Some(([4, 0, 7], [12, 12, 9]));
+        kind.solid = false;
+    } else if name.ends_with("_door") || name == "ladder" {
+        kind.bounds = Some(([0, 0, 13], [16, 16, 16]));
+        kind.layer = RenderLayer::Cutout;
+    } else if name.ends_with("_trapdoor") {
+        kind.bounds = flat(3);
+        kind.layer = RenderLayer::Cutout;
+    } else if name.ends_with("_bed") {
+        kind.bounds = flat(9);
+    } else if (name.ends_with("_head") && name != "piston_head")
+        || name.ends_with("_skull")
+        || name == "flower_pot"
+    {
+        kind.bounds = Some(([4, 0, 4], [12, 8, 12]));
+    } else if name == "lantern" || name == "soul_lantern" {
+        kind.bounds = Some(([5, 0, 5], [11, 9, 11]));
+        kind.layer = RenderLayer::Cutout;
+    } else if name.ends_with("stairs")
+        || name.ends_with("chest")
+        || name.ends_with("anvil")
+        || BOXY_BLOCKS.contains(&name)
+    {
+        // Roughly a full block, but not enough to hide a neighbour.
+        kind.bounds = Some(([1, 0, 1], [15, 15, 15]));
+    }
+
+    if name.ends_with("_leaves") || name == "spawner" {
+        kind.layer = RenderLayer::Cutout;
+    }
+    if name.ends_with("stained_glass") || name.ends_with("stained_glass_pane") {
+        kind.layer = RenderLayer::Translucent;
+        kind.alpha = 0.5;
+    }
+    match name {
+        "ice" | "frosted_ice" => {
+            kind.layer = RenderLayer::Translucent;
+            kind.alpha = 0.7;
+            kind.slipperiness = 0.98;
+        }
+        "packed_ice" => kind.slipperiness = 0.98,
+        "blue_ice" => kind.slipperiness = 0.989,
+        "slime_block" | "honey_block" => {
+            kind.layer = RenderLayer::Translucent;
+            kind.alpha = 0.7;
+            if name == "slime_block" {
+                kind.slipperiness = 0.8;
+            }
+        }
+        "nether_portal" => {
+            kind.layer = RenderLayer::Translucent;
+            kind.alpha = 0.6;
+            kind.solid = false;
+        }
+        "lava" | "bubble_column" | "structure_void" | "end_portal" | "end_gateway" => {
+            kind.solid = false;
+        }
+        _ => {}
+    }
+    kind
+}
+
+/// Gives unknown blocks a stable but distinct colour based on their name,
+/// so that neighbouring blocks of different types can be told apart.
+fn name_color(name: &str) -> [f32; 3] {
+    // FNV-1a, which is stable between runs unlike the std hasher.
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in name.bytes() {
+        hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+    }
+    [
+        0.3 + ((hash >> 8) & 0xff) as f32 / 512.0,
+        0.3 + ((hash >> 16) & 0xff) as f32 / 512.0,
+        0.3 + ((hash >> 24) & 0xff) as f32 / 512.0,
+    ]
+}
+
+/// Splits a block state into its namespaced name and its properties,
+/// sorted by key.
+fn parse_state(state: &str) -> (String, Vec<(String, String)>) {
+    let state = state.trim();
+    let (name, properties) = match state.find('[') {
+        Some(start) => (&state[..start], state[start + 1..].trim_end_matches(']')),
+        None => (state, ""),
+    };
+    let name = if name.contains(':') {
+        name.to_owned()
+    } else {
+        format!("minecraft:{}", name)
+    };
+
+    let mut properties: Vec<(String, String)> = properties
+        .split(',')
+        .filter_map(|property| {
+            let mut parts = property.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            Some((key.to_owned(), value.to_owned()))
+        })
+        .collect();
+    properties.sort();
+    (name, properties)
+}
+
+fn format_state(name: &str, properties: &[(String, String)]) -> String {
+    if properties.is_empty() {
+        return name.to_owned();
+    }
+    let properties: Vec<String> = properties
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    format!("{}[{}]", name, properties.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_full_blocks_are_opaque_cubes() {
+        let dirt = Block::from_state("minecraft:dirt");
+        assert!(dirt.is_full_cube() && dirt.is_opaque() && dirt.is_solid());
+        assert_eq!(dirt.render_layer(), RenderLayer::Opaque);
+        assert_eq!(dirt.partial_bounds(), None);
+    }
+
+    #[test]
+    fn see_through_blocks_are_not_opaque() {
+        for state in &["red_stained_glass", "ice", "slime_block"] {
+            let block = Block::from_state(state);
+            assert!(block.is_full_cube(), "{}", state);
+            assert!(!block.is_opaque(), "{}", state);
+            assert_eq!(block.render_layer(), RenderLayer::Translucent, "{}", state);
+        }
+
+        let leaves = Block::from_state("oak_leaves[distance=1,persistent=true]");
+        assert!(leaves.is_full_cube() && !leaves.is_opaque());
+        assert_eq!(leaves.render_layer(), RenderLayer::Cutout);
+    }
+
+    #[test]
+    fn partial_blocks_are_not_full_cubes() {
+        let states = [
+            "red_stained_glass_pane",
+            "repeater[delay=1,facing=north,locked=false,powered=false]",
+            "lever[face=floor,facing=north,powered=false]",
+            "stone_button[face=floor,facing=north,powered=false]",
+            "rail[shape=north_south]",
+            "poppy",
+        ];
+        for state in &states {
+            let block = Block::from_state(state);
+            assert!(!block.is_full_cube(), "{}", state);
+            assert!(!block.is_opaque(), "{}", state);
+            assert!(block.partial_bounds().is_some(), "{}", state);
+        }
+        assert!(!Block::from_state("poppy").is_solid());
+    }
+
+    #[test]
+    fn slabs_depend_on_their_type() {
+        let bottom = Block::from_state("stone_slab[type=bottom,waterlogged=false]");
+        let top = Block::from_state("stone_slab[type=top,waterlogged=false]");
+        let double = Block::from_state("stone_slab[type=double,waterlogged=false]");
+        assert_eq!(
+            bottom.partial_bounds(),
+            Some(([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]))
+        );
+        assert_eq!(
+            top.partial_bounds(),
+            Some(([0.0, 0.5, 0.0], [1.0, 1.0, 1.0]))
+        );
+        assert!(double.is_full_cube() && double.is_opaque());
+    }
+
+    #[test]
+    fn ice_is_slippery() {
+        assert_eq!(Block::from_state("dirt").slipperiness(), 0.6);
+        assert_eq!(Block::from_state("packed_ice").slipperiness(), 0.98);
+        assert_eq!(Block::from_state("blue_ice").slipperiness(), 0.989);
+        assert_eq!(Block::from_state("slime_block").slipperiness(), 0.8);
+    }
+}
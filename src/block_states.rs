@@ -0,0 +1,110 @@
+use crate::block::Block;
+use crate::window::unset_setting;
+use config::Config;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Far more than any version has, so that a malformed report can't make us
+// allocate without end.
+const MAX_STATES: u64 = 1 << 20;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The block each global block state id stands for, which is how chunk and
+/// block change packets name blocks. The ids change with every version, so
+/// rather than keeping a copy for 1.16.5 they are read from the
+/// `blocks.json` report the vanilla server generates with
+/// `java -cp server.jar net.minecraft.data.Main --reports`.
+pub struct BlockStates {
+    blocks: Vec<Block>,
+}
+
+impl BlockStates {
+    pub fn load(path: &Path) -> io::Result<BlockStates> {
+        let report: Value = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|error| invalid_data(&error.to_string()))?;
+        let report = report
+            .as_object()
+            .ok_or_else(|| invalid_data("block report is not an object"))?;
+
+        let mut blocks = Vec::new();
+        for (name, block) in report {
+            let states = block
+                .get("states")
+                .and_then(Value::as_array)
+                .ok_or_else(|| invalid_data(&format!("{} has no states", name)))?;
+            for state in states {
+                let id = state
+                    .get("id")
+                    .and_then(Value::as_u64)
+                    .filter(|id| *id < MAX_STATES)
+                    .ok_or_else(|| invalid_data(&format!("{} has a state without an id", name)))?
+                    as usize;
+                let properties: Vec<String> = state
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .map(|properties| {
+                        properties
+                            .iter()
+                            .filter_map(|(key, value)| Some(format!("{}={}", key, value.as_str()?)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                if blocks.len() <= id {
+                    blocks.resize(id + 1, Block::Air);
+                }
+                blocks[id] = if properties.is_empty() {
+                    Block::from_state(name)
+                } else {
+                    Block::from_state(&format!("{}[{}]", name, properties.join(",")))
+                };
+            }
+        }
+        Ok(BlockStates { blocks })
+    }
+
+    /// The block for a state id. Ids the report doesn't have are air.
+    pub fn get(&self, id: i32) -> Block {
+        usize::try_from(id)
+            .ok()
+            .and_then(|id| self.blocks.get(id))
+            .copied()
+            .unwrap_or(Block::Air)
+    }
+}
+
+const REPORT_HELP: &str = "Replays can't be played without the block state report. \
+Generate it with `java -cp server.jar net.minecraft.data.Main --reports` using the \
+1.16.5 server jar, and point the block_states setting at generated/reports/blocks.json";
+
+/// Loads the block states from the report named by the `block_states`
+/// setting. Without them the chunks sent by servers and kept in replays
+/// can't be read, but everything else still works, so a missing report is
+/// only reported.
+pub fn load_block_states(config: &Config) -> Option<BlockStates> {
+    let path = config
+        .get_str("block_states")
+        .expect(unset_setting("block_states").as_ref());
+    match BlockStates::load(Path::new(&path)) {
+        Ok(states) => Some(states),
+        Err(error) => {
+            eprintln!(
+                "Unable to load block states from {}: {}. Chunks from servers and replays won't be shown.",
+                path, error
+            );
+            None
+        }
+    }
+}
+
+/// Loads the block states for playing back a replay, which is nothing but
+/// chunk packets without them.
+pub fn require_block_states(config: &Config) -> BlockStates {
+    load_block_states(config).expect(REPORT_HELP)
+}
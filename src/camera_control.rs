@@ -0,0 +1,174 @@
+use crate::world::{BlockPos, World};
+use nalgebra::{Rotation3, Vector3};
+
+// How far behind or in front of the player the third person camera sits,
+// pulled in when a block is in the way.
+const ORBIT_DISTANCE: f32 = 4.0;
+const ORBIT_STEP: f32 = 0.05;
+const ORBIT_MARGIN: f32 = 0.2;
+
+const MIN_SPECTATOR_SPEED: f32 = 1.0;
+const MAX_SPECTATOR_SPEED: f32 = 200.0;
+// Each notch of the scroll wheel scales the spectator speed by this much.
+const SPECTATOR_SPEED_STEP: f32 = 1.25;
+// How quickly the spectator camera comes to a stop, as the fraction of its
+// velocity kept after a second.
+const SPECTATOR_DRAG: f32 = 0.002;
+
+/// Where the view is drawn from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    FirstPerson,
+    /// Behind the player, looking the same way.
+    ThirdPersonBack,
+    /// In front of the player, looking back at them.
+    ThirdPersonFront,
+    /// Flying freely through blocks, leaving the player where they were.
+    Spectator,
+}
+
+/// Places the camera for each mode. In spectator mode the camera has a
+/// position of its own, driven by the movement keys, while the player
+/// stands still and keeps being sent to the server as usual.
+pub struct CameraControl {
+    pub mode: CameraMode,
+    position: Vector3<f32>,
+    velocity: Vector3<f32>,
+    /// Blocks per second at full input.
+    speed: f32,
+    // The player's pitch and yaw from before spectating, given back to the
+    // player when spectating stops.
+    player_heading: (f32, f32),
+}
+
+impl CameraControl {
+    pub fn new(speed: f32) -> CameraControl {
+        CameraControl {
+            mode: CameraMode::FirstPerson,
+            position: Vector3::new(0.0, 0.0, 0.0),
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            speed: speed.clamp(MIN_SPECTATOR_SPEED, MAX_SPECTATOR_SPEED),
+            player_heading: (0.0, 0.0),
+        }
+    }
+
+    pub fn is_spectating(&self) -> bool {
+        self.mode == CameraMode::Spectator
+    }
+
+    /// Steps through first person and the two third person views, like
+    /// F5 in vanilla. Spectating stops first if it was on.
+    pub fn cycle_perspective(&mut self) -> Option<(f32, f32)> {
+        let (mode, heading) = match self.mode {
+            CameraMode::FirstPerson => (CameraMode::ThirdPersonBack, None),
+            CameraMode::ThirdPersonBack => (CameraMode::ThirdPersonFront, None),
+            CameraMode::ThirdPersonFront => (CameraMode::FirstPerson, None),
+            CameraMode::Spectator => (CameraMode::FirstPerson, Some(self.player_heading)),
+        };
+        self.mode = mode;
+        heading
+    }
+
+    /// Starts spectating from `eye`, remembering the player's heading, or
+    /// stops and returns the heading to give back to the player.
+    pub fn toggle_spectator(
+        &mut self,
+        eye: &Vector3<f32>,
+        heading: (f32, f32),
+    ) -> Option<(f32, f32)> {
+        if self.is_spectating() {
+            self.mode = CameraMode::FirstPerson;
+            return Some(self.player_heading);
+        }
+        self.mode = CameraMode::Spectator;
+        self.position = eye.clone_owned();
+        self.velocity = Vector3::new(0.0, 0.0, 0.0);
+        self.player_heading = heading;
+        None
+    }
+
+    /// The heading the player should keep while the mouse is turning the
+    /// camera instead.
+    pub fn player_heading(&self) -> (f32, f32) {
+        self.player_heading
+    }
+
+    /// Scales the spectator speed by a number of scroll wheel notches.
+    pub fn adjust_speed(&mut self, notches: f32) {
+        self.speed = (self.speed * SPECTATOR_SPEED_STEP.powf(notches))
+            .clamp(MIN_SPECTATOR_SPEED, MAX_SPECTATOR_SPEED);
+    }
+
+    /// Flies the spectator camera for `delta` seconds. The control vector
+    /// is the same as the player's, with horizontal movement turned to
+    /// face the camera's yaw and vertical movement straight up or down.
+    pub fn update_spectator(&mut self, delta: f32, control: &Vector3<f32>, yaw: f32, sprint: bool) {
+        let mut input = Vector3::new(control[0], 0.0, control[2]);
+        if input.magnitude() > 1.0 {
+            input = input.normalize();
+        }
+        let mut input = Rotation3::from_axis_angle(&Vector3::y_axis(), yaw.to_radians()) * input;
+        input[1] = control[1];
+
+        let speed = if sprint { self.speed * 2.0 } else { self.speed };
+        // Eases towards the target velocity rather than jumping to it.
+        let blend = 1.0 - SPECTATOR_DRAG.powf(delta);
+        self.velocity += (input * speed - self.velocity) * blend;
+        self.position += self.velocity * delta;
+    }
+
+    /// The camera's position and rotation (pitch, yaw and roll in degrees)
+    /// for the current mode, given the player's eye and heading.
+    pub fn place(
+        &self,
+        world: &World,
+        eye: &Vector3<f32>,
+        heading: &Vector3<f32>,
+    ) -> (Vector3<f32>, Vector3<f32>) {
+        let forward = look_vector(heading[0], heading[1]);
+        match self.mode {
+            CameraMode::FirstPerson => (eye.clone_owned(), heading.clone_owned()),
+            CameraMode::ThirdPersonBack => {
+                let distance = orbit_distance(world, eye, &-forward);
+                (eye - forward * distance, heading.clone_owned())
+            }
+            CameraMode::ThirdPersonFront => {
+                let distance = orbit_distance(world, eye, &forward);
+                let rotation = Vector3::new(-heading[0], heading[1] + 180.0, heading[2]);
+                (eye + forward * distance, rotation)
+            }
+            CameraMode::Spectator => (self.position, heading.clone_owned()),
+        }
+    }
+}
+
+/// The unit vector a pitch and yaw look along, matching the camera's
+/// model matrix.
+pub fn look_vector(pitch: f32, yaw: f32) -> Vector3<f32> {
+    let (pitch, yaw) = (pitch.to_radians(), yaw.to_radians());
+    Vector3::new(
+        -yaw.sin() * pitch.cos(),
+        pitch.sin(),
+        -yaw.cos() * pitch.cos(),
+    )
+}
+
+/// How far the third person camera can get from the eye along
+/// `direction` before hitting a solid block, up to the usual distance.
+fn orbit_distance(world: &World, eye: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+    let mut distance = 0.0;
+    while distance < ORBIT_DISTANCE {
+        let next = distance + ORBIT_STEP;
+        let point = eye + direction * next;
+        let pos = BlockPos::new(
+            point.x.floor() as i32,
+            point.y.floor() as i32,
+            point.z.floor() as i32,
+        );
+        if world.get_block(pos).is_solid() {
+            return (distance - ORBIT_MARGIN).max(0.0);
+        }
+        distance = next;
+    }
+    ORBIT_DISTANCE
+}
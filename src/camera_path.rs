@@ -0,0 +1,379 @@
+use crate::camera_control::look_vector;
+use crate::render_lines::LineRenderer;
+use crate::render_text::{Anchor, TextRenderer};
+use nalgebra::{UnitQuaternion, Vector3, Vector4};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const PATH_COLOR: [f32; 4] = [1.0, 0.85, 0.2, 1.0];
+const KEYFRAME_COLOR: [f32; 4] = [1.0, 0.4, 0.8, 1.0];
+const HEADING_COLOR: [f32; 4] = [0.4, 1.0, 1.0, 1.0];
+
+// How many points per second of the path its preview is drawn through.
+const PREVIEW_RESOLUTION: f32 = 20.0;
+const KEYFRAME_SIZE: f32 = 0.15;
+const HEADING_LENGTH: f32 = 1.0;
+
+/// Where the camera is and how it is pointed at one point on a path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    pub position: Vector3<f32>,
+    /// Pitch, yaw and roll in degrees, as the camera's spatial takes them.
+    pub rotation: Vector3<f32>,
+    /// The vertical field of view in degrees.
+    pub fov: f32,
+}
+
+/// A flythrough for showing off builds, made of keyframes placed one
+/// after another. Positions and fields of view follow a Catmull-Rom spline
+/// through the keyframes, and rotations are slerped between them.
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    // Seconds into the path, while it is playing.
+    playhead: Option<f32>,
+}
+
+impl CameraPath {
+    pub fn new() -> CameraPath {
+        CameraPath {
+            keyframes: Vec::new(),
+            playhead: None,
+        }
+    }
+
+    /// Reads a path saved by `save`.
+    pub fn load(path: &Path) -> io::Result<CameraPath> {
+        let mut keyframes: Vec<Keyframe> = Vec::new();
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse())
+                .collect::<Result<Vec<f32>, _>>()
+                .ok()
+                .filter(|values| values.len() == 8)
+                .ok_or_else(|| invalid_data(&format!("invalid keyframe '{}'", line)))?;
+            let keyframe = Keyframe {
+                time: values[0],
+                position: Vector3::new(values[1], values[2], values[3]),
+                rotation: Vector3::new(values[4], values[5], values[6]),
+                fov: values[7],
+            };
+            if let Some(last) = keyframes.last() {
+                if keyframe.time <= last.time {
+                    return Err(invalid_data("keyframes are out of order"));
+                }
+            }
+            keyframes.push(keyframe);
+        }
+        Ok(CameraPath {
+            keyframes,
+            playhead: None,
+        })
+    }
+
+    /// Writes the keyframes out as text, one to a line, so that paths can
+    /// be touched up by hand.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# time x y z pitch yaw roll fov")?;
+        for keyframe in self.keyframes.iter() {
+            writeln!(
+                out,
+                "{} {} {} {} {} {} {} {}",
+                keyframe.time,
+                keyframe.position.x,
+                keyframe.position.y,
+                keyframe.position.z,
+                keyframe.rotation[0],
+                keyframe.rotation[1],
+                keyframe.rotation[2],
+                keyframe.fov
+            )?;
+        }
+        out.flush()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// How long the path takes to play, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Adds a keyframe to the end of the path, `spacing` seconds after the
+    /// one before it.
+    pub fn add_keyframe(
+        &mut self,
+        position: &Vector3<f32>,
+        rotation: &Vector3<f32>,
+        fov: f32,
+        spacing: f32,
+    ) {
+        let time = match self.keyframes.last() {
+            Some(last) => last.time + spacing,
+            None => 0.0,
+        };
+        self.keyframes.push(Keyframe {
+            time,
+            position: position.clone_owned(),
+            rotation: rotation.clone_owned(),
+            fov,
+        });
+    }
+
+    pub fn remove_last_keyframe(&mut self) -> Option<Keyframe> {
+        let keyframe = self.keyframes.pop();
+        if self.keyframes.len() < 2 {
+            self.playhead = None;
+        }
+        keyframe
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playhead.is_some()
+    }
+
+    /// Plays the path from the start, or stops it if it was playing.
+    /// Returns whether it is playing now, which it can't without at least
+    /// two keyframes.
+    pub fn toggle_playback(&mut self) -> bool {
+        self.playhead = match self.playhead {
+            None if self.keyframes.len() >= 2 => Some(0.0),
+            _ => None,
+        };
+        self.is_playing()
+    }
+
+    /// Moves playback on by `delta` seconds, returning where the camera
+    /// should be. Playback stops once the end of the path is reached, after
+    /// handing back the last keyframe.
+    pub fn advance(&mut self, delta: f32) -> Option<Keyframe> {
+        let time = self.playhead?;
+        let duration = self.duration();
+        self.playhead = Some(time + delta).filter(|_| time < duration);
+        Some(self.sample(time.min(duration)))
+    }
+
+    /// Where the camera is `time` seconds into the path.
+    pub fn sample(&self, time: f32) -> Keyframe {
+        let last = self.keyframes.len() - 1;
+        let i = self
+            .keyframes
+            .iter()
+            .rposition(|keyframe| keyframe.time <= time)
+            .unwrap_or(0)
+            .min(last.saturating_sub(1));
+        let (from, to) = (&self.keyframes[i], &self.keyframes[(i + 1).min(last)]);
+        let span = to.time - from.time;
+        if span <= 0.0 {
+            return Keyframe { time, ..*from };
+        }
+        let s = ((time - from.time) / span).clamp(0.0, 1.0);
+
+        // A cubic Hermite curve between the two keyframes, with tangents
+        // taken from their neighbours.
+        let (s2, s3) = (s * s, s * s * s);
+        let point = control_point(from) * (2.0 * s3 - 3.0 * s2 + 1.0)
+            + self.tangent(i) * span * (s3 - 2.0 * s2 + s)
+            + control_point(to) * (-2.0 * s3 + 3.0 * s2)
+            + self.tangent(i + 1) * span * (s3 - s2);
+
+        let (start, end) = (to_quaternion(&from.rotation), to_quaternion(&to.rotation));
+        // Half a turn apart, there is no one way round to pick.
+        let rotation = match start.try_slerp(&end, s, 1.0e-6) {
+            Some(rotation) => rotation,
+            None if s < 0.5 => start,
+            None => end,
+        };
+
+        Keyframe {
+            time,
+            position: point.xyz(),
+            rotation: from_quaternion(&rotation),
+            fov: point[3],
+        }
+    }
+
+    /// How fast the spline moves through keyframe `i`, per second. The
+    /// ends of the path move straight towards their one neighbour.
+    fn tangent(&self, i: usize) -> Vector4<f32> {
+        let before = &self.keyframes[i.saturating_sub(1)];
+        let after = &self.keyframes[(i + 1).min(self.keyframes.len() - 1)];
+        let span = after.time - before.time;
+        if span <= 0.0 {
+            return Vector4::zeros();
+        }
+        (control_point(after) - control_point(before)) / span
+    }
+
+    /// Queues the path the camera will take, with a marker at each keyframe
+    /// and a line showing which way it looks there. Nothing is drawn while
+    /// the path plays, so that it doesn't end up in the shot.
+    pub fn queue_preview(&self, lines: &mut LineRenderer) {
+        if self.is_playing() || self.keyframes.is_empty() {
+            return;
+        }
+        let steps = (self.duration() * PREVIEW_RESOLUTION).ceil() as usize;
+        let mut previous = self.keyframes[0].position;
+        for step in 1..=steps {
+            let time = self.duration() * step as f32 / steps as f32;
+            let position = self.sample(time).position;
+            lines.queue_line(previous.into(), position.into(), PATH_COLOR);
+            previous = position;
+        }
+        for keyframe in self.keyframes.iter() {
+            let grow = Vector3::new(KEYFRAME_SIZE, KEYFRAME_SIZE, KEYFRAME_SIZE);
+            let position = keyframe.position;
+            lines.queue_box(
+                (position - grow).into(),
+                (position + grow).into(),
+                KEYFRAME_COLOR,
+            );
+            let heading = look_vector(keyframe.rotation[0], keyframe.rotation[1]);
+            lines.queue_line(
+                position.into(),
+                (position + heading * HEADING_LENGTH).into(),
+                HEADING_COLOR,
+            );
+        }
+    }
+
+    /// Queues how far through the path playback is on the left of the
+    /// screen, or how many keyframes there are while editing.
+    pub fn queue_info(&self, text: &mut TextRenderer, screen_height: u32) {
+        let line = match self.playhead {
+            Some(time) => format!(
+                "Camera path {:.1} / {:.1}s",
+                time.min(self.duration()),
+                self.duration()
+            ),
+            None if self.keyframes.is_empty() => return,
+            None => format!(
+                "Camera path: {} keyframes, {:.1}s",
+                self.keyframes.len(),
+                self.duration()
+            ),
+        };
+        text.queue_panel(
+            &[(line, [1.0, 1.0, 1.0, 1.0])],
+            Anchor::Left { screen_height },
+        );
+    }
+}
+
+/// The parts of a keyframe that follow the spline, its position and field
+/// of view.
+fn control_point(keyframe: &Keyframe) -> Vector4<f32> {
+    let position = keyframe.position;
+    Vector4::new(position.x, position.y, position.z, keyframe.fov)
+}
+
+/// The rotation the camera's spatial builds from pitch, yaw and roll,
+/// turning about the y axis, then x, then z.
+fn to_quaternion(rotation: &Vector3<f32>) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::y_axis(), rotation[1].to_radians())
+        * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), rotation[0].to_radians())
+        * UnitQuaternion::from_axis_angle(&Vector3::z_axis(), rotation[2].to_radians())
+}
+
+/// Takes a rotation back apart into pitch, yaw and roll in degrees.
+fn from_quaternion(rotation: &UnitQuaternion<f32>) -> Vector3<f32> {
+    let matrix = rotation.to_rotation_matrix();
+    let m = matrix.matrix();
+    let pitch = (-m[(1, 2)]).clamp(-1.0, 1.0).asin();
+    let yaw = m[(0, 2)].atan2(m[(2, 2)]);
+    let roll = m[(1, 0)].atan2(m[(1, 1)]);
+    Vector3::new(pitch.to_degrees(), yaw.to_degrees(), roll.to_degrees())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, x: f32, pitch: f32, yaw: f32, roll: f32, fov: f32) -> Keyframe {
+        Keyframe {
+            time,
+            position: Vector3::new(x, 64.0, -x),
+            rotation: Vector3::new(pitch, yaw, roll),
+            fov,
+        }
+    }
+
+    fn path() -> CameraPath {
+        CameraPath {
+            keyframes: vec![
+                keyframe(0.0, 0.0, 0.0, 0.0, 0.0, 70.0),
+                keyframe(2.0, 10.0, 30.0, 90.0, 0.0, 50.0),
+                keyframe(3.5, 4.0, -20.0, 45.0, 10.0, 90.0),
+            ],
+            playhead: None,
+        }
+    }
+
+    fn assert_close(a: &Vector3<f32>, b: &Vector3<f32>) {
+        assert!((a - b).norm() < 1.0e-3, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn sampling_at_a_keyframe_gives_that_keyframe() {
+        let path = path();
+        for expected in path.keyframes.iter() {
+            let sampled = path.sample(expected.time);
+            assert_eq!(sampled.time, expected.time);
+            assert_close(&sampled.position, &expected.position);
+            assert_close(&sampled.rotation, &expected.rotation);
+            assert!((sampled.fov - expected.fov).abs() < 1.0e-3);
+        }
+    }
+
+    #[test]
+    fn rotations_survive_a_quaternion_round_trip() {
+        for rotation in [
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(30.0, 90.0, 0.0),
+            Vector3::new(-45.0, -135.0, 20.0),
+            Vector3::new(80.0, 170.0, -60.0),
+        ]
+        .iter()
+        {
+            assert_close(&from_quaternion(&to_quaternion(rotation)), rotation);
+        }
+    }
+
+    #[test]
+    fn saved_paths_load_back_the_same() {
+        let file = std::env::temp_dir().join(format!("camera-path-{}.txt", std::process::id()));
+        let path = path();
+        path.save(&file).unwrap();
+        let loaded = CameraPath::load(&file);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(loaded.unwrap().keyframes, path.keyframes);
+    }
+
+    #[test]
+    fn loading_rejects_keyframes_out_of_order() {
+        let file =
+            std::env::temp_dir().join(format!("camera-path-order-{}.txt", std::process::id()));
+        fs::write(&file, "1 0 0 0 0 0 0 70\n0.5 0 0 0 0 0 0 70\n").unwrap();
+        let loaded = CameraPath::load(&file);
+        fs::remove_file(&file).unwrap();
+        assert!(loaded.is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::render::Renderer;
+use crate::window::{invalid_setting, unset_setting};
+use crate::world::{Chunk, ChunkPos, World, SECTION_SIZE};
+use config::Config;
+use nalgebra::Vector3;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+/// Somewhere chunks can be loaded from on request, such as a save on disk.
+pub trait ChunkSource {
+    /// Loads a chunk, returning None if the source has no chunk at that
+    /// position.
+    fn load_chunk(&mut self, pos: ChunkPos) -> Option<Chunk>;
+}
+
+/// Decides which chunks should be kept around the player. Chunks that fall
+/// outside of the render distance have both their world data and their GPU
+/// meshes freed. Chunks that can't be loaded back as they are, like those a
+/// server or a replay pushed to us or those edited since loading, only have
+/// their meshes freed and are meshed again once they come back into range.
+pub struct ChunkManager {
+    render_distance: i32,
+    load_budget: usize,
+    center: Option<ChunkPos>,
+    source: Option<Box<dyn ChunkSource>>,
+    // Chunks within range that have yet to be requested from the source,
+    // sorted furthest first.
+    load_queue: Vec<ChunkPos>,
+    // Chunks already requested from the source, whether or not it had them.
+    requested: HashSet<ChunkPos>,
+    // Chunks out of range that are kept without meshes, as nothing could
+    // load them again as they are.
+    hidden: HashSet<ChunkPos>,
+}
+
+impl ChunkManager {
+    pub fn new(config: &Config) -> ChunkManager {
+        let render_distance = config
+            .get_int("render_distance")
+            .expect(unset_setting("render_distance").as_ref());
+        let render_distance =
+            i32::try_from(render_distance).expect(invalid_setting("render_distance").as_ref());
+        let load_budget = config
+            .get_int("chunk_load_budget")
+            .expect(unset_setting("chunk_load_budget").as_ref());
+        let load_budget =
+            usize::try_from(load_budget).expect(invalid_setting("chunk_load_budget").as_ref());
+
+        ChunkManager {
+            render_distance,
+            load_budget,
+            center: None,
+            source: None,
+            load_queue: Vec::new(),
+            requested: HashSet::new(),
+            hidden: HashSet::new(),
+        }
+    }
+
+    /// Sets where chunks get loaded from. Without a source the manager only
+    /// hides chunks out of range, which is what we want when the server is
+    /// pushing chunks to us.
+    pub fn set_source(&mut self, source: Option<Box<dyn ChunkSource>>) {
+        self.source = source;
+        self.requested.clear();
+        self.hidden.clear();
+        self.center = None;
+    }
+
+    /// Forgets which chunks have been requested, so that the source is
+    /// asked for them again once the world has been cleared.
+    pub fn reload(&mut self) {
+        self.requested.clear();
+        self.hidden.clear();
+        self.center = None;
+    }
+
+    #[allow(dead_code)]
+    pub fn get_render_distance(&self) -> i32 {
+        self.render_distance
+    }
+
+    #[allow(dead_code)]
+    pub fn set_render_distance(&mut self, render_distance: i32) {
+        self.render_distance = render_distance.max(0);
+        self.center = None;
+    }
+
+    /// Whether a chunk is within the render distance, and so should be
+    /// meshed.
+    pub fn in_range(&self, pos: ChunkPos) -> bool {
+        match self.center {
+            Some(center) => chunk_in_range(center, self.render_distance, pos),
+            None => false,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        player_translation: &Vector3<f32>,
+        world: &mut World,
+        renderer: &mut Renderer,
+    ) {
+        let center = ChunkPos::new(
+            (player_translation[0] / SECTION_SIZE as f32).floor() as i32,
+            (player_translation[2] / SECTION_SIZE as f32).floor() as i32,
+        );
+
+        if self.center != Some(center) {
+            self.center = Some(center);
+            self.rebuild_load_queue(center);
+        }
+        // Chunks pushed to us from elsewhere can arrive out of range at any
+        // time, so this is checked every update rather than on moving.
+        self.unload_out_of_range(world, renderer);
+
+        let source = match &mut self.source {
+            Some(source) => source,
+            None => return,
+        };
+
+        for _ in 0..self.load_budget {
+            let pos = match self.load_queue.pop() {
+                Some(pos) => pos,
+                None => break,
+            };
+            self.requested.insert(pos);
+            // Edited chunks kept while out of range are still loaded.
+            if world.get_chunk(pos).is_some() {
+                continue;
+            }
+            if let Some(chunk) = source.load_chunk(pos) {
+                world.insert_chunk(pos, chunk);
+            }
+        }
+    }
+
+    fn unload_out_of_range(&mut self, world: &mut World, renderer: &mut Renderer) {
+        let out_of_range: Vec<ChunkPos> = world
+            .chunk_positions()
+            .filter(|pos| !self.in_range(*pos))
+            .collect();
+
+        for pos in out_of_range {
+            if self.source.is_some() && !world.is_chunk_edited(pos) {
+                world.remove_chunk(pos);
+                renderer.remove_chunk_meshes(pos);
+            } else if self.hidden.insert(pos) {
+                renderer.remove_chunk_meshes(pos);
+            }
+        }
+
+        // Hidden chunks coming back into range need meshes again, and those
+        // the server has since unloaded can be forgotten.
+        let shown: Vec<ChunkPos> = self
+            .hidden
+            .iter()
+            .copied()
+            .filter(|pos| self.in_range(*pos))
+            .collect();
+        for pos in shown {
+            self.hidden.remove(&pos);
+            world.mark_chunk_dirty(pos);
+        }
+        self.hidden.retain(|pos| world.get_chunk(*pos).is_some());
+
+        // Forget requests for chunks out of range so they are loaded again
+        // if the player comes back.
+        let render_distance = self.render_distance;
+        let center = self.center.unwrap();
+        self.requested
+            .retain(|pos| chunk_in_range(center, render_distance, *pos));
+    }
+
+    fn rebuild_load_queue(&mut self, center: ChunkPos) {
+        self.load_queue.clear();
+        if self.source.is_none() {
+            return;
+        }
+
+        for x in -self.render_distance..=self.render_distance {
+            for z in -self.render_distance..=self.render_distance {
+                let pos = ChunkPos::new(center.x + x, center.z + z);
+                if !self.requested.contains(&pos) {
+                    self.load_queue.push(pos);
+                }
+            }
+        }
+
+        self.load_queue.sort_by_key(|pos| {
+            let dx = pos.x - center.x;
+            let dz = pos.z - center.z;
+            -(dx * dx + dz * dz)
+        });
+    }
+
+    pub fn print_memory_report(&self, world: &World, renderer: &Renderer) {
+        let mebibyte = 1024.0 * 1024.0;
+        println!(
+            "Chunks: {} loaded, {} queued. World data: {:.2} MiB, meshes: {:.2} MiB",
+            world.chunk_count(),
+            self.load_queue.len(),
+            world.memory_usage() as f64 / mebibyte,
+            renderer.mesh_memory_usage() as f64 / mebibyte,
+        );
+    }
+}
+
+/// Vanilla uses a square render distance rather than a circular one.
+fn chunk_in_range(center: ChunkPos, render_distance: i32, pos: ChunkPos) -> bool {
+    (pos.x - center.x).abs() <= render_distance && (pos.z - center.z).abs() <= render_distance
+}
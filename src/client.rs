@@ -1,8 +1,51 @@
+use crate::anvil::{read_spawn, AnvilSource};
+use crate::arguments::{argument, parse_list};
+use crate::block_states::{load_block_states, require_block_states, BlockStates};
+use crate::camera_control::{look_vector, CameraControl, CameraMode};
+use crate::camera_path::CameraPath;
+use crate::chunk_manager::ChunkManager;
+use crate::entity::EntityRegistry;
+use crate::frame_recorder::FrameRecorder;
+use crate::ghost::Ghost;
+use crate::logic_analyzer::LogicAnalyzer;
+use crate::packet::{ClientboundPacket, RawPacket, ServerboundPacket};
 use crate::player::Player;
+use crate::player_list::PlayerList;
+use crate::redstone::{Network, RedstoneSimulation};
 use crate::render::Renderer;
-use crate::window::Window;
+use crate::render_lines::LineRenderer;
+use crate::replay::{
+    replay_player_packets, ReplayEvent, ReplayPlayer, ReplayRecorder, REPLAY_PLAYER_ID,
+};
+use crate::schematic::{Schematic, SchematicSource};
+use crate::screenshot::{save_screenshot, unused_path, Image};
+use crate::selection::{position_command, Selection};
+use crate::signal_overlay::queue_signal_overlay;
+use crate::view_filter::{SliceMode, ViewFilter};
+use crate::window::{invalid_setting, unset_setting, Window};
+use crate::world::{BlockPos, ChunkPos, SectionPos, World, SECTION_SIZE};
 use config::Config;
 use nalgebra::Vector3;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// How far away blocks can be used from, the same as in creative mode.
+const REACH: f32 = 5.0;
+// Circuits are often looked at from further away than they are used.
+const TRACE_REACH: f32 = 64.0;
+
+// The narrowest and widest the field of view can be set to, in degrees.
+const MIN_FOV: f32 = 10.0;
+const MAX_FOV: f32 = 110.0;
+
+const PLAYER_BOX_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+
+const NETWORK_WIRE_COLOR: [f32; 4] = [1.0, 0.85, 0.2, 1.0];
+const NETWORK_BLOCK_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 0.6];
+const NETWORK_INPUT_COLOR: [f32; 4] = [0.2, 1.0, 0.3, 1.0];
+const NETWORK_OUTPUT_COLOR: [f32; 4] = [0.3, 0.6, 1.0, 1.0];
 
 #[allow(dead_code)]
 pub struct Client {
@@ -10,6 +53,54 @@ pub struct Client {
     window: Window,
     renderer: Renderer,
     player: Player,
+    camera: CameraControl,
+    // The vertical field of view in degrees, when no camera path is
+    // playing.
+    fov: f32,
+    camera_path: CameraPath,
+    camera_path_directory: PathBuf,
+    // Seconds between keyframes added to the camera path.
+    keyframe_spacing: f32,
+    world: World,
+    // Needed to read chunks from the server, and missing if the report
+    // they come from couldn't be loaded.
+    block_states: Option<BlockStates>,
+    chunk_manager: ChunkManager,
+    entities: EntityRegistry,
+    player_list: PlayerList,
+    // Packets waiting to be sent to the server. They are only queued
+    // while connected to one, for the connection to send on.
+    outgoing: Vec<RawPacket>,
+    recorder: Option<ReplayRecorder>,
+    // The replay being played back instead of talking to a server.
+    replay: Option<ReplayPlayer>,
+    redstone: Option<RedstoneSimulation>,
+    // The redstone network picked out to be highlighted.
+    network: Option<Network>,
+    analyzer: LogicAnalyzer,
+    probe_directory: PathBuf,
+    selection: Selection,
+    // A schematic shown over the world as a guide to building it.
+    ghost: Option<Ghost>,
+    view_filter: ViewFilter,
+    // Whether every section of the slice layer needs meshing again, after
+    // the slice moved.
+    slice_layer_dirty: bool,
+    // Whether selection corners are sent to the server as WorldEdit
+    // commands too.
+    sync_selection: bool,
+    schematic_directory: PathBuf,
+    screenshot_directory: PathBuf,
+    frame_recorder: Option<FrameRecorder>,
+    // Whether the frame recording stops when the camera path does.
+    recording_camera_path: bool,
+    frame_directory: PathBuf,
+    // The command frames are piped to, or empty to save them as images.
+    frame_encoder: String,
+    frame_rate: u32,
+    // How many times the window resolution high resolution screenshots
+    // are taken at.
+    screenshot_scale: u32,
 }
 
 impl Client {
@@ -22,19 +113,181 @@ impl Client {
 
         let mut window = Window::init(&config);
 
-        let renderer = Renderer::init(&mut window.glfw_window);
+        let renderer = Renderer::init(&mut window.glfw_window, &config);
+
+        let mut chunk_manager = ChunkManager::new(&config);
+        let replay = argument("--replay")
+            .map(|path| ReplayPlayer::open(Path::new(&path)).expect("Unable to open replay file"));
+        let block_states = if replay.is_some() {
+            Some(require_block_states(&config))
+        } else {
+            load_block_states(&config)
+        };
+        let screenshot_directory = PathBuf::from(
+            config
+                .get_str("screenshot_directory")
+                .expect(unset_setting("screenshot_directory").as_ref()),
+        );
+        let screenshot_scale = config
+            .get_int("screenshot_scale")
+            .expect(unset_setting("screenshot_scale").as_ref());
+        let screenshot_scale = u32::try_from(screenshot_scale)
+            .ok()
+            .filter(|scale| *scale >= 1)
+            .expect(invalid_setting("screenshot_scale").as_ref());
+
+        let frame_directory = PathBuf::from(
+            config
+                .get_str("frame_directory")
+                .expect(unset_setting("frame_directory").as_ref()),
+        );
+        let frame_encoder = config
+            .get_str("frame_encoder")
+            .expect(unset_setting("frame_encoder").as_ref());
+        let frame_rate = config
+            .get_int("frame_rate")
+            .expect(unset_setting("frame_rate").as_ref());
+        let frame_rate = u32::try_from(frame_rate)
+            .ok()
+            .filter(|rate| *rate >= 1)
+            .expect(invalid_setting("frame_rate").as_ref());
 
-        let mut player = Player::new();
-        player.spatial.set_translation(&Vector3::new(0.0, 0.0, 3.0));
+        let probe_directory = PathBuf::from(
+            config
+                .get_str("probe_directory")
+                .expect(unset_setting("probe_directory").as_ref()),
+        );
 
+        let sync_selection = config
+            .get_bool("sync_selection")
+            .expect(unset_setting("sync_selection").as_ref());
+        let schematic_directory = PathBuf::from(
+            config
+                .get_str("schematic_directory")
+                .expect(unset_setting("schematic_directory").as_ref()),
+        );
+
+        let xray_blocks = config
+            .get_str("xray_blocks")
+            .expect(unset_setting("xray_blocks").as_ref())
+            .split(',')
+            .map(|name| name.trim().trim_start_matches("minecraft:").to_owned())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        let mut start = Vector3::new(0.0, 0.0, 3.0);
+        // Circuits are only simulated locally when there is no server to
+        // run them.
+        let mut redstone = None;
+        if let Some(path) = argument("--schematic") {
+            let schematic = Schematic::load(Path::new(&path)).expect("Unable to load schematic");
+            let origin = position_argument("--origin");
+            // Start off back from the schematic, facing it.
+            start = Vector3::new(
+                origin.x as f32 + schematic.width as f32 / 2.0,
+                origin.y as f32 + schematic.height as f32 / 2.0,
+                origin.z as f32
+                    + schematic.length as f32
+                    + 4.0
+                    + schematic.width.max(schematic.height) as f32,
+            );
+            chunk_manager.set_source(Some(Box::new(SchematicSource::new(schematic, origin))));
+            redstone = Some(start_redstone(&config));
+        }
+        if let Some(path) = argument("--world") {
+            let path = Path::new(&path);
+            let source = AnvilSource::open(path).expect("Unable to open world");
+            if let Some(spawn) = read_spawn(path) {
+                start = Vector3::new(spawn.x as f32 + 0.5, spawn.y as f32, spawn.z as f32 + 0.5);
+            }
+            chunk_manager.set_source(Some(Box::new(source)));
+            redstone = Some(start_redstone(&config));
+        }
+        let ghost = argument("--ghost").map(|path| {
+            let schematic =
+                Schematic::load(Path::new(&path)).expect("Unable to load ghost schematic");
+            Ghost::new(schematic, position_argument("--ghost-origin"))
+        });
+        if let Some(position) = replay
+            .as_ref()
+            .and_then(|replay| replay.first_player_position())
+        {
+            start = position;
+        }
+
+        let spectator_speed = config
+            .get_float("spectator_speed")
+            .expect(unset_setting("spectator_speed").as_ref()) as f32;
+
+        let camera_path = match argument("--camera-path") {
+            Some(path) => CameraPath::load(Path::new(&path)).expect("Unable to load camera path"),
+            None => CameraPath::new(),
+        };
+        let camera_path_directory = PathBuf::from(
+            config
+                .get_str("camera_path_directory")
+                .expect(unset_setting("camera_path_directory").as_ref()),
+        );
+        let keyframe_spacing = config
+            .get_float("keyframe_spacing")
+            .expect(unset_setting("keyframe_spacing").as_ref());
+        let keyframe_spacing = Some(keyframe_spacing as f32)
+            .filter(|spacing| *spacing > 0.0)
+            .expect(invalid_setting("keyframe_spacing").as_ref());
+
+        let mut player = Player::new(&config);
+        player.teleport(&start);
+
+        let fov = renderer.camera.vfov();
         let mut client = Client {
             config,
             window,
             renderer,
             player,
+            camera: CameraControl::new(spectator_speed),
+            fov,
+            camera_path,
+            camera_path_directory,
+            keyframe_spacing,
+            world: World::new(),
+            block_states,
+            chunk_manager,
+            entities: EntityRegistry::new(),
+            player_list: PlayerList::new(),
+            outgoing: Vec::new(),
+            recorder: None,
+            replay,
+            redstone,
+            network: None,
+            analyzer: LogicAnalyzer::new(),
+            probe_directory,
+            selection: Selection::new(),
+            ghost,
+            view_filter: ViewFilter::new(xray_blocks),
+            slice_layer_dirty: false,
+            sync_selection,
+            schematic_directory,
+            screenshot_directory,
+            frame_recorder: None,
+            recording_camera_path: false,
+            frame_directory,
+            frame_encoder,
+            frame_rate,
+            screenshot_scale,
         };
 
+        let mut last_frame = Instant::now();
+
         while !client.window.glfw_window.should_close() {
+            let now = Instant::now();
+            let mut delta = (now - last_frame).as_secs_f32();
+            last_frame = now;
+            // While recording frames, time moves on by one frame at a
+            // time however long each takes to draw and save.
+            if let Some(recorder) = &client.frame_recorder {
+                delta = recorder.frame_time();
+            }
+
             let mut control_vector = Vector3::new(0.0, 0.0, 0.0);
             if client.window.control_state.forward {
                 control_vector[2] -= 1.0;
@@ -55,19 +308,818 @@ impl Client {
                 control_vector[1] -= 1.0;
             }
 
-            client.player.set_control_vector(&control_vector);
-            client.player.set_control_heading(&Vector3::new(
-                client.window.control_state.pitch,
-                client.window.control_state.yaw,
-                0.0));
-            client.player.update();
+            client.update_camera_mode();
+            client.update_camera_path();
+            client.update_frame_recording();
+            let controls = &mut client.window.control_state;
+            if client.camera.is_spectating() {
+                // The movement keys fly the camera, and the player keeps
+                // still facing the way they were.
+                client.camera.update_spectator(
+                    delta,
+                    &control_vector,
+                    controls.yaw,
+                    controls.sprint,
+                );
+                let (pitch, yaw) = client.camera.player_heading();
+                client
+                    .player
+                    .set_control_vector(&Vector3::new(0.0, 0.0, 0.0));
+                client
+                    .player
+                    .set_control_heading(&Vector3::new(pitch, yaw, 0.0));
+                client.player.set_control_sprint(false);
+                if controls.scroll != 0.0 {
+                    client.camera.adjust_speed(controls.scroll);
+                    controls.scroll = 0.0;
+                }
+            } else if client.camera_path.is_playing() {
+                // The path has the camera, so the player stays put.
+                client
+                    .player
+                    .set_control_vector(&Vector3::new(0.0, 0.0, 0.0));
+                client.player.set_control_sprint(false);
+            } else {
+                client.player.set_control_vector(&control_vector);
+                client
+                    .player
+                    .set_control_heading(&Vector3::new(controls.pitch, controls.yaw, 0.0));
+                client.player.set_control_sprint(controls.sprint);
+                if controls.scroll != 0.0 {
+                    client.player.adjust_fly_speed(controls.scroll);
+                    controls.scroll = 0.0;
+                }
+            }
+            let view_heading = Vector3::new(controls.pitch, controls.yaw, 0.0);
+            client.player.update(delta, &client.world);
+            for packet in client.player.take_state_packets() {
+                client.send_packet(&packet);
+            }
+            client.record_player();
+            client.update_replay(delta);
 
+            let (translation, rotation) = match client.camera_path.advance(delta) {
+                Some(keyframe) => {
+                    client.renderer.camera.set_vfov(keyframe.fov);
+                    (keyframe.position, keyframe.rotation)
+                }
+                None => {
+                    client.renderer.camera.set_vfov(client.fov);
+                    client.camera.place(
+                        &client.world,
+                        client.player.spatial.get_translation(),
+                        &view_heading,
+                    )
+                }
+            };
             let camera_spatial = client.renderer.camera.borrow_spatial_mut();
-            camera_spatial.set_translation(client.player.spatial.get_translation());
-            camera_spatial.set_rotation(client.player.spatial.get_rotation());
+            camera_spatial.set_translation(&translation);
+            camera_spatial.set_rotation(&rotation);
+
+            client.chunk_manager.update(
+                client.player.spatial.get_translation(),
+                &mut client.world,
+                &mut client.renderer,
+            );
+
+            if client.window.control_state.memory_report {
+                client.window.control_state.memory_report = false;
+                client
+                    .chunk_manager
+                    .print_memory_report(&client.world, &client.renderer);
+            }
+
+            if client.window.control_state.use_block {
+                client.window.control_state.use_block = false;
+                client.use_block();
+            }
+            if client.window.control_state.trace_network {
+                client.window.control_state.trace_network = false;
+                client.trace_network();
+            }
+            if client.window.control_state.select_pos1 {
+                client.window.control_state.select_pos1 = false;
+                client.select_corner(1);
+            }
+            if client.window.control_state.select_pos2 {
+                client.window.control_state.select_pos2 = false;
+                client.select_corner(2);
+            }
+            if client.window.control_state.export_selection {
+                client.window.control_state.export_selection = false;
+                client.export_selection();
+            }
+            if client.window.control_state.move_ghost {
+                client.window.control_state.move_ghost = false;
+                client.move_ghost();
+            }
+            if client.window.control_state.toggle_probe {
+                client.window.control_state.toggle_probe = false;
+                client.toggle_probe();
+            }
+            if client.window.control_state.export_probes {
+                client.window.control_state.export_probes = false;
+                match client.analyzer.save_vcd(&client.probe_directory) {
+                    Ok(path) => println!("Saved probe recording to {}", path.display()),
+                    Err(error) => eprintln!("Unable to save probe recording: {}", error),
+                }
+            }
+            // Probes are sampled once per redstone tick, by the simulation
+            // if there is one and otherwise at the same rate by the clock.
+            let analyzer = &mut client.analyzer;
+            match &mut client.redstone {
+                Some(redstone) => {
+                    redstone.update(delta, &mut client.world, |world| analyzer.sample(world))
+                }
+                None => analyzer.update(delta, &client.world),
+            }
+
+            client.update_view_filter();
+            // Hidden chunks out of range are meshed again when they return.
+            let chunk_manager = &client.chunk_manager;
+            let dirty_sections: Vec<SectionPos> = client
+                .world
+                .take_dirty_sections()
+                .into_iter()
+                .filter(|pos| chunk_manager.in_range(ChunkPos::new(pos.x, pos.z)))
+                .collect();
+            for pos in dirty_sections.iter() {
+                let mut snapshot = client.world.snapshot_section(*pos);
+                client.view_filter.apply(&mut snapshot);
+                client.renderer.queue_section_mesh(snapshot);
+            }
+            client.update_slice_layer(&dirty_sections);
+            client.renderer.clip_range = client.view_filter.clip_range();
+            if let Some(ghost) = &mut client.ghost {
+                if client.window.control_state.toggle_ghost {
+                    client.window.control_state.toggle_ghost = false;
+                    ghost.toggle_visible();
+                }
+                ghost.mark_sections_dirty(&dirty_sections);
+                for (pos, mesh) in ghost.update(&client.world, &client.view_filter) {
+                    client.renderer.set_ghost_mesh(pos, mesh);
+                }
+            }
+
+            client.entities.update(delta);
+
+            client
+                .renderer
+                .set_viewport_size(client.window.width, client.window.height);
+            if client.window.control_state.show_player_list {
+                client
+                    .player_list
+                    .queue_overlay(&mut client.renderer.text, client.renderer.width);
+            }
+            client
+                .analyzer
+                .queue_panel(&mut client.renderer.text, client.renderer.height);
+            if let Some(ghost) = &client.ghost {
+                ghost.queue_info(
+                    &mut client.renderer.text,
+                    client.renderer.width,
+                    client.renderer.height,
+                );
+            }
+            client.view_filter.queue_info(
+                &mut client.renderer.text,
+                client.renderer.width,
+                client.renderer.height,
+            );
+            client.selection.queue_wireframe(&mut client.renderer.lines);
+            client
+                .selection
+                .queue_info(&mut client.renderer.text, client.renderer.width);
+            client.camera_path.queue_preview(&mut client.renderer.lines);
+            client
+                .camera_path
+                .queue_info(&mut client.renderer.text, client.renderer.height);
+            if let Some(network) = &client.network {
+                queue_network(&mut client.renderer.lines, network);
+            }
+            if client.camera.mode != CameraMode::FirstPerson {
+                let (min, max) = client.player.bounding_box();
+                client
+                    .renderer
+                    .lines
+                    .queue_box(min.into(), max.into(), PLAYER_BOX_COLOR);
+            }
+            if client.window.control_state.signal_overlay {
+                let (eye, direction) = client.view_ray();
+                queue_signal_overlay(
+                    &mut client.renderer,
+                    &client.world,
+                    &client.view_filter,
+                    &eye,
+                    &direction,
+                );
+            }
+
+            // Drawn before the frame itself, so that the tiles don't end up
+            // on screen.
+            if client.window.control_state.high_res_screenshot {
+                client.window.control_state.high_res_screenshot = false;
+                match client
+                    .renderer
+                    .capture_high_res(&client.entities, client.screenshot_scale)
+                {
+                    Ok(image) => client.save_screenshot(&image),
+                    Err(error) => eprintln!("Unable to take screenshot: {}", error),
+                }
+            }
+
+            // Recorded frames are read back before the overlays are drawn,
+            // so that the video only shows the world.
+            if client.frame_recorder.is_some() {
+                let image = client.renderer.update_and_capture(&client.entities);
+                client.record_frame(&image);
+            } else {
+                client.renderer.update(&client.entities);
+            }
+
+            if client.window.control_state.screenshot {
+                client.window.control_state.screenshot = false;
+                let image = client.renderer.capture_frame();
+                client.save_screenshot(&image);
+            }
 
-            client.renderer.update();
             client.window.update();
         }
+        client.stop_frame_recording();
+    }
+
+    /// Decodes a packet from the server and hands it to whichever part of
+    /// the client deals with it. Every packet received passes through
+    /// here, so this is also where sessions are recorded, from the moment
+    /// a live server lets us join. There is no connection to a server yet,
+    /// though, so for now packets only come from replays and nothing is
+    /// recorded.
+    pub fn handle_packet(&mut self, raw: &RawPacket) {
+        let packet = ClientboundPacket::decode(raw);
+        if let Some(ClientboundPacket::JoinGame { entity_id }) = &packet {
+            // A replayed Join Game was sent to whoever recorded it.
+            if self.replay.is_none() {
+                self.player.entity_id = Some(*entity_id);
+                if self.recorder.is_none() {
+                    self.recorder = start_recording(&self.config);
+                }
+            }
+        }
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(error) = recorder.record_packet(raw) {
+                eprintln!("Stopped recording replay: {}", error);
+                self.recorder = None;
+            }
+        }
+
+        let packet = match packet {
+            Some(packet) => packet,
+            None => return,
+        };
+        if let Some(states) = &self.block_states {
+            self.world.handle_packet(&packet, states);
+        }
+        if let ClientboundPacket::UnloadChunk { x, z } = packet {
+            self.renderer.remove_chunk_meshes(ChunkPos::new(x, z));
+        }
+        self.entities.handle_packet(&packet);
+        self.player_list.handle_packet(&packet);
+    }
+
+    /// Whether a live server has let us join, rather than the world coming
+    /// from a replay or from files.
+    fn connected(&self) -> bool {
+        self.replay.is_none() && self.player.entity_id.is_some()
+    }
+
+    /// Queues a packet to be sent to the server, or drops it when there is
+    /// no server to send it to, so that nothing piles up unsent.
+    fn send_packet(&mut self, packet: &ServerboundPacket) {
+        if self.connected() {
+            self.outgoing.push(packet.encode());
+        }
+    }
+
+    /// Where blocks under the crosshair are looked for from, and which
+    /// way. This is the camera when spectating, but otherwise the player's
+    /// eye, so that third person views pick what the player faces.
+    fn view_ray(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let spatial = if self.camera.is_spectating() {
+            self.renderer.camera.borrow_spatial()
+        } else {
+            &self.player.spatial
+        };
+        let rotation = spatial.get_rotation();
+        (
+            spatial.get_translation().clone_owned(),
+            look_vector(rotation[0], rotation[1]),
+        )
+    }
+
+    /// Switches camera modes from the controls, handing the mouse back to
+    /// the player where it left off when spectating stops.
+    fn update_camera_mode(&mut self) {
+        let controls = &mut self.window.control_state;
+        let mut heading = None;
+        if controls.cycle_perspective {
+            controls.cycle_perspective = false;
+            heading = self.camera.cycle_perspective();
+        }
+        if controls.toggle_spectator {
+            controls.toggle_spectator = false;
+            heading = self.camera.toggle_spectator(
+                self.player.spatial.get_translation(),
+                (controls.pitch, controls.yaw),
+            );
+        }
+        if let Some((pitch, yaw)) = heading {
+            controls.pitch = pitch;
+            controls.yaw = yaw;
+        }
+    }
+
+    /// Edits, plays and saves the camera path from the controls, and
+    /// changes the field of view.
+    fn update_camera_path(&mut self) {
+        let controls = &mut self.window.control_state;
+        if controls.fov_offset != 0.0 {
+            self.fov = (self.fov + controls.fov_offset).clamp(MIN_FOV, MAX_FOV);
+            controls.fov_offset = 0.0;
+        }
+        if controls.add_keyframe {
+            controls.add_keyframe = false;
+            // Keyframes are taken from wherever the camera is, in any mode.
+            let spatial = self.renderer.camera.borrow_spatial();
+            self.camera_path.add_keyframe(
+                spatial.get_translation(),
+                spatial.get_rotation(),
+                self.fov,
+                self.keyframe_spacing,
+            );
+            println!(
+                "Added keyframe {} at {:.1}s",
+                self.camera_path.len(),
+                self.camera_path.duration()
+            );
+        }
+        if controls.remove_keyframe {
+            controls.remove_keyframe = false;
+            if self.camera_path.remove_last_keyframe().is_some() {
+                println!("Removed keyframe {}", self.camera_path.len() + 1);
+            }
+        }
+        if controls.toggle_camera_path {
+            controls.toggle_camera_path = false;
+            if !self.camera_path.toggle_playback() && self.camera_path.len() < 2 {
+                eprintln!("Add at least two keyframes before playing a camera path");
+            }
+        }
+        if controls.save_camera_path {
+            controls.save_camera_path = false;
+            if self.camera_path.is_empty() {
+                eprintln!("Add keyframes before saving a camera path");
+                return;
+            }
+            let saved = unused_path(&self.camera_path_directory, "campath")
+                .and_then(|path| self.camera_path.save(&path).map(|_| path));
+            match saved {
+                Ok(path) => println!("Saved camera path to {}", path.display()),
+                Err(error) => eprintln!("Unable to save camera path: {}", error),
+            }
+        }
+    }
+
+    /// Starts or stops recording frames from the controls. Recording the
+    /// camera path plays it from the start and stops with it.
+    fn update_frame_recording(&mut self) {
+        let controls = &mut self.window.control_state;
+        let toggle = std::mem::replace(&mut controls.toggle_frame_recording, false);
+        let record_path = std::mem::replace(&mut controls.record_camera_path, false);
+        if toggle {
+            if self.frame_recorder.is_some() {
+                self.stop_frame_recording();
+            } else {
+                self.start_frame_recording();
+            }
+        }
+        if record_path {
+            if self.camera_path.is_playing() {
+                self.camera_path.toggle_playback();
+            }
+            if !self.camera_path.toggle_playback() {
+                eprintln!("Add at least two keyframes before recording a camera path");
+                return;
+            }
+            if self.frame_recorder.is_none() {
+                self.start_frame_recording();
+            }
+            self.recording_camera_path = self.frame_recorder.is_some();
+        }
     }
+
+    fn start_frame_recording(&mut self) {
+        let recorder =
+            FrameRecorder::start(&self.frame_directory, &self.frame_encoder, self.frame_rate);
+        match recorder {
+            Ok(recorder) => {
+                println!("Recording frames to {}", recorder.directory.display());
+                self.frame_recorder = Some(recorder);
+            }
+            Err(error) => eprintln!("Unable to record frames: {}", error),
+        }
+    }
+
+    fn stop_frame_recording(&mut self) {
+        self.recording_camera_path = false;
+        let recorder = match self.frame_recorder.take() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let (frames, directory) = (recorder.frames(), recorder.directory.clone());
+        match recorder.finish() {
+            Ok(()) => println!("Recorded {} frames to {}", frames, directory.display()),
+            Err(error) => eprintln!("Unable to finish recording frames: {}", error),
+        }
+    }
+
+    /// Adds a frame to the recording, if there is one.
+    fn record_frame(&mut self, image: &Image) {
+        let recorder = match &mut self.frame_recorder {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        if let Err(error) = recorder.write_frame(image) {
+            eprintln!("Stopped recording frames: {}", error);
+            self.stop_frame_recording();
+            return;
+        }
+        // The path's last frame has just been drawn.
+        if self.recording_camera_path && !self.camera_path.is_playing() {
+            self.stop_frame_recording();
+        }
+    }
+
+    /// Applies changes to the view filter from the controls. Moving the
+    /// slice only changes the clipping and the slice layer, but x-ray
+    /// changes need the world meshing again.
+    fn update_view_filter(&mut self) {
+        let controls = &mut self.window.control_state;
+        let mut slice_moved = false;
+        let mut xray_changed = false;
+        if controls.cycle_slice {
+            controls.cycle_slice = false;
+            // Slicing starts at the layer the player is standing on.
+            let y = self.player.position.y.floor() as i32 - 1;
+            self.view_filter.cycle_slice_mode(y);
+            slice_moved = true;
+        }
+        if controls.slice_offset != 0 {
+            self.view_filter.slice_y += controls.slice_offset;
+            controls.slice_offset = 0;
+            slice_moved |= self.view_filter.slice_mode != SliceMode::Off;
+        }
+        if controls.toggle_xray {
+            controls.toggle_xray = false;
+            self.view_filter.xray = !self.view_filter.xray;
+            xray_changed = true;
+        }
+        if controls.xray_unhide_type {
+            controls.xray_unhide_type = false;
+            if let Some(name) = self.view_filter.unhide_last_type() {
+                println!("Showing {} again", name);
+                xray_changed |= self.view_filter.xray;
+            }
+        }
+        if controls.xray_hide_type {
+            controls.xray_hide_type = false;
+            if let Some(pos) = self.target_block(TRACE_REACH) {
+                let block = self.world.get_block(pos);
+                if let Some(name) = self.view_filter.hide_type(block) {
+                    println!("Hiding {}", name);
+                }
+                xray_changed = true;
+            }
+        }
+        if slice_moved {
+            self.renderer.clear_slice_sections();
+            self.slice_layer_dirty = true;
+        }
+        if xray_changed {
+            let chunks: Vec<_> = self.world.chunk_positions().collect();
+            for pos in chunks {
+                self.world.mark_chunk_dirty(pos);
+            }
+            if let Some(ghost) = &mut self.ghost {
+                ghost.mark_dirty();
+            }
+        }
+    }
+
+    /// Meshes the layer the slice cuts through on its own, for the
+    /// sections of it in `dirty_sections`, or all of them after the slice
+    /// has moved.
+    fn update_slice_layer(&mut self, dirty_sections: &[SectionPos]) {
+        let dirty = std::mem::replace(&mut self.slice_layer_dirty, false);
+        let section_y = match self.view_filter.slice_layer() {
+            Some(y) => y.div_euclid(SECTION_SIZE),
+            None => return,
+        };
+        let sections: Vec<SectionPos> = if dirty {
+            self.world
+                .chunk_positions()
+                .map(|pos| SectionPos::new(pos.x, section_y, pos.z))
+                .collect()
+        } else {
+            dirty_sections
+                .iter()
+                .filter(|pos| pos.y == section_y)
+                .copied()
+                .collect()
+        };
+        for pos in sections {
+            let mut snapshot = self.world.snapshot_section(pos);
+            self.view_filter.apply_layer(&mut snapshot);
+            self.renderer.set_slice_section(&snapshot);
+        }
+    }
+
+    /// The block under the crosshair within `reach`, looking past blocks
+    /// the view filter hides.
+    fn target_block(&mut self, reach: f32) -> Option<BlockPos> {
+        let (eye, direction) = self.view_ray();
+        let filter = &self.view_filter;
+        self.world.raycast(&eye, &direction, reach, |pos, block| {
+            !block.is_air() && filter.is_visible(pos, block)
+        })
+    }
+
+    /// Uses the block under the crosshair, if it is within reach.
+    fn use_block(&mut self) {
+        let target = self.target_block(REACH);
+        if let (Some(redstone), Some(pos)) = (&mut self.redstone, target) {
+            redstone.use_block(&mut self.world, pos);
+        }
+    }
+
+    /// Picks out the redstone network under the crosshair, or clears the
+    /// one picked out before if there isn't one.
+    fn trace_network(&mut self) {
+        self.network = self
+            .target_block(TRACE_REACH)
+            .map(|pos| match &self.redstone {
+                Some(redstone) => redstone.trace_network(&self.world, pos),
+                // Without a simulation running, an empty one reads the
+                // circuit straight from the world.
+                None => RedstoneSimulation::new(1.0).trace_network(&self.world, pos),
+            })
+            .filter(|network| !network.is_empty());
+        if let Some(network) = &self.network {
+            println!(
+                "Traced {} wire, {} inputs and {} outputs",
+                network.wires.len(),
+                network.inputs.len(),
+                network.outputs.len()
+            );
+        }
+    }
+
+    /// Sets a corner of the selection to the block under the crosshair,
+    /// telling the server too when connected to one.
+    fn select_corner(&mut self, corner: u8) {
+        let pos = match self.target_block(TRACE_REACH) {
+            Some(pos) => pos,
+            None => return,
+        };
+        if corner == 1 {
+            self.selection.pos1 = Some(pos);
+        } else {
+            self.selection.pos2 = Some(pos);
+        }
+        match self.selection.volume() {
+            Some(volume) => println!(
+                "Set position {} to {} {} {} ({} blocks)",
+                corner, pos.x, pos.y, pos.z, volume
+            ),
+            None => println!("Set position {} to {} {} {}", corner, pos.x, pos.y, pos.z),
+        }
+
+        if self.sync_selection {
+            let packet = ServerboundPacket::ChatMessage {
+                message: position_command(corner, pos),
+            };
+            self.send_packet(&packet);
+        }
+    }
+
+    /// Saves the selected blocks as a schematic in the schematic
+    /// directory.
+    fn export_selection(&self) {
+        let (min, max) = match self.selection.bounds() {
+            Some(bounds) => bounds,
+            None => {
+                eprintln!("Select two corners before exporting a schematic");
+                return;
+            }
+        };
+        let saved = Schematic::from_world(&self.world, min, max).and_then(|schematic| {
+            let path = unused_path(&self.schematic_directory, "schem")?;
+            schematic.save(&path, min)?;
+            Ok(path)
+        });
+        match saved {
+            Ok(path) => println!("Saved schematic to {}", path.display()),
+            Err(error) => eprintln!("Unable to save schematic: {}", error),
+        }
+    }
+
+    /// Moves the ghost so that its lowest corner sits on top of the block
+    /// under the crosshair.
+    fn move_ghost(&mut self) {
+        let hit = self.target_block(TRACE_REACH);
+        if let (Some(ghost), Some(pos)) = (&mut self.ghost, hit) {
+            let origin = pos.offset(0, 1, 0);
+            ghost.set_origin(origin);
+            println!("Moved ghost to {} {} {}", origin.x, origin.y, origin.z);
+        }
+    }
+
+    /// Adds or removes a probe on the block under the crosshair.
+    fn toggle_probe(&mut self) {
+        if let Some(pos) = self.target_block(TRACE_REACH) {
+            let added = self.analyzer.toggle_probe(pos);
+            println!(
+                "{} probe at {} {} {}",
+                if added { "Added" } else { "Removed" },
+                pos.x,
+                pos.y,
+                pos.z
+            );
+        }
+    }
+
+    fn save_screenshot(&self, image: &Image) {
+        match save_screenshot(&self.screenshot_directory, image) {
+            Ok(path) => println!("Saved screenshot to {}", path.display()),
+            Err(error) => eprintln!("Unable to save screenshot: {}", error),
+        }
+    }
+
+    fn record_player(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            let (pitch, yaw) = self.player.minecraft_rotation();
+            if let Err(error) = recorder.record_player(&self.player.position, pitch, yaw) {
+                eprintln!("Stopped recording replay: {}", error);
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// Plays back the replay, if there is one. Seeking backwards starts
+    /// over from an empty state, and plays every event up to the new time
+    /// again to rebuild it.
+    fn update_replay(&mut self, delta: f32) {
+        let replay = match &mut self.replay {
+            Some(replay) => replay,
+            None => return,
+        };
+
+        let controls = &mut self.window.control_state;
+        if controls.replay_pause {
+            controls.replay_pause = false;
+            replay.toggle_pause();
+        }
+        if controls.replay_speed != 0 {
+            replay.change_speed(controls.replay_speed);
+            controls.replay_speed = 0;
+        }
+        let mut rewound = false;
+        if controls.replay_seek != 0.0 {
+            rewound = replay.seek(replay.time() + controls.replay_seek);
+            controls.replay_seek = 0.0;
+        }
+
+        let events = replay.advance(delta);
+        let status = format!(
+            "Replay {} / {}  x{}{}",
+            format_time(replay.time()),
+            format_time(replay.duration()),
+            replay.speed(),
+            if replay.is_paused() { "  Paused" } else { "" }
+        );
+
+        if rewound {
+            // The world is built up from the replayed chunk packets too, so
+            // it starts over along with its meshes.
+            for pos in self.world.chunk_positions().collect::<Vec<_>>() {
+                self.renderer.remove_chunk_meshes(pos);
+            }
+            self.world = World::new();
+            self.chunk_manager.reload();
+            self.entities.clear();
+            self.player_list = PlayerList::new();
+        }
+        for event in events {
+            match event {
+                ReplayEvent::Packet(raw) => self.handle_packet(&raw),
+                ReplayEvent::Player {
+                    position,
+                    pitch,
+                    yaw,
+                } => {
+                    let spawned = self.entities.get(REPLAY_PLAYER_ID).is_some();
+                    for packet in replay_player_packets(&position, pitch, yaw, spawned).iter() {
+                        self.entities.handle_packet(packet);
+                    }
+                }
+            }
+        }
+
+        self.renderer
+            .text
+            .queue_text(8.0, 8.0, 2.0, [1.0, 1.0, 1.0, 1.0], &status);
+    }
+}
+
+/// Where to put a schematic, given on the command line as e.g.
+/// `--origin <x>,<y>,<z>`.
+fn position_argument(name: &str) -> BlockPos {
+    match argument(name) {
+        Some(origin) => {
+            let origin =
+                parse_list::<i32>(&origin, ',', 3).unwrap_or_else(|error| panic!("{}", error));
+            BlockPos::new(origin[0], origin[1], origin[2])
+        }
+        None => BlockPos::new(0, 0, 0),
+    }
+}
+
+/// Starts recording a replay into the replay directory, if recording is
+/// turned on.
+fn start_recording(config: &Config) -> Option<ReplayRecorder> {
+    let record = config
+        .get_bool("record_replays")
+        .expect(unset_setting("record_replays").as_ref());
+    if !record {
+        return None;
+    }
+    let directory = PathBuf::from(
+        config
+            .get_str("replay_directory")
+            .expect(unset_setting("replay_directory").as_ref()),
+    );
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let path = directory.join(format!("{}.mcreplay", timestamp));
+    let recorder = fs::create_dir_all(&directory).and_then(|_| ReplayRecorder::create(&path));
+    match recorder {
+        Ok(recorder) => Some(recorder),
+        Err(error) => {
+            eprintln!("Unable to record replay to {}: {}", path.display(), error);
+            None
+        }
+    }
+}
+
+/// Outlines each part of a traced network, with wire drawn flat like the
+/// wire itself.
+fn queue_network(lines: &mut LineRenderer, network: &Network) {
+    // Grown a little so the outlines don't fight with the block faces.
+    let outline = |lines: &mut LineRenderer, pos: &BlockPos, height: f32, color| {
+        let min = [pos.x as f32, pos.y as f32, pos.z as f32];
+        lines.queue_box(
+            [min[0] - 0.01, min[1] - 0.01, min[2] - 0.01],
+            [min[0] + 1.01, min[1] + height + 0.01, min[2] + 1.01],
+            color,
+        );
+    };
+    for pos in network.wires.iter() {
+        outline(lines, pos, 0.0625, NETWORK_WIRE_COLOR);
+    }
+    for pos in network.blocks.iter() {
+        outline(lines, pos, 1.0, NETWORK_BLOCK_COLOR);
+    }
+    for pos in network.inputs.iter() {
+        outline(lines, pos, 1.0, NETWORK_INPUT_COLOR);
+    }
+    for pos in network.outputs.iter() {
+        outline(lines, pos, 1.0, NETWORK_OUTPUT_COLOR);
+    }
+}
+
+fn start_redstone(config: &Config) -> RedstoneSimulation {
+    let speed = config
+        .get_float("redstone_speed")
+        .expect(unset_setting("redstone_speed").as_ref());
+    let speed = Some(speed as f32)
+        .filter(|speed| *speed > 0.0)
+        .expect(invalid_setting("redstone_speed").as_ref());
+    RedstoneSimulation::new(speed)
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
 }
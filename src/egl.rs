@@ -0,0 +1,157 @@
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+
+// Just enough of EGL to make an OpenGL context that isn't tied to a window
+// or a display server, using Mesa's surfaceless platform.
+
+type Display = *mut c_void;
+type Config = *mut c_void;
+type RawContext = *mut c_void;
+type Surface = *mut c_void;
+
+const NONE: i32 = 0x3038;
+const SURFACE_TYPE: i32 = 0x3033;
+const PBUFFER_BIT: i32 = 0x0001;
+const RENDERABLE_TYPE: i32 = 0x3040;
+const OPENGL_BIT: i32 = 0x0008;
+const OPENGL_API: u32 = 0x30A2;
+const CONTEXT_MAJOR_VERSION: i32 = 0x3098;
+const CONTEXT_MINOR_VERSION: i32 = 0x30FB;
+const CONTEXT_OPENGL_PROFILE_MASK: i32 = 0x30FD;
+const CONTEXT_OPENGL_CORE_PROFILE_BIT: i32 = 0x0001;
+const PLATFORM_SURFACELESS_MESA: u32 = 0x31DD;
+
+type GetPlatformDisplay = unsafe extern "C" fn(
+    platform: u32,
+    native_display: *mut c_void,
+    attribs: *const i32,
+) -> Display;
+
+#[link(name = "EGL")]
+extern "C" {
+    fn eglGetProcAddress(name: *const c_char) -> *const c_void;
+    fn eglGetError() -> i32;
+    fn eglInitialize(display: Display, major: *mut i32, minor: *mut i32) -> u32;
+    fn eglTerminate(display: Display) -> u32;
+    fn eglBindAPI(api: u32) -> u32;
+    fn eglChooseConfig(
+        display: Display,
+        attribs: *const i32,
+        configs: *mut Config,
+        config_size: i32,
+        config_count: *mut i32,
+    ) -> u32;
+    fn eglCreateContext(
+        display: Display,
+        config: Config,
+        share_context: RawContext,
+        attribs: *const i32,
+    ) -> RawContext;
+    fn eglDestroyContext(display: Display, context: RawContext) -> u32;
+    fn eglMakeCurrent(display: Display, draw: Surface, read: Surface, context: RawContext) -> u32;
+}
+
+/// Looks up an EGL or OpenGL function, for `gl::load_with`.
+pub fn get_proc_address(name: &str) -> *const c_void {
+    match CString::new(name) {
+        Ok(name) => unsafe { eglGetProcAddress(name.as_ptr()) },
+        Err(_) => ptr::null(),
+    }
+}
+
+fn failed(call: &str) -> String {
+    let error = unsafe { eglGetError() };
+    format!("{} failed with EGL error {:#x}", call, error)
+}
+
+/// A core profile OpenGL context with no surface at all, current on the
+/// thread that made it. Everything has to be drawn to framebuffers of our
+/// own.
+pub struct Context {
+    display: Display,
+    context: RawContext,
+}
+
+impl Context {
+    pub fn create_surfaceless(major: i32, minor: i32) -> Result<Context, String> {
+        unsafe {
+            let get_platform_display = get_proc_address("eglGetPlatformDisplayEXT");
+            if get_platform_display.is_null() {
+                return Err("EGL doesn't support choosing a platform".to_owned());
+            }
+            let get_platform_display: GetPlatformDisplay =
+                std::mem::transmute(get_platform_display);
+            let display =
+                get_platform_display(PLATFORM_SURFACELESS_MESA, ptr::null_mut(), ptr::null());
+            if display.is_null() {
+                return Err(failed("eglGetPlatformDisplayEXT"));
+            }
+            if eglInitialize(display, ptr::null_mut(), ptr::null_mut()) == 0 {
+                return Err(failed("eglInitialize"));
+            }
+
+            // From here on, dropping the context terminates the display.
+            let mut context = Context {
+                display,
+                context: ptr::null_mut(),
+            };
+            if eglBindAPI(OPENGL_API) == 0 {
+                return Err(failed("eglBindAPI"));
+            }
+
+            // Configs are for window surfaces unless asked otherwise, and
+            // the surfaceless platform only has pbuffer ones.
+            let config_attribs = [SURFACE_TYPE, PBUFFER_BIT, RENDERABLE_TYPE, OPENGL_BIT, NONE];
+            let mut config = ptr::null_mut();
+            let mut config_count = 0;
+            if eglChooseConfig(
+                display,
+                config_attribs.as_ptr(),
+                &mut config,
+                1,
+                &mut config_count,
+            ) == 0
+                || config_count == 0
+            {
+                return Err(failed("eglChooseConfig"));
+            }
+
+            let context_attribs = [
+                CONTEXT_MAJOR_VERSION,
+                major,
+                CONTEXT_MINOR_VERSION,
+                minor,
+                CONTEXT_OPENGL_PROFILE_MASK,
+                CONTEXT_OPENGL_CORE_PROFILE_BIT,
+                NONE,
+            ];
+            context.context =
+                eglCreateContext(display, config, ptr::null_mut(), context_attribs.as_ptr());
+            if context.context.is_null() {
+                return Err(failed("eglCreateContext"));
+            }
+            if eglMakeCurrent(display, ptr::null_mut(), ptr::null_mut(), context.context) == 0 {
+                return Err(failed("eglMakeCurrent"));
+            }
+            Ok(context)
+        }
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe {
+            eglMakeCurrent(
+                self.display,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            if !self.context.is_null() {
+                eglDestroyContext(self.display, self.context);
+            }
+            eglTerminate(self.display);
+        }
+    }
+}
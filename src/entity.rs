@@ -0,0 +1,252 @@
+use crate::packet::ClientboundPacket;
+use crate::spatial::Spatial;
+use nalgebra::Vector3;
+use std::collections::HashMap;
+
+/// How long, in seconds, an entity takes to glide to a new position. The
+/// server sends movement every tick, vanilla smooths it over three ticks.
+const INTERPOLATION_TIME: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityKind {
+    Player,
+    /// Any other entity, identified by its entity type id.
+    Other(i32),
+}
+
+/// An entity in the world other than the local player. The spatial holds
+/// the smoothed position drawn on screen, with a rotation of (pitch, yaw,
+/// 0) in degrees using Minecraft's conventions.
+pub struct Entity {
+    pub id: i32,
+    pub uuid: u128,
+    pub kind: EntityKind,
+    pub spatial: Spatial,
+    pub head_yaw: f32,
+    pub on_ground: bool,
+
+    // Where the server last said the entity is. Relative moves are applied
+    // to this rather than to the smoothed position.
+    server_translation: Vector3<f64>,
+    start_translation: Vector3<f32>,
+    start_rotation: Vector3<f32>,
+    target_rotation: Vector3<f32>,
+    interpolation_elapsed: f32,
+}
+
+impl Entity {
+    fn new(
+        id: i32,
+        uuid: u128,
+        kind: EntityKind,
+        translation: Vector3<f64>,
+        rotation: Vector3<f32>,
+    ) -> Entity {
+        let mut spatial = Spatial::new();
+        let start_translation = translation.map(|x| x as f32);
+        spatial.set_translation(&start_translation);
+        spatial.set_rotation(&rotation);
+        Entity {
+            id,
+            uuid,
+            kind,
+            spatial,
+            head_yaw: rotation[1],
+            on_ground: false,
+            server_translation: translation,
+            start_translation,
+            start_rotation: rotation.clone_owned(),
+            target_rotation: rotation,
+            interpolation_elapsed: INTERPOLATION_TIME,
+        }
+    }
+
+    pub fn borrow_spatial(&self) -> &Spatial {
+        &self.spatial
+    }
+
+    /// Starts gliding from wherever the entity is drawn now towards the
+    /// latest server position and rotation.
+    fn begin_interpolation(&mut self, rotation: Option<(f32, f32)>) {
+        self.start_translation = self.spatial.get_translation().clone_owned();
+        self.start_rotation = self.spatial.get_rotation().clone_owned();
+        if let Some((yaw, pitch)) = rotation {
+            self.target_rotation = Vector3::new(pitch, yaw, 0.0);
+        }
+        self.interpolation_elapsed = 0.0;
+    }
+
+    fn update(&mut self, delta: f32) {
+        if self.interpolation_elapsed >= INTERPOLATION_TIME {
+            return;
+        }
+        self.interpolation_elapsed = (self.interpolation_elapsed + delta).min(INTERPOLATION_TIME);
+        let t = self.interpolation_elapsed / INTERPOLATION_TIME;
+
+        let target_translation = self.server_translation.map(|x| x as f32);
+        let translation = self.start_translation.lerp(&target_translation, t);
+        let rotation = Vector3::new(
+            lerp_degrees(self.start_rotation[0], self.target_rotation[0], t),
+            lerp_degrees(self.start_rotation[1], self.target_rotation[1], t),
+            0.0,
+        );
+        self.spatial.set_translation(&translation);
+        self.spatial.set_rotation(&rotation);
+    }
+}
+
+/// Interpolates between two angles in degrees the short way around.
+fn lerp_degrees(from: f32, to: f32, t: f32) -> f32 {
+    let mut difference = (to - from) % 360.0;
+    if difference > 180.0 {
+        difference -= 360.0;
+    } else if difference < -180.0 {
+        difference += 360.0;
+    }
+    from + difference * t
+}
+
+/// Keeps track of the entities the server has told us about.
+pub struct EntityRegistry {
+    entities: HashMap<i32, Entity>,
+}
+
+impl EntityRegistry {
+    pub fn new() -> EntityRegistry {
+        EntityRegistry {
+            entities: HashMap::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, id: i32) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.values()
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+
+    /// Applies an entity related packet.
+    pub fn handle_packet(&mut self, packet: &ClientboundPacket) {
+        match *packet {
+            ClientboundPacket::SpawnPlayer {
+                entity_id,
+                uuid,
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+            } => {
+                let entity = Entity::new(
+                    entity_id,
+                    uuid,
+                    EntityKind::Player,
+                    Vector3::new(x, y, z),
+                    Vector3::new(pitch, yaw, 0.0),
+                );
+                self.entities.insert(entity_id, entity);
+            }
+            ClientboundPacket::SpawnEntity {
+                entity_id,
+                uuid,
+                entity_type,
+                x,
+                y,
+                z,
+                pitch,
+                yaw,
+            } => {
+                let entity = Entity::new(
+                    entity_id,
+                    uuid,
+                    EntityKind::Other(entity_type),
+                    Vector3::new(x, y, z),
+                    Vector3::new(pitch, yaw, 0.0),
+                );
+                self.entities.insert(entity_id, entity);
+            }
+            ClientboundPacket::EntityPosition {
+                entity_id,
+                delta_x,
+                delta_y,
+                delta_z,
+                on_ground,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.server_translation += Vector3::new(delta_x, delta_y, delta_z);
+                    entity.on_ground = on_ground;
+                    entity.begin_interpolation(None);
+                }
+            }
+            ClientboundPacket::EntityPositionAndRotation {
+                entity_id,
+                delta_x,
+                delta_y,
+                delta_z,
+                yaw,
+                pitch,
+                on_ground,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.server_translation += Vector3::new(delta_x, delta_y, delta_z);
+                    entity.on_ground = on_ground;
+                    entity.begin_interpolation(Some((yaw, pitch)));
+                }
+            }
+            ClientboundPacket::EntityRotation {
+                entity_id,
+                yaw,
+                pitch,
+                on_ground,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.on_ground = on_ground;
+                    entity.begin_interpolation(Some((yaw, pitch)));
+                }
+            }
+            ClientboundPacket::EntityHeadLook {
+                entity_id,
+                head_yaw,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.head_yaw = head_yaw;
+                }
+            }
+            ClientboundPacket::EntityTeleport {
+                entity_id,
+                x,
+                y,
+                z,
+                yaw,
+                pitch,
+                on_ground,
+            } => {
+                if let Some(entity) = self.entities.get_mut(&entity_id) {
+                    entity.server_translation = Vector3::new(x, y, z);
+                    entity.on_ground = on_ground;
+                    entity.begin_interpolation(Some((yaw, pitch)));
+                }
+            }
+            ClientboundPacket::DestroyEntities { ref entity_ids } => {
+                for entity_id in entity_ids {
+                    self.entities.remove(entity_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances interpolation by `delta` seconds.
+    pub fn update(&mut self, delta: f32) {
+        for entity in self.entities.values_mut() {
+            entity.update(delta);
+        }
+    }
+}
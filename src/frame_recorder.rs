@@ -0,0 +1,143 @@
+use crate::screenshot::{unused_path, Image};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// Where recorded frames go.
+enum FrameOutput {
+    /// Numbered PNG files in a directory.
+    Images,
+    /// Raw RGBA frames piped into an encoder's standard input. The encoder
+    /// is started with the first frame, once its size is known.
+    Encoder {
+        command: String,
+        process: Option<Child>,
+    },
+}
+
+/// Records frames for making videos, one for each step of a fixed length
+/// rather than as fast as they can be drawn, so that the video plays back
+/// smoothly however slowly it was recorded.
+pub struct FrameRecorder {
+    output: FrameOutput,
+    /// Where the frames go, or where the encoder is run from.
+    pub directory: PathBuf,
+    frame_rate: u32,
+    frames: u32,
+    // The size of the first frame, which every later one has to match.
+    size: Option<(u32, u32)>,
+}
+
+impl FrameRecorder {
+    /// Starts a recording in a new directory under `directory`. Frames
+    /// are saved as images if `encoder` is empty, or otherwise piped to
+    /// the encoder command, with `{width}`, `{height}` and `{fps}` filled
+    /// in. The command is split on whitespace, so its arguments can't be
+    /// quoted.
+    pub fn start(directory: &Path, encoder: &str, frame_rate: u32) -> io::Result<FrameRecorder> {
+        let directory = unused_path(directory, "frames")?;
+        fs::create_dir(&directory)?;
+        let output = if encoder.trim().is_empty() {
+            FrameOutput::Images
+        } else {
+            FrameOutput::Encoder {
+                command: encoder.to_owned(),
+                process: None,
+            }
+        };
+        Ok(FrameRecorder {
+            output,
+            directory,
+            frame_rate,
+            frames: 0,
+            size: None,
+        })
+    }
+
+    /// How many seconds pass between frames.
+    pub fn frame_time(&self) -> f32 {
+        1.0 / self.frame_rate as f32
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Adds the next frame to the recording.
+    pub fn write_frame(&mut self, image: &Image) -> io::Result<()> {
+        let size = *self.size.get_or_insert((image.width, image.height));
+        if size != (image.width, image.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "the window changed size while recording",
+            ));
+        }
+        match &mut self.output {
+            FrameOutput::Images => {
+                let path = self.directory.join(format!("frame_{:06}.png", self.frames));
+                image.save_png(&path)?;
+            }
+            FrameOutput::Encoder { command, process } => {
+                if process.is_none() {
+                    *process = Some(spawn_encoder(
+                        command,
+                        &self.directory,
+                        size,
+                        self.frame_rate,
+                    )?);
+                }
+                let stdin = process.as_mut().and_then(|process| process.stdin.as_mut());
+                if let Some(stdin) = stdin {
+                    stdin.write_all(&image.pixels)?;
+                }
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Ends the recording, waiting for the encoder to finish writing the
+    /// video if there is one.
+    pub fn finish(self) -> io::Result<()> {
+        if let FrameOutput::Encoder {
+            process: Some(mut process),
+            ..
+        } = self.output
+        {
+            // Closing its input tells the encoder there are no more frames.
+            drop(process.stdin.take());
+            let status = process.wait()?;
+            if !status.success() {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("the encoder exited with {}", status),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Starts the encoder in `directory`, so that a relative output file ends
+/// up next to where the frames would have gone.
+fn spawn_encoder(
+    command: &str,
+    directory: &Path,
+    (width, height): (u32, u32),
+    frame_rate: u32,
+) -> io::Result<Child> {
+    let mut parts = command.split_whitespace().map(|part| {
+        part.replace("{width}", &width.to_string())
+            .replace("{height}", &height.to_string())
+            .replace("{fps}", &frame_rate.to_string())
+    });
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no encoder given"))?;
+    Command::new(program)
+        .args(parts)
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .spawn()
+}
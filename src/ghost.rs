@@ -0,0 +1,289 @@
+use crate::block::{Block, Direction};
+use crate::render_mesher::Mesh;
+use crate::render_text::{Anchor, TextRenderer};
+use crate::schematic::Schematic;
+use crate::view_filter::ViewFilter;
+use crate::world::{BlockPos, SectionPos, World, SECTION_SIZE};
+use std::collections::{HashMap, HashSet};
+
+const MATCHES_COLOR: [f32; 4] = [0.3, 1.0, 0.4, 0.15];
+const MISSING_COLOR: [f32; 4] = [0.3, 0.7, 1.0, 0.45];
+const DIFFERS_COLOR: [f32; 4] = [1.0, 0.25, 0.2, 0.55];
+
+// Grown a little so ghost faces sit in front of the faces of the real
+// blocks they cover.
+const GROW: f32 = 0.005;
+// Drawn at full light, so that the ghost reads the same indoors.
+const LIGHT: (u8, u8) = (15, 15);
+
+// Properties that a working circuit changes by itself, which shouldn't
+// count as the world differing from the design.
+const SIGNAL_PROPERTIES: [&str; 6] = ["power", "powered", "lit", "locked", "triggered", "extended"];
+
+// A section along with a one block border, so that faces against the
+// neighbouring sections can be left out too.
+const BORDERED_SIZE: i32 = SECTION_SIZE + 2;
+
+/// How a block of the real world compares with the ghost's block there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GhostStatus {
+    Matches,
+    /// The ghost has a block where the world has air.
+    Missing,
+    /// The world has a different block, or a block where the ghost has
+    /// air.
+    Differs,
+}
+
+impl GhostStatus {
+    fn color(self) -> [f32; 4] {
+        match self {
+            GhostStatus::Matches => MATCHES_COLOR,
+            GhostStatus::Missing => MISSING_COLOR,
+            GhostStatus::Differs => DIFFERS_COLOR,
+        }
+    }
+}
+
+/// A schematic laid over the world as a guide for building it, like a
+/// Litematica placement. Blocks are coloured by whether the world matches
+/// the design yet. The ghost is meshed a section at a time, so that a
+/// change to the world only compares the sections around it again.
+pub struct Ghost {
+    schematic: Schematic,
+    origin: BlockPos,
+    visible: bool,
+    // Sections whose part of the ghost needs comparing with the world
+    // again.
+    dirty_sections: HashSet<SectionPos>,
+    // How many blocks are missing and differ in each section, as of its
+    // last comparison.
+    counts: HashMap<SectionPos, (usize, usize)>,
+}
+
+impl Ghost {
+    /// Places a schematic with its lowest corner at `origin`.
+    pub fn new(schematic: Schematic, origin: BlockPos) -> Ghost {
+        let mut ghost = Ghost {
+            schematic,
+            origin,
+            visible: true,
+            dirty_sections: HashSet::new(),
+            counts: HashMap::new(),
+        };
+        ghost.mark_dirty();
+        ghost
+    }
+
+    pub fn set_origin(&mut self, origin: BlockPos) {
+        // The sections it is moving away from need clearing.
+        self.mark_dirty();
+        self.origin = origin;
+        self.mark_dirty();
+    }
+
+    pub fn toggle_visible(&mut self) {
+        self.visible = !self.visible;
+        self.mark_dirty();
+    }
+
+    /// The lowest and highest corners covered by the ghost.
+    pub fn bounds(&self) -> (BlockPos, BlockPos) {
+        let max = self.origin.offset(
+            self.schematic.width - 1,
+            self.schematic.height - 1,
+            self.schematic.length - 1,
+        );
+        (self.origin, max)
+    }
+
+    /// Whether any of the ghost lies in a section.
+    fn covers(&self, pos: SectionPos) -> bool {
+        let (min, max) = self.bounds();
+        let (min, max) = (min.section_pos(), max.section_pos());
+        pos.x >= min.x
+            && pos.x <= max.x
+            && pos.y >= min.y
+            && pos.y <= max.y
+            && pos.z >= min.z
+            && pos.z <= max.z
+    }
+
+    /// Makes the whole ghost be compared again, e.g. after the view filter
+    /// changes.
+    pub fn mark_dirty(&mut self) {
+        let (min, max) = self.bounds();
+        let (min, max) = (min.section_pos(), max.section_pos());
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    self.dirty_sections.insert(SectionPos::new(x, y, z));
+                }
+            }
+        }
+    }
+
+    /// Notes which sections of the world have changed, so that the parts
+    /// of the ghost in them are compared again.
+    pub fn mark_sections_dirty(&mut self, sections: &[SectionPos]) {
+        for pos in sections {
+            if self.covers(*pos) {
+                self.dirty_sections.insert(*pos);
+            }
+        }
+    }
+
+    /// How the world compares with the ghost at a position, or None where
+    /// both are air, the position is outside of the ghost or x-ray hides
+    /// the ghost's block. Slicing clips the ghost along with the world
+    /// when it is drawn.
+    fn status(&self, world: &World, filter: &ViewFilter, pos: BlockPos) -> Option<GhostStatus> {
+        let (x, y, z) = (
+            pos.x - self.origin.x,
+            pos.y - self.origin.y,
+            pos.z - self.origin.z,
+        );
+        if x < 0
+            || y < 0
+            || z < 0
+            || x >= self.schematic.width
+            || y >= self.schematic.height
+            || z >= self.schematic.length
+        {
+            return None;
+        }
+        let planned = self.schematic.get_block(x, y, z);
+        if filter.hides_type(planned) {
+            return None;
+        }
+        let actual = world.get_block(pos);
+        if planned.is_air() && actual.is_air() {
+            None
+        } else if actual.is_air() {
+            Some(GhostStatus::Missing)
+        } else if same_design(planned, actual) {
+            Some(GhostStatus::Matches)
+        } else {
+            Some(GhostStatus::Differs)
+        }
+    }
+
+    /// Compares the dirty sections of the ghost with the world again,
+    /// returning the new mesh for each of them. The meshes are empty while
+    /// the ghost is hidden, and leave out whatever x-ray hides.
+    pub fn update(&mut self, world: &World, filter: &ViewFilter) -> Vec<(SectionPos, Mesh)> {
+        let dirty: Vec<SectionPos> = self.dirty_sections.drain().collect();
+        let mut meshes = Vec::with_capacity(dirty.len());
+        for pos in dirty {
+            let mesh = if self.visible && self.covers(pos) {
+                self.build_section(world, filter, pos)
+            } else {
+                self.counts.remove(&pos);
+                Mesh::new()
+            };
+            meshes.push((pos, mesh));
+        }
+        meshes
+    }
+
+    fn build_section(&mut self, world: &World, filter: &ViewFilter, pos: SectionPos) -> Mesh {
+        let origin = pos.origin();
+        let mut statuses =
+            Vec::with_capacity((BORDERED_SIZE * BORDERED_SIZE * BORDERED_SIZE) as usize);
+        for y in -1..=SECTION_SIZE {
+            for z in -1..=SECTION_SIZE {
+                for x in -1..=SECTION_SIZE {
+                    statuses.push(self.status(world, filter, origin.offset(x, y, z)));
+                }
+            }
+        }
+        let status_at = |x: i32, y: i32, z: i32| {
+            statuses[(((y + 1) * BORDERED_SIZE + (z + 1)) * BORDERED_SIZE + (x + 1)) as usize]
+        };
+
+        let mut mesh = Mesh::new();
+        let (mut missing, mut differs) = (0, 0);
+        for y in 0..SECTION_SIZE {
+            for z in 0..SECTION_SIZE {
+                for x in 0..SECTION_SIZE {
+                    let status = match status_at(x, y, z) {
+                        Some(status) => status,
+                        None => continue,
+                    };
+                    match status {
+                        GhostStatus::Matches => {}
+                        GhostStatus::Missing => missing += 1,
+                        GhostStatus::Differs => differs += 1,
+                    }
+
+                    // Faces between blocks of the same status are left out,
+                    // so a run of them reads as one shape.
+                    let mut skip = [false; 6];
+                    for (i, direction) in Direction::ALL.iter().enumerate() {
+                        let (dx, dy, dz) = direction.offset();
+                        skip[i] = status_at(x + dx, y + dy, z + dz) == Some(status);
+                    }
+                    let pos = origin.offset(x, y, z);
+                    let min = [pos.x as f32, pos.y as f32, pos.z as f32];
+                    mesh.push_box(
+                        [min[0] - GROW, min[1] - GROW, min[2] - GROW],
+                        [
+                            min[0] + 1.0 + GROW,
+                            min[1] + 1.0 + GROW,
+                            min[2] + 1.0 + GROW,
+                        ],
+                        status.color(),
+                        LIGHT,
+                        skip,
+                    );
+                }
+            }
+        }
+        self.counts.insert(pos, (missing, differs));
+        mesh
+    }
+
+    /// Queues how much of the ghost is left to build in the bottom right
+    /// of the screen.
+    pub fn queue_info(&self, text: &mut TextRenderer, screen_width: u32, screen_height: u32) {
+        if !self.visible {
+            return;
+        }
+        let (missing, differs) = self
+            .counts
+            .values()
+            .fold((0, 0), |(missing, differs), counts| {
+                (missing + counts.0, differs + counts.1)
+            });
+        let line = format!("Ghost: {} missing, {} differ", missing, differs);
+        text.queue_panel(
+            &[(line, [1.0, 1.0, 1.0, 1.0])],
+            Anchor::BottomRight {
+                screen_width,
+                screen_height,
+            },
+        );
+    }
+}
+
+/// Whether a block in the world is the block the design asks for, not
+/// counting whether it is powered.
+fn same_design(planned: Block, actual: Block) -> bool {
+    if planned == actual {
+        return true;
+    }
+    let (planned_name, planned_properties) = planned.name_and_properties();
+    let (actual_name, actual_properties) = actual.name_and_properties();
+    if planned_name != actual_name {
+        return false;
+    }
+    let design = |properties: Vec<(String, String)>| {
+        let mut properties: Vec<_> = properties
+            .into_iter()
+            .filter(|(key, _)| !SIGNAL_PROPERTIES.contains(&key.as_str()))
+            .collect();
+        properties.sort();
+        properties
+    };
+    design(planned_properties) == design(actual_properties)
+}
@@ -0,0 +1,279 @@
+use crate::anvil::AnvilSource;
+use crate::arguments::parse_list;
+use crate::block_states::{require_block_states, BlockStates};
+use crate::chunk_manager::ChunkSource;
+#[cfg(target_os = "linux")]
+use crate::egl;
+use crate::entity::EntityRegistry;
+use crate::packet::ClientboundPacket;
+use crate::render::Renderer;
+use crate::render_framebuffer::Framebuffer;
+use crate::replay::{replay_player_packets, ReplayEvent, ReplayPlayer, REPLAY_PLAYER_ID};
+use crate::schematic::{Schematic, SchematicSource};
+use crate::screenshot::Image;
+use crate::window::{invalid_setting, unset_setting};
+use crate::world::{BlockPos, ChunkPos, World, SECTION_SIZE};
+use nalgebra::Vector3;
+use std::convert::TryFrom;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+Usage: mchprc render [options]
+
+Renders an image without a window, through a surfaceless EGL context, so
+no X11 or Wayland display is needed. Mesa falls back to rendering in
+software on machines without a GPU. Only available on Linux.
+
+Options:
+    --output <file>           PNG file to write [default: render.png]
+    --size <width>x<height>   Size of the image [default: 1920x1080]
+    --position <x>,<y>,<z>    Where the camera is [default: 0,0,3]
+    --rotation <pitch>,<yaw>  Which way the camera faces, in degrees as
+                              shown by Minecraft [default: 0,180]
+    --schematic <file>        Schematic to render
+    --origin <x>,<y>,<z>      Where the schematic's lowest corner goes
+                              [default: 0,0,0]
+    --world <directory>       Saved world to render the chunks around the
+                              camera from
+    --replay <file>           Replay to take the scene from
+    --time <seconds>          How far into the replay to render
+                              [default: the end]";
+
+/// Where a render is taken from and how big it is.
+pub struct RenderView {
+    pub position: Vector3<f32>,
+    /// Pitch and yaw in Minecraft's conventions.
+    pub pitch: f32,
+    pub yaw: f32,
+    pub width: u32,
+    pub height: u32,
+}
+
+struct RenderOptions {
+    output: PathBuf,
+    view: RenderView,
+    schematic: Option<PathBuf>,
+    origin: BlockPos,
+    world: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    time: Option<f32>,
+}
+
+impl RenderOptions {
+    fn parse(args: &[String]) -> Result<RenderOptions, String> {
+        let mut options = RenderOptions {
+            output: PathBuf::from("render.png"),
+            view: RenderView {
+                position: Vector3::new(0.0, 0.0, 3.0),
+                pitch: 0.0,
+                yaw: 180.0,
+                width: 1920,
+                height: 1080,
+            },
+            schematic: None,
+            origin: BlockPos::new(0, 0, 0),
+            world: None,
+            replay: None,
+            time: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(option) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value after '{}'", option))
+            };
+            match option.as_str() {
+                "--output" => options.output = PathBuf::from(value()?),
+                "--size" => {
+                    let value = value()?;
+                    let size = parse_list::<u32>(value, 'x', 2)?;
+                    if size.contains(&0) {
+                        return Err(format!("Invalid size '{}'", value));
+                    }
+                    options.view.width = size[0];
+                    options.view.height = size[1];
+                }
+                "--position" => {
+                    let position = parse_list::<f32>(value()?, ',', 3)?;
+                    options.view.position = Vector3::new(position[0], position[1], position[2]);
+                }
+                "--rotation" => {
+                    let rotation = parse_list::<f32>(value()?, ',', 2)?;
+                    options.view.pitch = rotation[0];
+                    options.view.yaw = rotation[1];
+                }
+                "--schematic" => options.schematic = Some(PathBuf::from(value()?)),
+                "--origin" => {
+                    let origin = parse_list::<i32>(value()?, ',', 3)?;
+                    options.origin = BlockPos::new(origin[0], origin[1], origin[2]);
+                }
+                "--world" => options.world = Some(PathBuf::from(value()?)),
+                "--replay" => options.replay = Some(PathBuf::from(value()?)),
+                "--time" => options.time = Some(parse_list::<f32>(value()?, ',', 1)?[0]),
+                _ => return Err(format!("Unknown option '{}'", option)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// A GL context with no window or display server behind it. Everything
+/// gets drawn to an offscreen framebuffer.
+pub struct HeadlessContext {
+    #[cfg(target_os = "linux")]
+    _context: egl::Context,
+}
+
+impl HeadlessContext {
+    /// Makes the context current and loads the GL functions through it.
+    #[cfg(target_os = "linux")]
+    pub fn create() -> Result<HeadlessContext, String> {
+        let context = egl::Context::create_surfaceless(3, 2)?;
+        gl::load_with(egl::get_proc_address);
+        Ok(HeadlessContext { _context: context })
+    }
+
+    /// Surfaceless contexts come from Mesa's EGL, which is only linked in
+    /// on Linux so that other builds don't need it.
+    #[cfg(not(target_os = "linux"))]
+    pub fn create() -> Result<HeadlessContext, String> {
+        Err("EGL is only available on Linux".to_owned())
+    }
+}
+
+/// Renders the world and entities from the view's camera position,
+/// looking the way given by its Minecraft pitch and yaw.
+pub fn render_world(
+    renderer: &mut Renderer,
+    world: &mut World,
+    entities: &EntityRegistry,
+    view: &RenderView,
+) -> io::Result<Image> {
+    for pos in world.take_dirty_sections() {
+        renderer.queue_section_mesh(world.snapshot_section(pos));
+    }
+
+    let camera_spatial = renderer.camera.borrow_spatial_mut();
+    camera_spatial.set_translation(&view.position);
+    camera_spatial.set_rotation(&Vector3::new(-view.pitch, 180.0 - view.yaw, 0.0));
+
+    renderer.render_offscreen(entities, view.width, view.height)
+}
+
+/// Puts the world and entities into the state they were in `time` seconds
+/// into a replay, or at its end.
+fn load_replay(
+    path: &Path,
+    time: Option<f32>,
+    block_states: &BlockStates,
+    world: &mut World,
+    entities: &mut EntityRegistry,
+) {
+    let mut replay = ReplayPlayer::open(path).expect("Unable to open replay file");
+    replay.seek(time.unwrap_or_else(|| replay.duration()));
+
+    for event in replay.advance(0.0) {
+        match event {
+            ReplayEvent::Packet(raw) => {
+                if let Some(packet) = ClientboundPacket::decode(&raw) {
+                    world.handle_packet(&packet, block_states);
+                    entities.handle_packet(&packet);
+                }
+            }
+            ReplayEvent::Player {
+                position,
+                pitch,
+                yaw,
+            } => {
+                let spawned = entities.get(REPLAY_PLAYER_ID).is_some();
+                for packet in replay_player_packets(&position, pitch, yaw, spawned).iter() {
+                    entities.handle_packet(packet);
+                }
+            }
+        }
+    }
+
+    // Skip to the end of any movement still being smoothed out.
+    entities.update(1.0);
+}
+
+/// The `render` subcommand.
+pub fn run(args: &[String]) {
+    let options = match RenderOptions::parse(args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut config = config::Config::default();
+    config
+        .merge(config::File::with_name("Config"))
+        .expect("Unable to load Config.toml");
+
+    let _context = HeadlessContext::create().unwrap_or_else(|error| {
+        eprintln!("Unable to create a surfaceless EGL context: {}", error);
+        process::exit(1);
+    });
+    // The size can only be checked against what the GPU supports once
+    // there is a context to ask.
+    let max_size = Framebuffer::max_size();
+    if options.view.width > max_size || options.view.height > max_size {
+        eprintln!(
+            "Invalid size '{}x{}', the largest supported is {}x{}\n\n{}",
+            options.view.width, options.view.height, max_size, max_size, USAGE
+        );
+        process::exit(2);
+    }
+    let mut renderer = Renderer::new(options.view.width, options.view.height, &config);
+
+    let mut world = World::new();
+    if let Some(path) = &options.schematic {
+        let schematic = Schematic::load(path).expect("Unable to load schematic");
+        let mut source = SchematicSource::new(schematic, options.origin);
+        for pos in source.chunk_positions() {
+            if let Some(chunk) = source.load_chunk(pos) {
+                world.insert_chunk(pos, chunk);
+            }
+        }
+    }
+    if let Some(path) = &options.world {
+        let mut source = AnvilSource::open(path).expect("Unable to open world");
+        let render_distance = config
+            .get_int("render_distance")
+            .expect(unset_setting("render_distance").as_ref());
+        let render_distance =
+            i32::try_from(render_distance).expect(invalid_setting("render_distance").as_ref());
+        let center = ChunkPos::new(
+            (options.view.position.x.floor() as i32).div_euclid(SECTION_SIZE),
+            (options.view.position.z.floor() as i32).div_euclid(SECTION_SIZE),
+        );
+        for x in -render_distance..=render_distance {
+            for z in -render_distance..=render_distance {
+                let pos = ChunkPos::new(center.x + x, center.z + z);
+                if let Some(chunk) = source.load_chunk(pos) {
+                    world.insert_chunk(pos, chunk);
+                }
+            }
+        }
+    }
+    let mut entities = EntityRegistry::new();
+    if let Some(path) = &options.replay {
+        let block_states = require_block_states(&config);
+        load_replay(path, options.time, &block_states, &mut world, &mut entities);
+    }
+
+    let image =
+        render_world(&mut renderer, &mut world, &entities, &options.view).unwrap_or_else(|error| {
+            eprintln!("Unable to render: {}", error);
+            process::exit(1);
+        });
+    image
+        .save_png(&options.output)
+        .expect("Unable to save the rendered image");
+    println!("Rendered {}", options.output.display());
+}
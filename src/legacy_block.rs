@@ -0,0 +1,404 @@
+use crate::block::Block;
+
+// The sixteen colours of wool, concrete and the like, by data value.
+const COLORS: [&str; 16] = [
+    "white",
+    "orange",
+    "magenta",
+    "light_blue",
+    "yellow",
+    "lime",
+    "pink",
+    "gray",
+    "light_gray",
+    "cyan",
+    "purple",
+    "blue",
+    "brown",
+    "green",
+    "red",
+    "black",
+];
+
+const WOODS: [&str; 6] = ["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+
+/// Converts a pre-1.13 numeric block id and data value into the block it
+/// became in 1.16. Blocks missing from the table are kept under their
+/// numbers, so that they can still be told apart.
+pub fn legacy_block(id: u16, data: u8) -> Block {
+    match (id, data) {
+        (0, _) => Block::Air,
+        (8, _) | (9, _) => Block::Water,
+        (55, power) => Block::RedstoneWire { power },
+        (123, _) => Block::RedstoneLamp { lit: false },
+        (124, _) => Block::RedstoneLamp { lit: true },
+        (152, _) => Block::RedstoneBlock,
+        _ => match legacy_state(id, data) {
+            Some(state) => Block::from_state(&state),
+            None => Block::from_state(&format!("legacy:{}:{}", id, data)),
+        },
+    }
+}
+
+/// The 1.16 block state of a legacy block, for the redstone components and
+/// the common building blocks.
+fn legacy_state(id: u16, data: u8) -> Option<String> {
+    let color = COLORS[data as usize & 15];
+    let powered = data & 8 != 0;
+    let state = match id {
+        1 => match data {
+            0 => "stone",
+            1 => "granite",
+            2 => "polished_granite",
+            3 => "diorite",
+            4 => "polished_diorite",
+            5 => "andesite",
+            6 => "polished_andesite",
+            _ => return None,
+        }
+        .to_owned(),
+        2 => "grass_block[snowy=false]".to_owned(),
+        3 => match data {
+            0 => "dirt",
+            1 => "coarse_dirt",
+            2 => "podzol[snowy=false]",
+            _ => return None,
+        }
+        .to_owned(),
+        4 => "cobblestone".to_owned(),
+        5 => format!("{}_planks", WOODS.get(data as usize)?),
+        7 => "bedrock".to_owned(),
+        12 => if data == 1 { "red_sand" } else { "sand" }.to_owned(),
+        13 => "gravel".to_owned(),
+        17 => log(WOODS[data as usize & 3], data >> 2),
+        162 => log(WOODS.get(4 + (data as usize & 3))?, data >> 2),
+        18 => leaves(WOODS[data as usize & 3]),
+        161 => leaves(WOODS.get(4 + (data as usize & 3))?),
+        20 => "glass".to_owned(),
+        22 => "lapis_block".to_owned(),
+        23 => format!("dispenser[facing={},triggered={}]", facing(data)?, powered),
+        158 => format!("dropper[facing={},triggered={}]", facing(data)?, powered),
+        24 => match data {
+            0 => "sandstone",
+            1 => "chiseled_sandstone",
+            2 => "cut_sandstone",
+            _ => return None,
+        }
+        .to_owned(),
+        25 => "note_block[instrument=harp,note=0,powered=false]".to_owned(),
+        29 => format!(
+            "sticky_piston[extended={},facing={}]",
+            powered,
+            facing(data)?
+        ),
+        33 => format!("piston[extended={},facing={}]", powered, facing(data)?),
+        34 => format!(
+            "piston_head[facing={},short=false,type={}]",
+            facing(data)?,
+            if powered { "sticky" } else { "normal" }
+        ),
+        35 => format!("{}_wool", color),
+        41 => "gold_block".to_owned(),
+        42 => "iron_block".to_owned(),
+        43 => format!("{}[type=double,waterlogged=false]", slab(data & 7)),
+        44 => format!(
+            "{}[type={},waterlogged=false]",
+            slab(data & 7),
+            if powered { "top" } else { "bottom" }
+        ),
+        45 => "bricks".to_owned(),
+        46 => "tnt[unstable=false]".to_owned(),
+        49 => "obsidian".to_owned(),
+        50 => match wall_facing(data) {
+            Some(facing) => format!("wall_torch[facing={}]", facing),
+            None => "torch".to_owned(),
+        },
+        57 => "diamond_block".to_owned(),
+        69 => {
+            // Levers on the floor and ceiling point along one of two axes.
+            let (face, facing) = match data & 7 {
+                0 => ("ceiling", "west"),
+                5 => ("floor", "north"),
+                6 => ("floor", "west"),
+                7 => ("ceiling", "north"),
+                side => ("wall", wall_facing(side)?),
+            };
+            format!("lever[face={},facing={},powered={}]", face, facing, powered)
+        }
+        70 => format!("stone_pressure_plate[powered={}]", data & 1 != 0),
+        72 => format!("oak_pressure_plate[powered={}]", data & 1 != 0),
+        147 => format!("light_weighted_pressure_plate[power={}]", data),
+        148 => format!("heavy_weighted_pressure_plate[power={}]", data),
+        73 => "redstone_ore[lit=false]".to_owned(),
+        74 => "redstone_ore[lit=true]".to_owned(),
+        75 | 76 => {
+            let lit = id == 76;
+            match wall_facing(data) {
+                Some(facing) => format!("redstone_wall_torch[facing={},lit={}]", facing, lit),
+                None => format!("redstone_torch[lit={}]", lit),
+            }
+        }
+        77 | 143 => {
+            let name = if id == 77 {
+                "stone_button"
+            } else {
+                "oak_button"
+            };
+            let (face, facing) = match data & 7 {
+                0 => ("ceiling", "north"),
+                5 => ("floor", "north"),
+                side => ("wall", wall_facing(side)?),
+            };
+            format!(
+                "{}[face={},facing={},powered={}]",
+                name, face, facing, powered
+            )
+        }
+        80 => "snow_block".to_owned(),
+        82 => "clay".to_owned(),
+        87 => "netherrack".to_owned(),
+        88 => "soul_sand".to_owned(),
+        89 => "glowstone".to_owned(),
+        93 | 94 => format!(
+            "repeater[delay={},facing={},locked=false,powered={}]",
+            (data >> 2) + 1,
+            horizontal_facing(data),
+            id == 94
+        ),
+        149 | 150 => format!(
+            "comparator[facing={},mode={},powered={}]",
+            horizontal_facing(data),
+            if data & 4 != 0 { "subtract" } else { "compare" },
+            id == 150 || powered
+        ),
+        95 => format!("{}_stained_glass", color),
+        98 => match data {
+            0 => "stone_bricks",
+            1 => "mossy_stone_bricks",
+            2 => "cracked_stone_bricks",
+            3 => "chiseled_stone_bricks",
+            _ => return None,
+        }
+        .to_owned(),
+        112 => "nether_bricks".to_owned(),
+        121 => "end_stone".to_owned(),
+        133 => "emerald_block".to_owned(),
+        151 | 178 => format!("daylight_detector[inverted={},power={}]", id == 178, data),
+        154 => format!(
+            "hopper[enabled={},facing={}]",
+            !powered,
+            match facing(data)? {
+                "up" => return None,
+                facing => facing,
+            }
+        ),
+        155 => match data {
+            0 => "quartz_block",
+            1 => "chiseled_quartz_block",
+            2 => "quartz_pillar[axis=y]",
+            3 => "quartz_pillar[axis=x]",
+            4 => "quartz_pillar[axis=z]",
+            _ => return None,
+        }
+        .to_owned(),
+        159 => format!("{}_terracotta", color),
+        165 => "slime_block".to_owned(),
+        168 => match data {
+            0 => "prismarine",
+            1 => "prismarine_bricks",
+            2 => "dark_prismarine",
+            _ => return None,
+        }
+        .to_owned(),
+        169 => "sea_lantern".to_owned(),
+        171 => format!("{}_carpet", color),
+        172 => "terracotta".to_owned(),
+        173 => "coal_block".to_owned(),
+        201 => "purpur_block".to_owned(),
+        214 => "nether_wart_block".to_owned(),
+        215 => "red_nether_bricks".to_owned(),
+        218 => format!("observer[facing={},powered={}]", facing(data)?, powered),
+        235..=250 => format!(
+            "{}_glazed_terracotta[facing={}]",
+            COLORS[id as usize - 235],
+            horizontal_facing(data)
+        ),
+        251 => format!("{}_concrete", color),
+        252 => format!("{}_concrete_powder", color),
+        _ => return None,
+    };
+    Some(state)
+}
+
+/// The direction of blocks that can face any way, such as pistons, kept in
+/// the low three bits.
+fn facing(data: u8) -> Option<&'static str> {
+    match data & 7 {
+        0 => Some("down"),
+        1 => Some("up"),
+        2 => Some("north"),
+        3 => Some("south"),
+        4 => Some("west"),
+        5 => Some("east"),
+        _ => None,
+    }
+}
+
+/// The direction of diodes and other blocks that only face sideways, kept
+/// in the low two bits.
+fn horizontal_facing(data: u8) -> &'static str {
+    ["south", "west", "north", "east"][data as usize & 3]
+}
+
+/// Which way a torch, lever or button on a wall points, away from the
+/// block it hangs on, or None if it isn't on a wall.
+fn wall_facing(data: u8) -> Option<&'static str> {
+    match data & 7 {
+        1 => Some("east"),
+        2 => Some("west"),
+        3 => Some("south"),
+        4 => Some("north"),
+        _ => None,
+    }
+}
+
+fn log(wood: &str, axis: u8) -> String {
+    match axis {
+        0 => format!("{}_log[axis=y]", wood),
+        1 => format!("{}_log[axis=x]", wood),
+        2 => format!("{}_log[axis=z]", wood),
+        _ => format!("{}_wood[axis=y]", wood),
+    }
+}
+
+fn leaves(wood: &str) -> String {
+    format!("{}_leaves[distance=7,persistent=true]", wood)
+}
+
+fn slab(material: u8) -> &'static str {
+    [
+        "smooth_stone_slab",
+        "sandstone_slab",
+        "petrified_oak_slab",
+        "cobblestone_slab",
+        "brick_slab",
+        "stone_brick_slab",
+        "nether_brick_slab",
+        "quartz_slab",
+    ][material as usize & 7]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_state(id: u16, data: u8, state: &str) {
+        assert_eq!(
+            legacy_block(id, data),
+            Block::from_state(state),
+            "{}:{} should be {}",
+            id,
+            data,
+            state
+        );
+    }
+
+    #[test]
+    fn torches_stand_or_hang_on_walls() {
+        assert_state(76, 5, "redstone_torch[lit=true]");
+        assert_state(75, 5, "redstone_torch[lit=false]");
+        assert_state(75, 1, "redstone_wall_torch[facing=east,lit=false]");
+        assert_state(76, 2, "redstone_wall_torch[facing=west,lit=true]");
+        assert_state(76, 3, "redstone_wall_torch[facing=south,lit=true]");
+        assert_state(76, 4, "redstone_wall_torch[facing=north,lit=true]");
+        assert_state(50, 3, "wall_torch[facing=south]");
+    }
+
+    #[test]
+    fn diodes_keep_their_facing_delay_and_mode() {
+        assert_state(
+            93,
+            0,
+            "repeater[delay=1,facing=south,locked=false,powered=false]",
+        );
+        assert_state(
+            94,
+            0b1101,
+            "repeater[delay=4,facing=west,locked=false,powered=true]",
+        );
+        assert_state(
+            149,
+            2,
+            "comparator[facing=north,mode=compare,powered=false]",
+        );
+        assert_state(
+            149,
+            0b1111,
+            "comparator[facing=east,mode=subtract,powered=true]",
+        );
+        assert_state(
+            150,
+            4,
+            "comparator[facing=south,mode=subtract,powered=true]",
+        );
+    }
+
+    #[test]
+    fn levers_and_buttons_keep_where_they_are_attached() {
+        assert_state(69, 1, "lever[face=wall,facing=east,powered=false]");
+        assert_state(69, 4 | 8, "lever[face=wall,facing=north,powered=true]");
+        assert_state(69, 5, "lever[face=floor,facing=north,powered=false]");
+        assert_state(69, 6, "lever[face=floor,facing=west,powered=false]");
+        assert_state(69, 8, "lever[face=ceiling,facing=west,powered=true]");
+        assert_state(69, 7, "lever[face=ceiling,facing=north,powered=false]");
+        assert_state(77, 2, "stone_button[face=wall,facing=west,powered=false]");
+        assert_state(
+            77,
+            5 | 8,
+            "stone_button[face=floor,facing=north,powered=true]",
+        );
+        assert_state(
+            143,
+            0,
+            "oak_button[face=ceiling,facing=north,powered=false]",
+        );
+        assert_state(143, 3, "oak_button[face=wall,facing=south,powered=false]");
+    }
+
+    #[test]
+    fn pistons_and_observers_face_every_way() {
+        assert_state(33, 1, "piston[extended=false,facing=up]");
+        assert_state(29, 5 | 8, "sticky_piston[extended=true,facing=east]");
+        assert_state(
+            34,
+            2 | 8,
+            "piston_head[facing=north,short=false,type=sticky]",
+        );
+        assert_state(218, 0, "observer[facing=down,powered=false]");
+        assert_state(218, 4 | 8, "observer[facing=west,powered=true]");
+        assert_eq!(legacy_block(33, 6), Block::from_state("legacy:33:6"));
+    }
+
+    #[test]
+    fn coloured_blocks_take_their_colour_from_the_data() {
+        assert_state(35, 0, "white_wool");
+        assert_state(35, 14, "red_wool");
+        assert_state(251, 15, "black_concrete");
+        assert_state(252, 3, "light_blue_concrete_powder");
+        assert_state(159, 8, "light_gray_terracotta");
+        assert_state(95, 11, "blue_stained_glass");
+        assert_state(171, 5, "lime_carpet");
+        assert_state(249, 2, "red_glazed_terracotta[facing=north]");
+    }
+
+    #[test]
+    fn modelled_blocks_keep_their_own_variants() {
+        assert_eq!(legacy_block(1, 0), Block::Stone);
+        assert_eq!(legacy_block(20, 0), Block::Glass);
+        assert_eq!(legacy_block(9, 0), Block::Water);
+        assert_eq!(legacy_block(55, 7), Block::RedstoneWire { power: 7 });
+        assert_eq!(legacy_block(124, 0), Block::RedstoneLamp { lit: true });
+        assert_eq!(legacy_block(152, 0), Block::RedstoneBlock);
+        assert_eq!(legacy_block(76, 5), Block::RedstoneTorch { lit: true });
+    }
+}
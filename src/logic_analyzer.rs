@@ -0,0 +1,260 @@
+use crate::block::Block;
+use crate::redstone::TICKS_PER_SECOND;
+use crate::render_text::{Anchor, TextRenderer, PANEL_SCALE};
+use crate::screenshot::unused_path;
+use crate::world::{BlockPos, World};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// How many ticks of history the panel shows, and how wide each one is.
+const PANEL_TICKS: usize = 200;
+const TICK_WIDTH: f32 = 2.0;
+const ROW_HEIGHT: f32 = 20.0;
+const LABEL_WIDTH: f32 = 120.0;
+
+const WAVE_COLOR: [f32; 4] = [0.3, 1.0, 0.4, 1.0];
+const UNKNOWN_COLOR: [f32; 4] = [0.5, 0.2, 0.2, 1.0];
+
+/// The signal strength shown by a block's state. Blocks that only show
+/// whether they are on count as 15 when they are.
+pub fn signal_level(block: Block) -> u8 {
+    match block {
+        Block::RedstoneWire { power } => power,
+        Block::RedstoneTorch { lit } | Block::RedstoneLamp { lit } => {
+            if lit {
+                15
+            } else {
+                0
+            }
+        }
+        Block::RedstoneBlock => 15,
+        Block::Other(_) => {
+            let (_, properties) = block.name_and_properties();
+            let mut level = 0;
+            for (key, value) in properties.iter() {
+                match key.as_str() {
+                    "power" => level = value.parse().unwrap_or(0),
+                    "powered" | "lit" | "extended" if value == "true" => level = 15,
+                    _ => {}
+                }
+            }
+            level
+        }
+        _ => 0,
+    }
+}
+
+/// A block being watched, with its level at every tick since it was
+/// added.
+struct Probe {
+    pos: BlockPos,
+    first_tick: u64,
+    levels: Vec<u8>,
+}
+
+impl Probe {
+    fn level_at(&self, tick: u64) -> Option<u8> {
+        if tick < self.first_tick {
+            return None;
+        }
+        self.levels.get((tick - self.first_tick) as usize).copied()
+    }
+}
+
+/// Records the signal levels of chosen blocks every redstone tick, to be
+/// looked at as waveforms or exported as a VCD file for GTKWave.
+pub struct LogicAnalyzer {
+    probes: Vec<Probe>,
+    tick: u64,
+    accumulator: f32,
+}
+
+impl LogicAnalyzer {
+    pub fn new() -> LogicAnalyzer {
+        LogicAnalyzer {
+            probes: Vec::new(),
+            tick: 0,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Starts watching a block, or stops if it was already being watched.
+    /// Returns whether the block is now being watched.
+    pub fn toggle_probe(&mut self, pos: BlockPos) -> bool {
+        if let Some(index) = self.probes.iter().position(|probe| probe.pos == pos) {
+            self.probes.remove(index);
+            return false;
+        }
+        self.probes.push(Probe {
+            pos,
+            first_tick: self.tick,
+            levels: Vec::new(),
+        });
+        true
+    }
+
+    /// Samples the world at the redstone tick rate, for when the world is
+    /// changed by a server rather than the local simulation.
+    pub fn update(&mut self, delta: f32, world: &World) {
+        self.accumulator += delta * TICKS_PER_SECOND;
+        while self.accumulator >= 1.0 {
+            self.accumulator -= 1.0;
+            self.sample(world);
+        }
+    }
+
+    /// Records the level of every probe for the current tick.
+    pub fn sample(&mut self, world: &World) {
+        for probe in self.probes.iter_mut() {
+            probe.levels.push(signal_level(world.get_block(probe.pos)));
+        }
+        self.tick += 1;
+    }
+
+    /// Queues a panel of waveforms scrolling along the bottom left of the
+    /// screen, newest ticks on the right.
+    pub fn queue_panel(&self, text: &mut TextRenderer, screen_height: u32) {
+        if self.probes.is_empty() {
+            return;
+        }
+
+        let line_height = TextRenderer::line_height(PANEL_SCALE);
+        let width = LABEL_WIDTH + PANEL_TICKS as f32 * TICK_WIDTH;
+        let height = line_height + ROW_HEIGHT * self.probes.len() as f32;
+        let (x, y) =
+            text.queue_panel_background(width, height, Anchor::BottomLeft { screen_height });
+        text.queue_text(
+            x,
+            y,
+            PANEL_SCALE,
+            [1.0, 1.0, 0.6, 1.0],
+            &format!("Logic analyzer, tick {}", self.tick),
+        );
+
+        let first_tick = self.tick.saturating_sub(PANEL_TICKS as u64);
+        let wave_x = x + LABEL_WIDTH;
+        for (i, probe) in self.probes.iter().enumerate() {
+            let row_y = y + line_height + ROW_HEIGHT * i as f32;
+            let current = probe
+                .levels
+                .last()
+                .map(|level| level.to_string())
+                .unwrap_or_else(|| "-".to_owned());
+            text.queue_text(
+                x,
+                row_y + (ROW_HEIGHT - line_height) / 2.0,
+                PANEL_SCALE,
+                [1.0, 1.0, 1.0, 1.0],
+                &format!(
+                    "{} {} {}: {}",
+                    probe.pos.x, probe.pos.y, probe.pos.z, current
+                ),
+            );
+
+            // Runs of the same level are drawn as one rectangle.
+            let mut tick = first_tick;
+            while tick < self.tick {
+                let level = probe.level_at(tick);
+                let mut end = tick + 1;
+                while end < self.tick && probe.level_at(end) == level {
+                    end += 1;
+                }
+                let left = wave_x + (tick - first_tick) as f32 * TICK_WIDTH;
+                let run_width = (end - tick) as f32 * TICK_WIDTH;
+                let bottom = row_y + ROW_HEIGHT - 2.0;
+                match level {
+                    Some(level) => {
+                        // Low signals still show a sliver, so that they
+                        // don't look the same as no signal.
+                        let level_height = (ROW_HEIGHT - 4.0) * level as f32 / 15.0;
+                        let level_height = level_height.max(1.0);
+                        text.queue_rect(
+                            left,
+                            bottom - level_height,
+                            run_width,
+                            level_height,
+                            WAVE_COLOR,
+                        );
+                    }
+                    None => text.queue_rect(left, bottom - 1.0, run_width, 1.0, UNKNOWN_COLOR),
+                }
+                tick = end;
+            }
+        }
+    }
+
+    /// Writes every probe's history as a value change dump, one time unit
+    /// per redstone tick. Levels are four bit vectors, and are unknown
+    /// before a probe was added.
+    pub fn write_vcd<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "$version {} {} $end",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(out, "$timescale 100 ms $end")?;
+        writeln!(out, "$scope module probes $end")?;
+        for (i, probe) in self.probes.iter().enumerate() {
+            writeln!(
+                out,
+                "$var wire 4 {} probe_{}_{}_{} $end",
+                vcd_identifier(i),
+                probe.pos.x,
+                probe.pos.y,
+                probe.pos.z
+            )?;
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+
+        let mut last: Vec<Option<Option<u8>>> = vec![None; self.probes.len()];
+        for tick in 0..self.tick {
+            let mut changes = Vec::new();
+            for (i, probe) in self.probes.iter().enumerate() {
+                let level = probe.level_at(tick);
+                if last[i] != Some(level) {
+                    last[i] = Some(level);
+                    let value = match level {
+                        Some(level) => format!("b{:b}", level),
+                        None => "bx".to_owned(),
+                    };
+                    changes.push(format!("{} {}", value, vcd_identifier(i)));
+                }
+            }
+            if !changes.is_empty() {
+                writeln!(out, "#{}", tick)?;
+                for change in changes {
+                    writeln!(out, "{}", change)?;
+                }
+            }
+        }
+        writeln!(out, "#{}", self.tick)?;
+        Ok(())
+    }
+
+    /// Saves the recording as a VCD file in `directory`, returning where it
+    /// went.
+    pub fn save_vcd(&self, directory: &Path) -> io::Result<PathBuf> {
+        let path = unused_path(directory, "vcd")?;
+        let mut out = BufWriter::new(File::create(&path)?);
+        self.write_vcd(&mut out)?;
+        out.flush()?;
+        Ok(path)
+    }
+}
+
+/// Short names for signals in a VCD file, made of printable ASCII.
+fn vcd_identifier(index: usize) -> String {
+    let mut index = index;
+    let mut identifier = String::new();
+    loop {
+        identifier.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return identifier;
+        }
+        index -= 1;
+    }
+}
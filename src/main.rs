@@ -1,12 +1,52 @@
+mod anvil;
+mod arguments;
+mod block;
+mod block_states;
+mod camera_control;
+mod camera_path;
+mod chunk_manager;
 mod client;
+#[cfg(target_os = "linux")]
+mod egl;
+mod entity;
+mod frame_recorder;
+mod ghost;
+mod headless;
+mod legacy_block;
+mod logic_analyzer;
+mod nbt;
+mod packet;
 mod player;
+mod player_list;
+mod redstone;
 mod render;
 mod render_camera;
+mod render_chunk;
+mod render_entity;
+mod render_framebuffer;
+mod render_lines;
+mod render_mesh_workers;
+mod render_mesher;
+mod render_shader;
+mod render_state;
+mod render_text;
+mod replay;
+mod schematic;
+mod screenshot;
+mod selection;
+mod signal_overlay;
 mod spatial;
+mod view_filter;
 mod window;
+mod world;
 
 use client::Client;
+use std::env;
 
 fn main() {
-    Client::run();
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("render") => headless::run(&args[2..]),
+        _ => Client::run(),
+    }
 }
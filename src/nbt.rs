@@ -0,0 +1,300 @@
+use crate::packet::{PacketReader, PacketWriter};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+// Deeper nesting than this is treated as malformed rather than risking
+// running out of stack.
+const MAX_DEPTH: usize = 512;
+
+/// A named binary tag, the format Minecraft stores worlds and schematics
+/// in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Looks up a child of a compound tag.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(children) => children.get(name),
+            _ => None,
+        }
+    }
+
+    /// Reads any integer tag as an i32, wrapping longs.
+    pub fn as_i32(&self) -> Option<i32> {
+        match *self {
+            Tag::Byte(value) => Some(value as i32),
+            Tag::Short(value) => Some(value as i32),
+            Tag::Int(value) => Some(value),
+            Tag::Long(value) => Some(value as i32),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Tag::ByteArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Tag::Compound(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads an NBT file, which may be gzip or zlib compressed, returning the
+/// name and value of its root tag.
+pub fn read_nbt_file(path: &Path) -> io::Result<(String, Tag)> {
+    read_nbt(&fs::read(path)?)
+}
+
+/// Reads NBT data, decompressing it first if it is gzip or zlib
+/// compressed.
+pub fn read_nbt(data: &[u8]) -> io::Result<(String, Tag)> {
+    let mut decompressed = Vec::new();
+    let data = match data {
+        [0x1f, 0x8b, ..] => {
+            GzDecoder::new(data).read_to_end(&mut decompressed)?;
+            &decompressed
+        }
+        [0x78, ..] => {
+            ZlibDecoder::new(data).read_to_end(&mut decompressed)?;
+            &decompressed
+        }
+        _ => data,
+    };
+
+    let mut reader = PacketReader::new(data);
+    let kind = reader
+        .read_u8()
+        .ok_or_else(|| invalid_data("empty NBT data"))?;
+    if kind != 10 {
+        return Err(invalid_data("NBT root is not a compound"));
+    }
+    read_named_payload(&mut reader, kind).ok_or_else(|| invalid_data("malformed NBT data"))
+}
+
+/// Reads an uncompressed NBT compound embedded in other data, such as the
+/// heightmaps of a chunk packet, leaving the reader just past it.
+pub fn read_tag(reader: &mut PacketReader) -> Option<(String, Tag)> {
+    let kind = reader.read_u8()?;
+    if kind != 10 {
+        return None;
+    }
+    read_named_payload(reader, kind)
+}
+
+fn read_named_payload(reader: &mut PacketReader, kind: u8) -> Option<(String, Tag)> {
+    let name = read_string(reader)?;
+    let tag = read_payload(reader, kind, 0)?;
+    Some((name, tag))
+}
+
+fn read_string(reader: &mut PacketReader) -> Option<String> {
+    let length = reader.read_i16()? as u16 as usize;
+    // Java's modified UTF-8 only differs for characters we don't care
+    // about, so anything unusual is replaced rather than rejected.
+    Some(String::from_utf8_lossy(reader.read_bytes(length)?).into_owned())
+}
+
+fn read_length(reader: &mut PacketReader) -> Option<usize> {
+    let length = reader.read_i32()?;
+    if length < 0 {
+        return None;
+    }
+    Some(length as usize)
+}
+
+fn read_payload(reader: &mut PacketReader, kind: u8, depth: usize) -> Option<Tag> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    let tag = match kind {
+        1 => Tag::Byte(reader.read_u8()? as i8),
+        2 => Tag::Short(reader.read_i16()?),
+        3 => Tag::Int(reader.read_i32()?),
+        4 => Tag::Long(reader.read_i64()?),
+        5 => Tag::Float(reader.read_f32()?),
+        6 => Tag::Double(reader.read_f64()?),
+        7 => {
+            let length = read_length(reader)?;
+            Tag::ByteArray(reader.read_bytes(length)?.to_vec())
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let element_kind = reader.read_u8()?;
+            let length = read_length(reader)?;
+            // Empty lists are allowed to have an element type of End.
+            if element_kind == 0 && length > 0 {
+                return None;
+            }
+            let mut elements = Vec::new();
+            for _ in 0..length {
+                elements.push(read_payload(reader, element_kind, depth + 1)?);
+            }
+            Tag::List(elements)
+        }
+        10 => {
+            let mut children = HashMap::new();
+            loop {
+                let child_kind = reader.read_u8()?;
+                if child_kind == 0 {
+                    break;
+                }
+                let name = read_string(reader)?;
+                children.insert(name, read_payload(reader, child_kind, depth + 1)?);
+            }
+            Tag::Compound(children)
+        }
+        11 => {
+            let length = read_length(reader)?;
+            let mut values = Vec::new();
+            for _ in 0..length {
+                values.push(reader.read_i32()?);
+            }
+            Tag::IntArray(values)
+        }
+        12 => {
+            let length = read_length(reader)?;
+            let mut values = Vec::new();
+            for _ in 0..length {
+                values.push(reader.read_i64()?);
+            }
+            Tag::LongArray(values)
+        }
+        _ => return None,
+    };
+    Some(tag)
+}
+
+/// Writes a compound as a gzip compressed NBT file, the way Minecraft
+/// saves schematics.
+pub fn write_nbt_file(path: &Path, name: &str, root: &Tag) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(File::create(path)?, Compression::default());
+    encoder.write_all(&write_nbt(name, root))?;
+    encoder.finish()?.flush()
+}
+
+/// Encodes a named root tag as uncompressed NBT.
+pub fn write_nbt(name: &str, root: &Tag) -> Vec<u8> {
+    let mut writer = PacketWriter::new();
+    writer.write_u8(root.kind());
+    write_string(&mut writer, name);
+    write_payload(&mut writer, root);
+    writer.finish()
+}
+
+fn write_string(writer: &mut PacketWriter, value: &str) {
+    writer.write_bytes(&(value.len() as u16).to_be_bytes());
+    writer.write_bytes(value.as_bytes());
+}
+
+fn write_payload(writer: &mut PacketWriter, tag: &Tag) {
+    match tag {
+        Tag::Byte(value) => writer.write_u8(*value as u8),
+        Tag::Short(value) => writer.write_bytes(&value.to_be_bytes()),
+        Tag::Int(value) => writer.write_bytes(&value.to_be_bytes()),
+        Tag::Long(value) => writer.write_bytes(&value.to_be_bytes()),
+        Tag::Float(value) => writer.write_f32(*value),
+        Tag::Double(value) => writer.write_bytes(&value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            writer.write_bytes(&(values.len() as i32).to_be_bytes());
+            writer.write_bytes(values);
+        }
+        Tag::String(value) => write_string(writer, value),
+        Tag::List(elements) => {
+            // Lists take the type of their first element, and empty ones
+            // are written as lists of End.
+            writer.write_u8(elements.first().map(Tag::kind).unwrap_or(0));
+            writer.write_bytes(&(elements.len() as i32).to_be_bytes());
+            for element in elements {
+                write_payload(writer, element);
+            }
+        }
+        Tag::Compound(children) => {
+            for (name, child) in children {
+                writer.write_u8(child.kind());
+                write_string(writer, name);
+                write_payload(writer, child);
+            }
+            writer.write_u8(0);
+        }
+        Tag::IntArray(values) => {
+            writer.write_bytes(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                writer.write_bytes(&value.to_be_bytes());
+            }
+        }
+        Tag::LongArray(values) => {
+            writer.write_bytes(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                writer.write_bytes(&value.to_be_bytes());
+            }
+        }
+    }
+}
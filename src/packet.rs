@@ -0,0 +1,858 @@
+use crate::nbt::{read_tag, Tag};
+use std::convert::TryInto;
+
+/// A packet as it comes off the connection: the packet id and the still
+/// encoded body.
+#[derive(Clone, Debug)]
+pub struct RawPacket {
+    pub id: i32,
+    pub data: Vec<u8>,
+}
+
+/// The clientbound play packets we understand. Packet ids are those of
+/// protocol version 754 (1.16.5), which MCHPRS speaks.
+#[derive(Clone, Debug)]
+pub enum ClientboundPacket {
+    SpawnEntity {
+        entity_id: i32,
+        uuid: u128,
+        entity_type: i32,
+        x: f64,
+        y: f64,
+        z: f64,
+        pitch: f32,
+        yaw: f32,
+    },
+    SpawnPlayer {
+        entity_id: i32,
+        uuid: u128,
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: f32,
+        pitch: f32,
+    },
+    EntityPosition {
+        entity_id: i32,
+        delta_x: f64,
+        delta_y: f64,
+        delta_z: f64,
+        on_ground: bool,
+    },
+    EntityPositionAndRotation {
+        entity_id: i32,
+        delta_x: f64,
+        delta_y: f64,
+        delta_z: f64,
+        yaw: f32,
+        pitch: f32,
+        on_ground: bool,
+    },
+    EntityRotation {
+        entity_id: i32,
+        yaw: f32,
+        pitch: f32,
+        on_ground: bool,
+    },
+    EntityHeadLook {
+        entity_id: i32,
+        head_yaw: f32,
+    },
+    EntityTeleport {
+        entity_id: i32,
+        x: f64,
+        y: f64,
+        z: f64,
+        yaw: f32,
+        pitch: f32,
+        on_ground: bool,
+    },
+    DestroyEntities {
+        entity_ids: Vec<i32>,
+    },
+    PlayerInfo {
+        actions: Vec<PlayerInfoAction>,
+    },
+    /// Sent on joining. Only our entity id is kept.
+    JoinGame {
+        entity_id: i32,
+    },
+    /// The blocks of a chunk. A full chunk replaces every section, where a
+    /// partial one only replaces the sections it carries. Block entities
+    /// are NBT compounds holding their world position.
+    ChunkData {
+        x: i32,
+        z: i32,
+        full: bool,
+        sections: Vec<SectionData>,
+        block_entities: Vec<Tag>,
+    },
+    UnloadChunk {
+        x: i32,
+        z: i32,
+    },
+    /// Light for the sections of a chunk, from the one below the world
+    /// (-1) to the one above it (16).
+    UpdateLight {
+        x: i32,
+        z: i32,
+        sky_light: Vec<SectionLight>,
+        block_light: Vec<SectionLight>,
+    },
+    BlockChange(BlockRecord),
+    MultiBlockChange {
+        records: Vec<BlockRecord>,
+    },
+}
+
+/// The blocks of one section of a chunk packet, as 4096 global block
+/// state ids indexed by `y << 8 | z << 4 | x`.
+#[derive(Clone, Debug)]
+pub struct SectionData {
+    pub y: i32,
+    pub states: Vec<i32>,
+}
+
+/// A nibble array of light levels for one section, as sent. The world
+/// checks that it is 2048 bytes before using it.
+#[derive(Clone, Debug)]
+pub struct SectionLight {
+    pub y: i32,
+    pub light: Vec<u8>,
+}
+
+/// A block changed to a global block state id.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockRecord {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    pub state: i32,
+}
+
+#[derive(Clone, Debug)]
+pub enum PlayerInfoAction {
+    AddPlayer {
+        uuid: u128,
+        name: String,
+        gamemode: i32,
+        ping: i32,
+        /// The display name as a JSON chat component.
+        display_name: Option<String>,
+    },
+    UpdateGamemode {
+        uuid: u128,
+        gamemode: i32,
+    },
+    UpdateLatency {
+        uuid: u128,
+        ping: i32,
+    },
+    UpdateDisplayName {
+        uuid: u128,
+        display_name: Option<String>,
+    },
+    RemovePlayer {
+        uuid: u128,
+    },
+}
+
+impl ClientboundPacket {
+    /// Decodes a raw packet. Returns None for packets we don't handle, or
+    /// that are malformed.
+    pub fn decode(raw: &RawPacket) -> Option<ClientboundPacket> {
+        let mut reader = PacketReader::new(&raw.data);
+        let packet = match raw.id {
+            0x00 => ClientboundPacket::SpawnEntity {
+                entity_id: reader.read_varint()?,
+                uuid: reader.read_uuid()?,
+                entity_type: reader.read_varint()?,
+                x: reader.read_f64()?,
+                y: reader.read_f64()?,
+                z: reader.read_f64()?,
+                pitch: reader.read_angle()?,
+                yaw: reader.read_angle()?,
+            },
+            0x04 => ClientboundPacket::SpawnPlayer {
+                entity_id: reader.read_varint()?,
+                uuid: reader.read_uuid()?,
+                x: reader.read_f64()?,
+                y: reader.read_f64()?,
+                z: reader.read_f64()?,
+                yaw: reader.read_angle()?,
+                pitch: reader.read_angle()?,
+            },
+            0x0B => {
+                let (x, y, z) = reader.read_position()?;
+                ClientboundPacket::BlockChange(BlockRecord {
+                    x,
+                    y,
+                    z,
+                    state: reader.read_varint()?,
+                })
+            }
+            0x1C => ClientboundPacket::UnloadChunk {
+                x: reader.read_i32()?,
+                z: reader.read_i32()?,
+            },
+            0x20 => read_chunk_data(&mut reader)?,
+            0x23 => read_update_light(&mut reader)?,
+            0x24 => ClientboundPacket::JoinGame {
+                entity_id: reader.read_i32()?,
+            },
+            0x27 => ClientboundPacket::EntityPosition {
+                entity_id: reader.read_varint()?,
+                delta_x: reader.read_position_delta()?,
+                delta_y: reader.read_position_delta()?,
+                delta_z: reader.read_position_delta()?,
+                on_ground: reader.read_bool()?,
+            },
+            0x28 => ClientboundPacket::EntityPositionAndRotation {
+                entity_id: reader.read_varint()?,
+                delta_x: reader.read_position_delta()?,
+                delta_y: reader.read_position_delta()?,
+                delta_z: reader.read_position_delta()?,
+                yaw: reader.read_angle()?,
+                pitch: reader.read_angle()?,
+                on_ground: reader.read_bool()?,
+            },
+            0x29 => ClientboundPacket::EntityRotation {
+                entity_id: reader.read_varint()?,
+                yaw: reader.read_angle()?,
+                pitch: reader.read_angle()?,
+                on_ground: reader.read_bool()?,
+            },
+            0x32 => ClientboundPacket::PlayerInfo {
+                actions: read_player_info_actions(&mut reader)?,
+            },
+            0x36 => {
+                let count = reader.read_varint()?;
+                let mut entity_ids = Vec::new();
+                for _ in 0..count {
+                    entity_ids.push(reader.read_varint()?);
+                }
+                ClientboundPacket::DestroyEntities { entity_ids }
+            }
+            0x3A => ClientboundPacket::EntityHeadLook {
+                entity_id: reader.read_varint()?,
+                head_yaw: reader.read_angle()?,
+            },
+            0x3B => read_multi_block_change(&mut reader)?,
+            0x56 => ClientboundPacket::EntityTeleport {
+                entity_id: reader.read_varint()?,
+                x: reader.read_f64()?,
+                y: reader.read_f64()?,
+                z: reader.read_f64()?,
+                yaw: reader.read_angle()?,
+                pitch: reader.read_angle()?,
+                on_ground: reader.read_bool()?,
+            },
+            _ => return None,
+        };
+        Some(packet)
+    }
+}
+
+/// What a player is doing, as reported to the server with an Entity Action
+/// packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityAction {
+    StartSneaking,
+    StopSneaking,
+    StartSprinting,
+    StopSprinting,
+}
+
+impl EntityAction {
+    fn id(self) -> i32 {
+        match self {
+            EntityAction::StartSneaking => 0,
+            EntityAction::StopSneaking => 1,
+            EntityAction::StartSprinting => 3,
+            EntityAction::StopSprinting => 4,
+        }
+    }
+}
+
+/// The serverbound play packets we send, with the packet ids of protocol
+/// version 754.
+#[derive(Clone, Debug)]
+pub enum ServerboundPacket {
+    /// A chat message, or a command if it starts with a slash.
+    ChatMessage {
+        message: String,
+    },
+    PlayerAbilities {
+        flying: bool,
+    },
+    EntityAction {
+        entity_id: i32,
+        action: EntityAction,
+    },
+}
+
+impl ServerboundPacket {
+    pub fn encode(&self) -> RawPacket {
+        let mut writer = PacketWriter::new();
+        let id = match *self {
+            ServerboundPacket::ChatMessage { ref message } => {
+                writer.write_string(message);
+                0x03
+            }
+            ServerboundPacket::PlayerAbilities { flying } => {
+                writer.write_u8(if flying { 0x02 } else { 0x00 });
+                0x1A
+            }
+            ServerboundPacket::EntityAction { entity_id, action } => {
+                writer.write_varint(entity_id);
+                writer.write_varint(action.id());
+                // Horse jump boost, which only matters when riding one.
+                writer.write_varint(0);
+                0x1C
+            }
+        };
+        RawPacket {
+            id,
+            data: writer.finish(),
+        }
+    }
+}
+
+fn read_player_info_actions(reader: &mut PacketReader) -> Option<Vec<PlayerInfoAction>> {
+    let action = reader.read_varint()?;
+    let count = reader.read_varint()?;
+    let mut actions = Vec::new();
+    for _ in 0..count {
+        let uuid = reader.read_uuid()?;
+        let action = match action {
+            0 => {
+                let name = reader.read_string()?;
+                // Skin properties, which we have no use for.
+                let property_count = reader.read_varint()?;
+                for _ in 0..property_count {
+                    reader.read_string()?;
+                    reader.read_string()?;
+                    if reader.read_bool()? {
+                        reader.read_string()?;
+                    }
+                }
+                PlayerInfoAction::AddPlayer {
+                    uuid,
+                    name,
+                    gamemode: reader.read_varint()?,
+                    ping: reader.read_varint()?,
+                    display_name: reader.read_optional_string()?,
+                }
+            }
+            1 => PlayerInfoAction::UpdateGamemode {
+                uuid,
+                gamemode: reader.read_varint()?,
+            },
+            2 => PlayerInfoAction::UpdateLatency {
+                uuid,
+                ping: reader.read_varint()?,
+            },
+            3 => PlayerInfoAction::UpdateDisplayName {
+                uuid,
+                display_name: reader.read_optional_string()?,
+            },
+            4 => PlayerInfoAction::RemovePlayer { uuid },
+            _ => return None,
+        };
+        actions.push(action);
+    }
+    Some(actions)
+}
+
+fn read_chunk_data(reader: &mut PacketReader) -> Option<ClientboundPacket> {
+    let x = reader.read_i32()?;
+    let z = reader.read_i32()?;
+    let full = reader.read_bool()?;
+    let mask = reader.read_varint()?;
+    // Heightmaps, which we work out ourselves where we need them.
+    read_tag(reader)?;
+    if full {
+        let biome_count = reader.read_varint()?;
+        for _ in 0..biome_count {
+            reader.read_varint()?;
+        }
+    }
+    let length = reader.read_varint()?;
+    if length < 0 {
+        return None;
+    }
+    let mut data = PacketReader::new(reader.read_bytes(length as usize)?);
+
+    let mut sections = Vec::new();
+    for y in 0..16 {
+        if mask & (1 << y) == 0 {
+            continue;
+        }
+        sections.push(SectionData {
+            y,
+            states: read_section_states(&mut data)?,
+        });
+    }
+
+    let count = reader.read_varint()?;
+    if count < 0 {
+        return None;
+    }
+    let mut block_entities = Vec::new();
+    for _ in 0..count {
+        let (_, tag) = read_tag(reader)?;
+        block_entities.push(tag);
+    }
+    Some(ClientboundPacket::ChunkData {
+        x,
+        z,
+        full,
+        sections,
+        block_entities,
+    })
+}
+
+/// Reads the block states of a chunk section. Sections with a palette
+/// store indices into it, and the rest store global ids directly. Since
+/// 1.16 no entry spans two longs.
+fn read_section_states(reader: &mut PacketReader) -> Option<Vec<i32>> {
+    // The number of non-air blocks, which we count ourselves.
+    reader.read_i16()?;
+    let bits = reader.read_u8()? as usize;
+    if bits == 0 || bits > 32 {
+        return None;
+    }
+    let palette = if bits <= 8 {
+        let length = reader.read_varint()?;
+        let palette = (0..length)
+            .map(|_| reader.read_varint())
+            .collect::<Option<Vec<i32>>>()?;
+        Some(palette)
+    } else {
+        None
+    };
+    let long_count = reader.read_varint()?;
+    let longs = (0..long_count)
+        .map(|_| reader.read_i64())
+        .collect::<Option<Vec<i64>>>()?;
+
+    let count = 4096;
+    let per_long = 64 / bits;
+    if longs.len() * per_long < count {
+        return None;
+    }
+    let mask = (1u64 << bits) - 1;
+    (0..count)
+        .map(|i| {
+            let value = (longs[i / per_long] as u64 >> ((i % per_long) * bits)) & mask;
+            match &palette {
+                Some(palette) => palette.get(value as usize).copied(),
+                None => Some(value as i32),
+            }
+        })
+        .collect()
+}
+
+fn read_update_light(reader: &mut PacketReader) -> Option<ClientboundPacket> {
+    let x = reader.read_varint()?;
+    let z = reader.read_varint()?;
+    // Whether the edges can be trusted, which only matters for lighting
+    // that is worked out locally.
+    reader.read_bool()?;
+    let sky_mask = reader.read_varint()?;
+    let block_mask = reader.read_varint()?;
+    let empty_sky_mask = reader.read_varint()?;
+    let empty_block_mask = reader.read_varint()?;
+
+    let mut read_arrays = |mask: i32, empty_mask: i32| {
+        let mut arrays = Vec::new();
+        // Bit 0 is the section below the world.
+        for bit in 0..18 {
+            let light = if mask & (1 << bit) != 0 {
+                let length = reader.read_varint()?;
+                if length < 0 {
+                    return None;
+                }
+                reader.read_bytes(length as usize)?.to_vec()
+            } else if empty_mask & (1 << bit) != 0 {
+                vec![0; 2048]
+            } else {
+                continue;
+            };
+            arrays.push(SectionLight { y: bit - 1, light });
+        }
+        Some(arrays)
+    };
+    let sky_light = read_arrays(sky_mask, empty_sky_mask)?;
+    let block_light = read_arrays(block_mask, empty_block_mask)?;
+    Some(ClientboundPacket::UpdateLight {
+        x,
+        z,
+        sky_light,
+        block_light,
+    })
+}
+
+fn read_multi_block_change(reader: &mut PacketReader) -> Option<ClientboundPacket> {
+    // The section as 22 bits of x, 22 of z and 20 of y.
+    let section = reader.read_i64()?;
+    let section_x = (section >> 42) as i32;
+    let section_y = (section << 44 >> 44) as i32;
+    let section_z = (section << 22 >> 42) as i32;
+    // Whether to skip light updates, which are the server's business.
+    reader.read_bool()?;
+
+    let count = reader.read_varint()?;
+    let mut records = Vec::new();
+    for _ in 0..count {
+        // The state id, then the position within the section as a nibble
+        // each of x, z and y.
+        let record = reader.read_varlong()?;
+        records.push(BlockRecord {
+            x: section_x * 16 + ((record >> 8) & 15) as i32,
+            y: section_y * 16 + (record & 15) as i32,
+            z: section_z * 16 + ((record >> 4) & 15) as i32,
+            state: (record >> 12) as i32,
+        });
+    }
+    Some(ClientboundPacket::MultiBlockChange { records })
+}
+
+/// Reads the primitive types of the Minecraft protocol from a packet body.
+/// Every read returns None once the data runs out.
+pub struct PacketReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(data: &'a [u8]) -> PacketReader<'a> {
+        PacketReader { data, position: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        if self.position + length > self.data.len() {
+            return None;
+        }
+        let bytes = &self.data[self.position..self.position + length];
+        self.position += length;
+        Some(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        Some(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Option<bool> {
+        Some(self.read_u8()? != 0)
+    }
+
+    pub fn read_i16(&mut self) -> Option<i16> {
+        Some(i16::from_be_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_f32(&mut self) -> Option<f32> {
+        Some(f32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_f64(&mut self) -> Option<f64> {
+        Some(f64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_uuid(&mut self) -> Option<u128> {
+        Some(u128::from_be_bytes(
+            self.read_bytes(16)?.try_into().unwrap(),
+        ))
+    }
+
+    pub fn read_varint(&mut self) -> Option<i32> {
+        let mut value: u32 = 0;
+        for i in 0..5 {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as u32) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some(value as i32);
+            }
+        }
+        None
+    }
+
+    pub fn read_varlong(&mut self) -> Option<i64> {
+        let mut value: u64 = 0;
+        for i in 0..10 {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as u64) << (7 * i);
+            if byte & 0x80 == 0 {
+                return Some(value as i64);
+            }
+        }
+        None
+    }
+
+    /// Block positions are packed into a long as 26 bits of x, 26 of z
+    /// and 12 of y.
+    pub fn read_position(&mut self) -> Option<(i32, i32, i32)> {
+        let value = self.read_i64()?;
+        Some((
+            (value >> 38) as i32,
+            (value << 52 >> 52) as i32,
+            (value << 26 >> 38) as i32,
+        ))
+    }
+
+    pub fn read_string(&mut self) -> Option<String> {
+        let length = self.read_varint()?;
+        if length < 0 {
+            return None;
+        }
+        let bytes = self.read_bytes(length as usize)?;
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+
+    /// Reads a string prefixed by a boolean saying whether it is present.
+    /// The outer option is None if the data runs out.
+    pub fn read_optional_string(&mut self) -> Option<Option<String>> {
+        if self.read_bool()? {
+            Some(Some(self.read_string()?))
+        } else {
+            Some(None)
+        }
+    }
+
+    /// Angles are sent as steps of 1/256 of a full turn. Returns degrees.
+    pub fn read_angle(&mut self) -> Option<f32> {
+        Some(self.read_u8()? as f32 * 360.0 / 256.0)
+    }
+
+    /// Relative moves are sent as shorts in 1/4096ths of a block.
+    pub fn read_position_delta(&mut self) -> Option<f64> {
+        Some(self.read_i16()? as f64 / 4096.0)
+    }
+}
+
+/// Writes the primitive types of the Minecraft protocol into a packet body.
+pub struct PacketWriter {
+    data: Vec<u8>,
+}
+
+impl Default for PacketWriter {
+    fn default() -> PacketWriter {
+        PacketWriter::new()
+    }
+}
+
+impl PacketWriter {
+    pub fn new() -> PacketWriter {
+        PacketWriter { data: Vec::new() }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn write_string(&mut self, value: &str) {
+        self.write_varint(value.len() as i32);
+        self.write_bytes(value.as_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_bytes(&value.to_be_bytes());
+    }
+
+    pub fn write_varint(&mut self, value: i32) {
+        let mut value = value as u32;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.data.push(byte);
+                return;
+            }
+            self.data.push(byte | 0x80);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_varlong(writer: &mut PacketWriter, value: i64) {
+        let mut value = value as u64;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                writer.write_u8(byte);
+                return;
+            }
+            writer.write_u8(byte | 0x80);
+        }
+    }
+
+    /// Writes a chunk section the way the server does, with no entry
+    /// spanning two longs and a palette if there is one.
+    fn write_section(writer: &mut PacketWriter, values: &[u32], bits: usize, palette: &[i32]) {
+        writer.write_bytes(&4096i16.to_be_bytes());
+        writer.write_u8(bits as u8);
+        if !palette.is_empty() {
+            writer.write_varint(palette.len() as i32);
+            for &id in palette {
+                writer.write_varint(id);
+            }
+        }
+        let per_long = 64 / bits;
+        let mut longs = vec![0u64; values.len().div_ceil(per_long)];
+        for (i, &value) in values.iter().enumerate() {
+            longs[i / per_long] |= (value as u64) << ((i % per_long) * bits);
+        }
+        writer.write_varint(longs.len() as i32);
+        for long in longs {
+            writer.write_bytes(&long.to_be_bytes());
+        }
+    }
+
+    fn read_section(data: Vec<u8>) -> Option<Vec<i32>> {
+        read_section_states(&mut PacketReader::new(&data))
+    }
+
+    #[test]
+    fn chat_messages_are_length_prefixed() {
+        let raw = ServerboundPacket::ChatMessage {
+            message: "//pos1 1,2,3".to_owned(),
+        }
+        .encode();
+        assert_eq!(raw.id, 0x03);
+        assert_eq!(raw.data[0], 12);
+        assert_eq!(&raw.data[1..], b"//pos1 1,2,3");
+    }
+
+    #[test]
+    fn palettized_section_round_trips() {
+        let palette = [0, 1, 1337, 8, 20000];
+        let indices: Vec<u32> = (0..4096).map(|i| (i * 7 % 5) as u32).collect();
+        // Five bits don't divide 64, so each long is padded.
+        let mut writer = PacketWriter::new();
+        write_section(&mut writer, &indices, 5, &palette);
+        let states = read_section(writer.finish()).unwrap();
+        let expected: Vec<i32> = indices.iter().map(|&i| palette[i as usize]).collect();
+        assert_eq!(states, expected);
+    }
+
+    #[test]
+    fn direct_section_round_trips() {
+        let ids: Vec<u32> = (0..4096).map(|i| (i * 31 % 17000) as u32).collect();
+        let mut writer = PacketWriter::new();
+        write_section(&mut writer, &ids, 15, &[]);
+        let states = read_section(writer.finish()).unwrap();
+        assert_eq!(states, ids.iter().map(|&id| id as i32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn section_rejects_bad_data() {
+        // An index past the end of the palette.
+        let mut writer = PacketWriter::new();
+        write_section(&mut writer, &[2; 4096], 4, &[0, 1]);
+        assert_eq!(read_section(writer.finish()), None);
+        // Too few longs for every block.
+        let mut writer = PacketWriter::new();
+        write_section(&mut writer, &[1; 4000], 4, &[0, 1]);
+        assert_eq!(read_section(writer.finish()), None);
+        // No bits per block.
+        let mut writer = PacketWriter::new();
+        writer.write_bytes(&0i16.to_be_bytes());
+        writer.write_u8(0);
+        assert_eq!(read_section(writer.finish()), None);
+    }
+
+    #[test]
+    fn light_masks_include_empty_sections() {
+        let mut writer = PacketWriter::new();
+        writer.write_varint(3);
+        writer.write_varint(-2);
+        writer.write_u8(1);
+        // Sky light for the section below the world and section 3, with
+        // section 1 empty. No block light.
+        writer.write_varint(1 << 0 | 1 << 4);
+        writer.write_varint(0);
+        writer.write_varint(1 << 2);
+        writer.write_varint(0);
+        for &level in [0x11u8, 0xff].iter() {
+            writer.write_varint(2048);
+            writer.write_bytes(&[level; 2048]);
+        }
+        let raw = RawPacket {
+            id: 0x23,
+            data: writer.finish(),
+        };
+
+        match ClientboundPacket::decode(&raw) {
+            Some(ClientboundPacket::UpdateLight {
+                x,
+                z,
+                sky_light,
+                block_light,
+            }) => {
+                assert_eq!((x, z), (3, -2));
+                let sections: Vec<(i32, u8)> = sky_light
+                    .iter()
+                    .map(|section| {
+                        assert_eq!(section.light.len(), 2048);
+                        (section.y, section.light[0])
+                    })
+                    .collect();
+                assert_eq!(sections, vec![(-1, 0x11), (1, 0), (3, 0xff)]);
+                assert!(block_light.is_empty());
+            }
+            packet => panic!("expected light, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn multi_block_change_places_records_in_their_section() {
+        let (section_x, section_y, section_z) = (-2i64, 3i64, 5i64);
+        let mut writer = PacketWriter::new();
+        let section =
+            (section_x & 0x3fffff) << 42 | (section_z & 0x3fffff) << 20 | (section_y & 0xfffff);
+        writer.write_bytes(&section.to_be_bytes());
+        writer.write_u8(0);
+        writer.write_varint(2);
+        write_varlong(&mut writer, 1337 << 12 | 15 << 8 | 1 << 4 | 2);
+        write_varlong(&mut writer, 9);
+        let raw = RawPacket {
+            id: 0x3B,
+            data: writer.finish(),
+        };
+
+        match ClientboundPacket::decode(&raw) {
+            Some(ClientboundPacket::MultiBlockChange { records }) => {
+                let records: Vec<_> = records
+                    .iter()
+                    .map(|record| (record.x, record.y, record.z, record.state))
+                    .collect();
+                assert_eq!(records, vec![(-17, 50, 81, 1337), (-32, 57, 80, 0)]);
+            }
+            packet => panic!("expected block changes, got {:?}", packet),
+        }
+    }
+}
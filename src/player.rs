@@ -1,16 +1,92 @@
+use crate::packet::{EntityAction, ServerboundPacket};
 use crate::spatial::Spatial;
+use crate::window::{invalid_setting, unset_setting};
+use crate::world::{BlockPos, World};
+use config::Config;
 use nalgebra::{Rotation3, Vector3};
 
+// Movement runs at the server's tick rate, with the values vanilla uses,
+// and the drawn position is interpolated between ticks.
+const TICK_TIME: f32 = 0.05;
+// Don't try to catch up on more than this many ticks after a long stall.
+const MAX_TICKS_PER_UPDATE: u32 = 10;
+
+const WALK_SPEED: f32 = 0.1;
+const SPRINT_MULTIPLIER: f32 = 1.3;
+const SNEAK_INPUT_SCALE: f32 = 0.3;
+const AIR_ACCELERATION: f32 = 0.02;
+const SPRINT_AIR_ACCELERATION: f32 = 0.026;
+// Horizontal speed is kept by this much each tick, times the slipperiness
+// of the block underfoot when on the ground.
+const AIR_FRICTION: f32 = 0.91;
+const GRAVITY: f32 = 0.08;
+const VERTICAL_DRAG: f32 = 0.98;
+const JUMP_VELOCITY: f32 = 0.42;
+const SPRINT_JUMP_BOOST: f32 = 0.2;
+const FLY_VERTICAL_DRAG: f32 = 0.6;
+const MIN_FLY_SPEED: f32 = 0.01;
+const MAX_FLY_SPEED: f32 = 1.0;
+// Each notch of the scroll wheel scales the fly speed by this much.
+const FLY_SPEED_STEP: f32 = 1.25;
+
+// How quickly space has to be tapped twice to toggle flying, seven ticks
+// like vanilla.
+const DOUBLE_TAP_TIME: f32 = 0.35;
+
+// Players on the ground walk up anything no higher than this, like slabs.
+const STEP_HEIGHT: f32 = 0.6;
+
+const HALF_WIDTH: f32 = 0.3;
+const HEIGHT: f32 = 1.8;
+const EYE_HEIGHT: f32 = 1.62;
+const SNEAK_EYE_HEIGHT: f32 = 1.27;
+
+// Moves are broken into steps no longer than this so that fast moves can't
+// pass through blocks.
+const MAX_MOVE_STEP: f32 = 0.5;
+const COLLISION_EPSILON: f32 = 1e-4;
+
+/// The local player. The spatial sits at eye height, while `position` is
+/// the position of the player's feet as of the last movement tick.
 pub struct Player {
     pub spatial: Spatial,
     pub velocity: Vector3<f32>,
+    pub position: Vector3<f32>,
+
+    /// Our entity id, once the server has told us it on joining.
+    pub entity_id: Option<i32>,
+    pub on_ground: bool,
+    pub flying: bool,
+    pub sprinting: bool,
+    pub sneaking: bool,
+    /// The horizontal acceleration while flying, in blocks per tick
+    /// squared. Vanilla uses 0.05.
+    pub fly_speed: f32,
 
     pub control_heading: Vector3<f32>,
     pub control_vector: Vector3<f32>,
+    pub control_sprint: bool,
+
+    previous_position: Vector3<f32>,
+    tick_accumulator: f32,
+    jump_held: bool,
+    // Time left to tap space a second time and toggle flying.
+    double_tap_timer: f32,
+    // What the server was last told, so that only changes get sent.
+    reported_flying: bool,
+    reported_sprinting: bool,
+    reported_sneaking: bool,
 }
 
 impl Player {
-    pub fn new() -> Player {
+    pub fn new(config: &Config) -> Player {
+        let fly_speed = config
+            .get_float("fly_speed")
+            .expect(unset_setting("fly_speed").as_ref());
+        let fly_speed = Some(fly_speed as f32)
+            .filter(|speed| *speed > 0.0)
+            .expect(invalid_setting("fly_speed").as_ref());
+
         let spatial = Spatial::new();
         let velocity = Vector3::new(0.0, 0.0, 0.0);
         let control_heading = Vector3::new(0.0, 0.0, 0.0);
@@ -18,35 +94,339 @@ impl Player {
         Player {
             spatial,
             velocity,
+            position: Vector3::new(0.0, 0.0, 0.0),
+            entity_id: None,
+            on_ground: false,
+            // MCHPRS puts everyone in creative, so start off flying.
+            flying: true,
+            sprinting: false,
+            sneaking: false,
+            fly_speed: fly_speed.clamp(MIN_FLY_SPEED, MAX_FLY_SPEED),
             control_heading,
             control_vector,
+            control_sprint: false,
+            previous_position: Vector3::new(0.0, 0.0, 0.0),
+            tick_accumulator: 0.0,
+            jump_held: false,
+            double_tap_timer: 0.0,
+            reported_flying: false,
+            reported_sprinting: false,
+            reported_sneaking: false,
         }
     }
 
-    pub fn update(&mut self) {
-        //Apply our control heading and vector
-        self.velocity = self.control_vector.clone_owned();
-        self.velocity = Rotation3::from_axis_angle(&Vector3::y_axis(), 
-            self.control_heading[1].to_radians()) * self.velocity;
-        if self.velocity.magnitude() > 0.001 {
-            self.velocity = self.velocity.normalize();
-        }
+    /// Advances the player by `delta` seconds.
+    pub fn update(&mut self, delta: f32, world: &World) {
+        self.update_movement_mode(delta);
 
-        self.velocity *= 0.2;
+        self.tick_accumulator += delta;
+        let mut ticks = 0;
+        while self.tick_accumulator >= TICK_TIME {
+            self.tick_accumulator -= TICK_TIME;
+            if ticks < MAX_TICKS_PER_UPDATE {
+                self.previous_position = self.position;
+                self.tick(world);
+                ticks += 1;
+            }
+        }
 
-        self.move_player(&self.velocity.clone_owned());
+        let t = self.tick_accumulator / TICK_TIME;
+        let mut translation = self.previous_position.lerp(&self.position, t);
+        translation[1] += self.eye_height();
+        self.borrow_spatial_mut().set_translation(&translation);
 
         let rotation = self.control_heading.clone_owned();
         self.borrow_spatial_mut().set_rotation(&rotation);
     }
 
-    pub fn move_player(&mut self, vector: &Vector3<f32>) {
-        //This function will later be used to process physics as well.
-        let mut translation = self.borrow_spatial().get_translation().clone_owned();
-        translation += vector;
+    /// Works out whether we're flying, sprinting or sneaking from the
+    /// controls. This runs every frame so that quick taps aren't missed.
+    fn update_movement_mode(&mut self, delta: f32) {
+        let jump_held = self.control_vector[1] > 0.0;
+        self.double_tap_timer = (self.double_tap_timer - delta).max(0.0);
+        if jump_held && !self.jump_held {
+            if self.double_tap_timer > 0.0 {
+                self.flying = !self.flying;
+                self.velocity[1] = 0.0;
+                self.double_tap_timer = 0.0;
+            } else {
+                self.double_tap_timer = DOUBLE_TAP_TIME;
+            }
+        }
+        self.jump_held = jump_held;
+
+        // When flying, sneak descends instead.
+        self.sneaking = self.control_vector[1] < 0.0 && !self.flying;
+
+        let moving_forward = self.control_vector[2] < 0.0;
+        if !moving_forward || self.sneaking {
+            self.sprinting = false;
+        } else if self.control_sprint {
+            self.sprinting = true;
+        }
+    }
+
+    fn tick(&mut self, world: &World) {
+        // The horizontal input, turned to face where we're looking.
+        let mut input = Vector3::new(self.control_vector[0], 0.0, self.control_vector[2]);
+        if input.magnitude() > 1.0 {
+            input = input.normalize();
+        }
+        if self.sneaking {
+            input *= SNEAK_INPUT_SCALE;
+        }
+        let input =
+            Rotation3::from_axis_angle(&Vector3::y_axis(), self.control_heading[1].to_radians())
+                * input;
+
+        if self.flying {
+            let speed = if self.sprinting {
+                self.fly_speed * 2.0
+            } else {
+                self.fly_speed
+            };
+            self.velocity += input * speed;
+            self.velocity[1] += self.control_vector[1] * self.fly_speed * 3.0;
+
+            self.move_player(&self.velocity.clone_owned(), world);
+
+            self.velocity[0] *= AIR_FRICTION;
+            self.velocity[2] *= AIR_FRICTION;
+            self.velocity[1] *= FLY_VERTICAL_DRAG;
+
+            // Like vanilla, landing stops flying.
+            if self.on_ground {
+                self.flying = false;
+            }
+        } else {
+            // Vanilla looks just under the feet, so that the block stood on
+            // is found even when the player sinks into it slightly.
+            let ground = BlockPos::new(
+                self.position[0].floor() as i32,
+                (self.position[1] - 0.5).floor() as i32,
+                self.position[2].floor() as i32,
+            );
+            let slipperiness = world.get_block(ground).slipperiness();
+            let friction = if self.on_ground {
+                slipperiness * AIR_FRICTION
+            } else {
+                AIR_FRICTION
+            };
+            let acceleration = if self.on_ground {
+                let speed = if self.sprinting {
+                    WALK_SPEED * SPRINT_MULTIPLIER
+                } else {
+                    WALK_SPEED
+                };
+                // Scaled so that slippery blocks keep the same top speed.
+                speed * 0.216 / (slipperiness * slipperiness * slipperiness)
+            } else if self.sprinting {
+                SPRINT_AIR_ACCELERATION
+            } else {
+                AIR_ACCELERATION
+            };
+
+            if self.jump_held && self.on_ground {
+                self.velocity[1] = JUMP_VELOCITY;
+                if self.sprinting {
+                    let yaw = self.control_heading[1].to_radians();
+                    self.velocity[0] -= yaw.sin() * SPRINT_JUMP_BOOST;
+                    self.velocity[2] -= yaw.cos() * SPRINT_JUMP_BOOST;
+                }
+            }
+
+            self.velocity += input * acceleration;
+            self.move_player(&self.velocity.clone_owned(), world);
+
+            self.velocity[1] = (self.velocity[1] - GRAVITY) * VERTICAL_DRAG;
+            self.velocity[0] *= friction;
+            self.velocity[2] *= friction;
+        }
+    }
+
+    /// Moves the player, stopping at any solid blocks in the way or
+    /// stepping up onto low ones when on the ground.
+    pub fn move_player(&mut self, vector: &Vector3<f32>, world: &World) {
+        let steps = (vector.abs().max() / MAX_MOVE_STEP).ceil().max(1.0) as u32;
+        let step = vector / steps as f32;
+
+        let mut blocked = [false; 3];
+        for _ in 0..steps {
+            // Vertical first, so that walking off a ledge can't clip into
+            // the block beside it.
+            for &axis in [1, 0, 2].iter() {
+                if !blocked[axis] && step[axis] != 0.0 {
+                    let can_step = self.on_ground || (blocked[1] && vector[1] < 0.0);
+                    blocked[axis] = self.move_axis(axis, step[axis], world, can_step);
+                }
+            }
+        }
+
+        self.on_ground = blocked[1] && vector[1] < 0.0;
+        for (axis, blocked) in blocked.iter().enumerate() {
+            if *blocked {
+                self.velocity[axis] = 0.0;
+            }
+        }
+        if blocked[0] || blocked[2] {
+            self.sprinting = false;
+        }
+    }
+
+    /// Moves along a single axis and pushes the player back out of any
+    /// block it ends up in. Moving sideways into blocks low enough to step
+    /// onto lifts the player on top of them instead, if `can_step` and
+    /// there is room. Returns whether a block was hit.
+    fn move_axis(&mut self, axis: usize, amount: f32, world: &World, can_step: bool) -> bool {
+        self.position[axis] += amount;
+
+        let boxes = self.colliding_boxes(world);
+        if boxes.is_empty() {
+            return false;
+        }
+        if axis != 1 && can_step {
+            let top = boxes
+                .iter()
+                .map(|(_, high)| high[1])
+                .fold(f32::MIN, f32::max);
+            if top - self.position[1] <= STEP_HEIGHT {
+                let feet = self.position[1];
+                self.position[1] = top;
+                if self.colliding_boxes(world).is_empty() {
+                    return false;
+                }
+                self.position[1] = feet;
+            }
+        }
+
+        // How far the player's box reaches either side of its position.
+        let below = [HALF_WIDTH, 0.0, HALF_WIDTH][axis];
+        let above = [HALF_WIDTH, HEIGHT, HALF_WIDTH][axis];
+        for (low, high) in boxes {
+            if amount > 0.0 {
+                self.position[axis] = self.position[axis].min(low[axis] - above);
+            } else {
+                self.position[axis] = self.position[axis].max(high[axis] + below);
+            }
+        }
+        true
+    }
+
+    /// The collision boxes of the solid blocks the player overlaps, in
+    /// world coordinates. Blocks that aren't full cubes collide with the
+    /// box they are drawn as.
+    fn colliding_boxes(&self, world: &World) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        let min = self.position - Vector3::new(HALF_WIDTH, 0.0, HALF_WIDTH);
+        let max = self.position + Vector3::new(HALF_WIDTH, HEIGHT, HALF_WIDTH);
+        let first = min.map(|v| (v + COLLISION_EPSILON).floor() as i32);
+        let last = max.map(|v| (v - COLLISION_EPSILON).floor() as i32);
+
+        let mut boxes = Vec::new();
+        for x in first[0]..=last[0] {
+            for y in first[1]..=last[1] {
+                for z in first[2]..=last[2] {
+                    let block = world.get_block(BlockPos::new(x, y, z));
+                    if !block.is_solid() {
+                        continue;
+                    }
+                    let (low, high) = block
+                        .partial_bounds()
+                        .unwrap_or(([0.0, 0.0, 0.0], [1.0, 1.0, 1.0]));
+                    let cell = Vector3::new(x as f32, y as f32, z as f32);
+                    let low = cell + Vector3::from(low);
+                    let high = cell + Vector3::from(high);
+                    let overlaps = (0..3).all(|axis| {
+                        max[axis] > low[axis] + COLLISION_EPSILON
+                            && min[axis] < high[axis] - COLLISION_EPSILON
+                    });
+                    if overlaps {
+                        boxes.push((low, high));
+                    }
+                }
+            }
+        }
+        boxes
+    }
+
+    /// Puts the player's feet at `position` without interpolating.
+    pub fn teleport(&mut self, position: &Vector3<f32>) {
+        self.position = position.clone_owned();
+        self.previous_position = position.clone_owned();
+        self.velocity = Vector3::new(0.0, 0.0, 0.0);
+        let mut translation = position.clone_owned();
+        translation[1] += self.eye_height();
         self.borrow_spatial_mut().set_translation(&translation);
     }
 
+    /// Our (pitch, yaw) in Minecraft's conventions, where yaw turns
+    /// clockwise from +Z and positive pitch looks down.
+    pub fn minecraft_rotation(&self) -> (f32, f32) {
+        let pitch = -self.control_heading[0];
+        let yaw = (180.0 - self.control_heading[1]).rem_euclid(360.0);
+        (pitch, yaw)
+    }
+
+    /// The corners of the player's collision box.
+    pub fn bounding_box(&self) -> (Vector3<f32>, Vector3<f32>) {
+        (
+            self.position - Vector3::new(HALF_WIDTH, 0.0, HALF_WIDTH),
+            self.position + Vector3::new(HALF_WIDTH, HEIGHT, HALF_WIDTH),
+        )
+    }
+
+    pub fn eye_height(&self) -> f32 {
+        if self.sneaking {
+            SNEAK_EYE_HEIGHT
+        } else {
+            EYE_HEIGHT
+        }
+    }
+
+    /// Scales the fly speed by a number of scroll wheel notches.
+    pub fn adjust_fly_speed(&mut self, notches: f32) {
+        self.fly_speed =
+            (self.fly_speed * FLY_SPEED_STEP.powf(notches)).clamp(MIN_FLY_SPEED, MAX_FLY_SPEED);
+    }
+
+    /// Returns the packets needed to tell the server about any change in
+    /// whether we're flying, sprinting or sneaking. Nothing is sent until
+    /// the server has told us our entity id on joining, which sprinting
+    /// and sneaking are sent as actions of; the state at that point is
+    /// sent then.
+    pub fn take_state_packets(&mut self) -> Vec<ServerboundPacket> {
+        let entity_id = match self.entity_id {
+            Some(entity_id) => entity_id,
+            None => return Vec::new(),
+        };
+        let mut packets = Vec::new();
+        if self.flying != self.reported_flying {
+            self.reported_flying = self.flying;
+            packets.push(ServerboundPacket::PlayerAbilities {
+                flying: self.flying,
+            });
+        }
+        if self.sprinting != self.reported_sprinting {
+            self.reported_sprinting = self.sprinting;
+            let action = if self.sprinting {
+                EntityAction::StartSprinting
+            } else {
+                EntityAction::StopSprinting
+            };
+            packets.push(ServerboundPacket::EntityAction { entity_id, action });
+        }
+        if self.sneaking != self.reported_sneaking {
+            self.reported_sneaking = self.sneaking;
+            let action = if self.sneaking {
+                EntityAction::StartSneaking
+            } else {
+                EntityAction::StopSneaking
+            };
+            packets.push(ServerboundPacket::EntityAction { entity_id, action });
+        }
+        packets
+    }
+
+    #[allow(dead_code)]
     pub fn borrow_spatial(&self) -> &Spatial {
         &self.spatial
     }
@@ -83,4 +463,217 @@ impl Player {
     pub fn set_control_vector(&mut self, vector: &Vector3<f32>) {
         self.control_vector = vector.clone_owned();
     }
+
+    pub fn set_control_sprint(&mut self, sprint: bool) {
+        self.control_sprint = sprint;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+
+    fn walker() -> Player {
+        let mut config = Config::default();
+        config.set("fly_speed", 0.05).unwrap();
+        let mut player = Player::new(&config);
+        player.flying = false;
+        player
+    }
+
+    /// A floor of `ground` blocks with its top at y = 1.
+    fn floor(ground: &str) -> World {
+        let mut world = World::new();
+        for x in -8..8 {
+            for z in -8..8 {
+                world.set_block(BlockPos::new(x, 0, z), Block::from_state(ground));
+            }
+        }
+        world
+    }
+
+    /// Puts the player on the floor and lets them settle onto it.
+    fn stand(player: &mut Player, world: &World) {
+        player.teleport(&Vector3::new(0.5, 1.0, 0.5));
+        for _ in 0..3 {
+            player.tick(world);
+        }
+        assert!(player.on_ground);
+        assert_eq!(player.position[1], 1.0);
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn falls_with_gravity_and_drag() {
+        let world = World::new();
+        let mut player = walker();
+        player.teleport(&Vector3::new(0.5, 64.0, 0.5));
+        player.tick(&world);
+        assert_close(player.velocity[1], -GRAVITY * VERTICAL_DRAG);
+        assert_eq!(player.position[1], 64.0);
+        player.tick(&world);
+        assert_close(player.position[1], 64.0 - GRAVITY * VERTICAL_DRAG);
+        assert_close(
+            player.velocity[1],
+            (-GRAVITY * VERTICAL_DRAG - GRAVITY) * VERTICAL_DRAG,
+        );
+    }
+
+    #[test]
+    fn ground_friction_scales_with_slipperiness() {
+        for &(ground, slipperiness) in [("stone", 0.6), ("packed_ice", 0.98)].iter() {
+            let world = floor(ground);
+            let mut player = walker();
+            stand(&mut player, &world);
+            player.velocity[0] = 0.5;
+            player.tick(&world);
+            assert_close(player.velocity[0], 0.5 * slipperiness * AIR_FRICTION);
+        }
+    }
+
+    #[test]
+    fn air_friction_ignores_the_ground() {
+        let world = floor("packed_ice");
+        let mut player = walker();
+        player.teleport(&Vector3::new(0.5, 32.0, 0.5));
+        player.velocity[0] = 0.5;
+        player.tick(&world);
+        assert_close(player.velocity[0], 0.5 * AIR_FRICTION);
+    }
+
+    #[test]
+    fn walking_accelerates_to_the_same_speed_on_any_ground() {
+        for &ground in ["stone", "blue_ice"].iter() {
+            let world = floor(ground);
+            let mut player = walker();
+            stand(&mut player, &world);
+            // Forward, facing north.
+            player.set_control_vector(&Vector3::new(0.0, 0.0, -1.0));
+            for _ in 0..200 {
+                player.tick(&world);
+                player.position = Vector3::new(0.5, 1.0, 0.5);
+            }
+            let slipperiness = Block::from_state(ground).slipperiness();
+            let friction = slipperiness * AIR_FRICTION;
+            let acceleration = WALK_SPEED * 0.216 / slipperiness.powi(3);
+            // Where accelerating and friction balance out.
+            let top_speed = acceleration * friction / (1.0 - friction);
+            assert!((player.velocity[2] + top_speed).abs() < 1e-3);
+            assert_eq!(player.velocity[0], 0.0);
+        }
+    }
+
+    #[test]
+    fn fly_speed_scales_by_notches_and_clamps() {
+        let mut player = walker();
+        player.adjust_fly_speed(1.0);
+        assert_close(player.fly_speed, 0.05 * FLY_SPEED_STEP);
+        player.adjust_fly_speed(-2.0);
+        assert_close(player.fly_speed, 0.05 / FLY_SPEED_STEP);
+        player.adjust_fly_speed(100.0);
+        assert_eq!(player.fly_speed, MAX_FLY_SPEED);
+        player.adjust_fly_speed(-100.0);
+        assert_eq!(player.fly_speed, MIN_FLY_SPEED);
+
+        let mut config = Config::default();
+        config.set("fly_speed", 5.0).unwrap();
+        assert_eq!(Player::new(&config).fly_speed, MAX_FLY_SPEED);
+    }
+
+    #[test]
+    fn double_tapping_space_toggles_flying() {
+        let mut player = walker();
+        let press = |player: &mut Player, held: bool, delta: f32| {
+            player.set_control_vector(&Vector3::new(0.0, if held { 1.0 } else { 0.0 }, 0.0));
+            player.update_movement_mode(delta);
+        };
+
+        press(&mut player, true, 0.0);
+        press(&mut player, false, 0.1);
+        press(&mut player, true, 0.1);
+        assert!(player.flying);
+
+        // Holding space doesn't count as tapping it again.
+        press(&mut player, true, 0.1);
+        assert!(player.flying);
+
+        // A second tap that comes too late only starts the window again.
+        press(&mut player, false, 0.0);
+        press(&mut player, true, 0.0);
+        press(&mut player, false, 0.2);
+        press(&mut player, true, 0.2);
+        assert!(player.flying);
+        press(&mut player, false, 0.1);
+        press(&mut player, true, 0.1);
+        assert!(!player.flying);
+    }
+
+    #[test]
+    fn steps_onto_slabs_but_not_full_blocks() {
+        let mut world = floor("stone");
+        world.set_block(
+            BlockPos::new(2, 1, 0),
+            Block::from_state("smooth_stone_slab[type=bottom,waterlogged=false]"),
+        );
+        world.set_block(BlockPos::new(-2, 1, 0), Block::Stone);
+
+        let mut player = walker();
+        stand(&mut player, &world);
+        player.set_control_vector(&Vector3::new(1.0, 0.0, 0.0));
+        while player.position[0] < 2.5 {
+            player.tick(&world);
+        }
+        assert_close(player.position[1], 1.5);
+
+        let mut player = walker();
+        stand(&mut player, &world);
+        player.set_control_vector(&Vector3::new(-1.0, 0.0, 0.0));
+        for _ in 0..40 {
+            player.tick(&world);
+        }
+        assert_close(player.position[0], -1.0 + HALF_WIDTH);
+        assert_eq!(player.position[1], 1.0);
+    }
+
+    #[test]
+    fn state_packets_wait_for_join_game() {
+        let mut player = walker();
+        player.flying = true;
+        player.sprinting = true;
+        assert!(player.take_state_packets().is_empty());
+
+        player.entity_id = Some(7);
+        let packets = player.take_state_packets();
+        assert_eq!(packets.len(), 2);
+        assert!(matches!(
+            packets[0],
+            ServerboundPacket::PlayerAbilities { flying: true }
+        ));
+        assert!(matches!(
+            packets[1],
+            ServerboundPacket::EntityAction {
+                entity_id: 7,
+                action: EntityAction::StartSprinting,
+            }
+        ));
+        // Only changes are sent.
+        assert!(player.take_state_packets().is_empty());
+        player.sprinting = false;
+        assert!(matches!(
+            player.take_state_packets()[..],
+            [ServerboundPacket::EntityAction {
+                action: EntityAction::StopSprinting,
+                ..
+            }]
+        ));
+    }
 }
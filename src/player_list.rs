@@ -0,0 +1,272 @@
+use crate::packet::{ClientboundPacket, PlayerInfoAction};
+use crate::render_text::TextRenderer;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gamemode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl Gamemode {
+    pub fn from_id(id: i32) -> Gamemode {
+        match id {
+            1 => Gamemode::Creative,
+            2 => Gamemode::Adventure,
+            3 => Gamemode::Spectator,
+            _ => Gamemode::Survival,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Gamemode::Survival => "Survival",
+            Gamemode::Creative => "Creative",
+            Gamemode::Adventure => "Adventure",
+            Gamemode::Spectator => "Spectator",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlayerInfo {
+    pub uuid: u128,
+    pub name: String,
+    pub gamemode: Gamemode,
+    /// Round trip latency in milliseconds.
+    pub ping: i32,
+    /// The display name as plain text, if the server set one.
+    pub display_name: Option<String>,
+}
+
+impl PlayerInfo {
+    /// The name to show for the player: the display name if there is one,
+    /// otherwise the account name.
+    pub fn shown_name(&self) -> &str {
+        match &self.display_name {
+            Some(display_name) => display_name,
+            None => &self.name,
+        }
+    }
+}
+
+/// Everyone the server lists as online, as shown on the tab list.
+pub struct PlayerList {
+    players: HashMap<u128, PlayerInfo>,
+}
+
+impl PlayerList {
+    pub fn new() -> PlayerList {
+        PlayerList {
+            players: HashMap::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, uuid: u128) -> Option<&PlayerInfo> {
+        self.players.get(&uuid)
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Returns the players in the order vanilla lists them: spectators
+    /// last, otherwise sorted by name.
+    pub fn sorted(&self) -> Vec<&PlayerInfo> {
+        let mut players: Vec<&PlayerInfo> = self.players.values().collect();
+        players.sort_by(|a, b| {
+            let a_spectator = a.gamemode == Gamemode::Spectator;
+            let b_spectator = b.gamemode == Gamemode::Spectator;
+            a_spectator
+                .cmp(&b_spectator)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+        players
+    }
+
+    pub fn handle_packet(&mut self, packet: &ClientboundPacket) {
+        let actions = match packet {
+            ClientboundPacket::PlayerInfo { actions } => actions,
+            _ => return,
+        };
+
+        for action in actions {
+            match action {
+                PlayerInfoAction::AddPlayer {
+                    uuid,
+                    name,
+                    gamemode,
+                    ping,
+                    display_name,
+                } => {
+                    let info = PlayerInfo {
+                        uuid: *uuid,
+                        name: name.clone(),
+                        gamemode: Gamemode::from_id(*gamemode),
+                        ping: *ping,
+                        display_name: display_name.as_ref().map(|json| chat_to_plain_text(json)),
+                    };
+                    self.players.insert(*uuid, info);
+                }
+                PlayerInfoAction::UpdateGamemode { uuid, gamemode } => {
+                    if let Some(player) = self.players.get_mut(uuid) {
+                        player.gamemode = Gamemode::from_id(*gamemode);
+                    }
+                }
+                PlayerInfoAction::UpdateLatency { uuid, ping } => {
+                    if let Some(player) = self.players.get_mut(uuid) {
+                        player.ping = *ping;
+                    }
+                }
+                PlayerInfoAction::UpdateDisplayName { uuid, display_name } => {
+                    if let Some(player) = self.players.get_mut(uuid) {
+                        player.display_name =
+                            display_name.as_ref().map(|json| chat_to_plain_text(json));
+                    }
+                }
+                PlayerInfoAction::RemovePlayer { uuid } => {
+                    self.players.remove(uuid);
+                }
+            }
+        }
+    }
+
+    /// Queues the tab list overlay, centered at the top of the screen.
+    pub fn queue_overlay(&self, text: &mut TextRenderer, screen_width: u32) {
+        let scale = 2.0;
+        let line_height = TextRenderer::line_height(scale);
+        let padding = 4.0;
+        let players = self.sorted();
+
+        let rows: Vec<(String, String)> = players
+            .iter()
+            .map(|player| {
+                (
+                    player.shown_name().to_owned(),
+                    format!("{} {}ms", player.gamemode.name(), player.ping),
+                )
+            })
+            .collect();
+
+        let name_width = rows
+            .iter()
+            .map(|(name, _)| TextRenderer::text_width(name, scale))
+            .fold(0.0, f32::max);
+        let detail_width = rows
+            .iter()
+            .map(|(_, detail)| TextRenderer::text_width(detail, scale))
+            .fold(0.0, f32::max);
+        let title = format!("Players online: {}", rows.len());
+        let width = (name_width + line_height + detail_width)
+            .max(TextRenderer::text_width(&title, scale))
+            + padding * 2.0;
+        let height = line_height * (rows.len() + 1) as f32 + padding * 2.0;
+
+        let x = (screen_width as f32 - width) / 2.0;
+        let y = line_height;
+        text.queue_rect(x, y, width, height, [0.0, 0.0, 0.0, 0.5]);
+        text.queue_text(
+            x + padding,
+            y + padding,
+            scale,
+            [1.0, 1.0, 0.6, 1.0],
+            &title,
+        );
+
+        for (i, (name, detail)) in rows.iter().enumerate() {
+            let row_y = y + padding + line_height * (i + 1) as f32;
+            text.queue_text(x + padding, row_y, scale, [1.0, 1.0, 1.0, 1.0], name);
+            text.queue_text(
+                x + width - padding - TextRenderer::text_width(detail, scale),
+                row_y,
+                scale,
+                [0.7, 0.7, 0.7, 1.0],
+                detail,
+            );
+        }
+    }
+}
+
+/// Flattens a JSON chat component into its plain text: each component's
+/// own text followed by that of its `extra` children. Translated text is
+/// shown as its key followed by its arguments, as we don't have the
+/// language files. Formatting is dropped.
+pub fn chat_to_plain_text(json: &str) -> String {
+    match serde_json::from_str(json) {
+        Ok(component) => {
+            let mut text = String::new();
+            append_plain_text(&component, &mut text);
+            text
+        }
+        // Not a chat component at all, so show it as is.
+        Err(_) => json.to_owned(),
+    }
+}
+
+fn append_plain_text(component: &Value, text: &mut String) {
+    match component {
+        Value::String(string) => text.push_str(string),
+        Value::Array(components) => {
+            for component in components {
+                append_plain_text(component, text);
+            }
+        }
+        Value::Object(fields) => {
+            if let Some(Value::String(string)) = fields.get("text") {
+                text.push_str(string);
+            } else if let Some(Value::String(key)) = fields.get("translate") {
+                text.push_str(key);
+                if let Some(Value::Array(arguments)) = fields.get("with") {
+                    for argument in arguments {
+                        text.push(' ');
+                        append_plain_text(argument, text);
+                    }
+                }
+            } else if let Some(Value::String(key)) = fields.get("keybind") {
+                text.push_str(key);
+            }
+            if let Some(extra) = fields.get("extra") {
+                append_plain_text(extra, text);
+            }
+        }
+        Value::Number(number) => text.push_str(&number.to_string()),
+        Value::Bool(value) => text.push_str(&value.to_string()),
+        Value::Null => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_comes_before_its_extra_children() {
+        let json =
+            r#"{"extra":[{"text":"b","extra":["c"]},{"color":"red","text":"d"}],"text":"a"}"#;
+        assert_eq!(chat_to_plain_text(json), "abcd");
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        let json = r#"{"text":"caf\u00e9 \"quoted\"\n\ud83d\ude00"}"#;
+        assert_eq!(chat_to_plain_text(json), "café \"quoted\"\n\u{1f600}");
+    }
+
+    #[test]
+    fn translated_text_shows_its_key_and_arguments() {
+        let json = r#"{"translate":"chat.type.text","with":[{"text":"Steve"},"hello"]}"#;
+        assert_eq!(chat_to_plain_text(json), "chat.type.text Steve hello");
+    }
+
+    #[test]
+    fn plain_strings_and_other_text_are_kept() {
+        assert_eq!(chat_to_plain_text(r#""Steve""#), "Steve");
+        assert_eq!(chat_to_plain_text("Steve"), "Steve");
+        assert_eq!(chat_to_plain_text(r#"["a",{"text":"b"}]"#), "ab");
+    }
+}
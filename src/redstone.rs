@@ -0,0 +1,1524 @@
+use crate::block::{Block, Direction};
+use crate::nbt::Tag;
+use crate::world::{BlockPos, ChunkPos, World, SECTIONS_PER_CHUNK, SECTION_SIZE};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+/// Redstone ticks per second at normal speed, each two game ticks long.
+pub const TICKS_PER_SECOND: f32 = 10.0;
+
+// More ticks than this in one frame are dropped rather than letting a slow
+// frame make the next one slower still.
+const MAX_TICKS_PER_UPDATE: u32 = 100;
+
+// How many blocks a piston can push.
+const PUSH_LIMIT: usize = 12;
+
+const HORIZONTAL: [Direction; 4] = [
+    Direction::North,
+    Direction::South,
+    Direction::West,
+    Direction::East,
+];
+
+/// The state of a block that takes part in a circuit, as far as the
+/// simulation is concerned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Component {
+    Wire {
+        power: u8,
+    },
+    Torch {
+        lit: bool,
+        // The side of the torch holding it up.
+        attached: Direction,
+    },
+    Repeater {
+        // Diodes face their input, so their output is the opposite side.
+        facing: Direction,
+        delay: u8,
+        powered: bool,
+        locked: bool,
+    },
+    Comparator {
+        facing: Direction,
+        subtract: bool,
+        // Only whether this is above zero is kept in the block state, the
+        // strength itself is in the block entity.
+        output: u8,
+    },
+    Lamp {
+        lit: bool,
+    },
+    Lever {
+        attached: Direction,
+        powered: bool,
+    },
+    Button {
+        attached: Direction,
+        powered: bool,
+        wooden: bool,
+    },
+    Observer {
+        // The side being watched, the output is the opposite side.
+        facing: Direction,
+        powered: bool,
+    },
+    Piston {
+        facing: Direction,
+        extended: bool,
+        sticky: bool,
+    },
+    RedstoneBlock,
+}
+
+impl Component {
+    fn from_block(block: Block) -> Option<Component> {
+        match block {
+            Block::RedstoneWire { power } => return Some(Component::Wire { power }),
+            Block::RedstoneTorch { lit } => {
+                return Some(Component::Torch {
+                    lit,
+                    attached: Direction::Down,
+                })
+            }
+            Block::RedstoneLamp { lit } => return Some(Component::Lamp { lit }),
+            Block::RedstoneBlock => return Some(Component::RedstoneBlock),
+            Block::Other(_) => {}
+            _ => return None,
+        }
+
+        let (name, properties) = block.name_and_properties();
+        let property = |key: &str| {
+            properties
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        let flag = |key: &str| property(key) == Some("true");
+        let facing = property("facing").and_then(Direction::from_name);
+        // Levers and buttons can be on the floor, the ceiling or a wall.
+        let attached = || match property("face") {
+            Some("floor") => Some(Direction::Down),
+            Some("ceiling") => Some(Direction::Up),
+            _ => facing.map(Direction::opposite),
+        };
+
+        let name = name.trim_start_matches("minecraft:");
+        let component = match name {
+            "redstone_wall_torch" => Component::Torch {
+                lit: property("lit") != Some("false"),
+                attached: facing?.opposite(),
+            },
+            "repeater" => Component::Repeater {
+                facing: facing?,
+                delay: property("delay")
+                    .and_then(|delay| delay.parse().ok())
+                    .unwrap_or(1u8)
+                    .clamp(1, 4),
+                powered: flag("powered"),
+                locked: flag("locked"),
+            },
+            "comparator" => Component::Comparator {
+                facing: facing?,
+                subtract: property("mode") == Some("subtract"),
+                output: if flag("powered") { 15 } else { 0 },
+            },
+            "lever" => Component::Lever {
+                attached: attached()?,
+                powered: flag("powered"),
+            },
+            "observer" => Component::Observer {
+                facing: facing?,
+                powered: flag("powered"),
+            },
+            "piston" | "sticky_piston" => Component::Piston {
+                facing: facing?,
+                extended: flag("extended"),
+                sticky: name == "sticky_piston",
+            },
+            _ if name.ends_with("_button") => Component::Button {
+                attached: attached()?,
+                powered: flag("powered"),
+                wooden: name != "stone_button" && name != "polished_blackstone_button",
+            },
+            _ => return None,
+        };
+        Some(component)
+    }
+
+    /// Takes the parts of the component kept outside the block state from
+    /// the block's block entity, which is only a comparator's output.
+    fn with_block_entity(self, block_entity: Option<&Tag>) -> Component {
+        let output = block_entity
+            .and_then(|data| data.get("OutputSignal"))
+            .and_then(Tag::as_i32);
+        match (self, output) {
+            (
+                Component::Comparator {
+                    facing, subtract, ..
+                },
+                Some(output),
+            ) => Component::Comparator {
+                facing,
+                subtract,
+                output: output.clamp(0, 15) as u8,
+            },
+            _ => self,
+        }
+    }
+
+    /// Writes the parts of the component kept in the block state back into
+    /// a block.
+    fn apply_to(self, block: Block) -> Block {
+        match self {
+            Component::Wire { power } => Block::RedstoneWire { power },
+            Component::Torch { lit, .. } => block.with_property("lit", &lit.to_string()),
+            Component::Lamp { lit } => Block::RedstoneLamp { lit },
+            Component::Repeater {
+                powered, locked, ..
+            } => block
+                .with_property("powered", &powered.to_string())
+                .with_property("locked", &locked.to_string()),
+            Component::Comparator { output, .. } => {
+                block.with_property("powered", &(output > 0).to_string())
+            }
+            Component::Lever { powered, .. }
+            | Component::Button { powered, .. }
+            | Component::Observer { powered, .. } => {
+                block.with_property("powered", &powered.to_string())
+            }
+            Component::Piston { extended, .. } => {
+                block.with_property("extended", &extended.to_string())
+            }
+            Component::RedstoneBlock => block,
+        }
+    }
+
+    /// Lamps are the only components that also pass power on the way
+    /// ordinary solid blocks do.
+    fn conducts(self) -> bool {
+        matches!(self, Component::Lamp { .. })
+    }
+}
+
+/// Whether wire with the given connections points along a horizontal
+/// side. Wire with fewer than two connections points in a line, or
+/// everywhere if it is a lone dot.
+fn points_along(connections: &[Direction], toward: Direction) -> bool {
+    match connections.len() {
+        0 => true,
+        1 => connections[0] == toward || connections[0] == toward.opposite(),
+        _ => connections.contains(&toward),
+    }
+}
+
+/// Keeps a comparator's output strength in its block entity, where the
+/// game keeps it, so that it is shown and saved along with the block.
+fn set_output_signal(world: &mut World, pos: BlockPos, output: u8) {
+    let block_entity = world.get_block_entity(pos);
+    let current = block_entity
+        .and_then(|data| data.get("OutputSignal"))
+        .and_then(Tag::as_i32);
+    if current == Some(output as i32) {
+        return;
+    }
+    let mut data = block_entity
+        .and_then(Tag::as_compound)
+        .cloned()
+        .unwrap_or_default();
+    data.entry("id".to_owned())
+        .or_insert_with(|| Tag::String("minecraft:comparator".to_owned()));
+    data.insert("x".to_owned(), Tag::Int(pos.x));
+    data.insert("y".to_owned(), Tag::Int(pos.y));
+    data.insert("z".to_owned(), Tag::Int(pos.z));
+    data.insert("OutputSignal".to_owned(), Tag::Int(output as i32));
+    world.insert_block_entity(Tag::Compound(data));
+}
+
+/// The two sides of a diode, which lock repeaters and feed comparators.
+fn sides(facing: Direction) -> [Direction; 2] {
+    match facing {
+        Direction::North | Direction::South => [Direction::West, Direction::East],
+        _ => [Direction::North, Direction::South],
+    }
+}
+
+/// A local simulation of redstone, for watching circuits work without a
+/// server. Components are read out of the world as chunks arrive and their
+/// changes written back, so that they are drawn like any other block.
+///
+/// Timing follows vanilla in redstone ticks: torches and comparators take
+/// one, repeaters one to four, lamps two to turn off, and wire updates
+/// instantly. Quasi-connectivity and block update order are not modelled.
+pub struct RedstoneSimulation {
+    components: BTreeMap<BlockPos, Component>,
+    scanned_chunks: HashSet<ChunkPos>,
+    // The components read out of the states of blocks we don't model, and
+    // the blocks components were written back into, so that neither goes
+    // through the state strings more than once.
+    parsed: HashMap<Block, Option<Component>>,
+    applied: HashMap<(Component, Block), Block>,
+    // Scheduled updates keyed by the tick they are due in, then the order
+    // they were scheduled in, which keeps runs deterministic.
+    scheduled: BTreeMap<(u64, u64), BlockPos>,
+    pending: HashSet<BlockPos>,
+    schedule_count: u64,
+    tick: u64,
+    speed: f32,
+    accumulator: f32,
+}
+
+impl RedstoneSimulation {
+    pub fn new(speed: f32) -> RedstoneSimulation {
+        RedstoneSimulation {
+            components: BTreeMap::new(),
+            scanned_chunks: HashSet::new(),
+            parsed: HashMap::new(),
+            applied: HashMap::new(),
+            scheduled: BTreeMap::new(),
+            pending: HashSet::new(),
+            schedule_count: 0,
+            tick: 0,
+            speed,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Runs however many redstone ticks are due after `delta` seconds,
+    /// calling `on_tick` with the world after each one.
+    pub fn update<F: FnMut(&World)>(&mut self, delta: f32, world: &mut World, mut on_tick: F) {
+        self.sync_chunks(world);
+
+        self.accumulator += delta * TICKS_PER_SECOND * self.speed;
+        let mut ticks = 0;
+        while self.accumulator >= 1.0 {
+            self.accumulator -= 1.0;
+            if ticks < MAX_TICKS_PER_UPDATE {
+                self.tick(world);
+                on_tick(world);
+                ticks += 1;
+            }
+        }
+    }
+
+    /// Picks up the components of newly loaded chunks and forgets those of
+    /// unloaded ones.
+    pub fn sync_chunks(&mut self, world: &World) {
+        let loaded: HashSet<ChunkPos> = world.chunk_positions().collect();
+        if loaded == self.scanned_chunks {
+            return;
+        }
+
+        self.components
+            .retain(|pos, _| loaded.contains(&pos.chunk_pos()));
+        let new_chunks: Vec<ChunkPos> = loaded.difference(&self.scanned_chunks).copied().collect();
+        for pos in new_chunks {
+            let chunk = match world.get_chunk(pos) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+            for y in 0..SECTIONS_PER_CHUNK as i32 * SECTION_SIZE {
+                for z in 0..SECTION_SIZE {
+                    for x in 0..SECTION_SIZE {
+                        if let Some(component) = self.parse(chunk.get_block(x, y, z)) {
+                            let component =
+                                component.with_block_entity(chunk.get_block_entity(x, y, z));
+                            let block_pos = BlockPos::new(
+                                pos.x * SECTION_SIZE + x,
+                                y,
+                                pos.z * SECTION_SIZE + z,
+                            );
+                            self.components.insert(block_pos, component);
+                        }
+                    }
+                }
+            }
+        }
+        self.scanned_chunks = loaded;
+    }
+
+    fn parse(&mut self, block: Block) -> Option<Component> {
+        match block {
+            Block::Other(_) => *self
+                .parsed
+                .entry(block)
+                .or_insert_with(|| Component::from_block(block)),
+            _ => Component::from_block(block),
+        }
+    }
+
+    /// Advances the simulation by one redstone tick.
+    pub fn tick(&mut self, world: &mut World) {
+        self.tick += 1;
+
+        let due: Vec<(u64, u64)> = self
+            .scheduled
+            .range(..(self.tick + 1, 0))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in due {
+            let pos = self.scheduled.remove(&key).unwrap();
+            self.pending.remove(&pos);
+            self.run_scheduled(world, pos);
+        }
+
+        self.update_wires(world);
+        self.update_components(world);
+    }
+
+    /// Flips a lever or presses a button, returning false if the block is
+    /// neither.
+    pub fn use_block(&mut self, world: &mut World, pos: BlockPos) -> bool {
+        match self.components.get(&pos).copied() {
+            Some(Component::Lever { attached, powered }) => {
+                self.set_component(
+                    world,
+                    pos,
+                    Component::Lever {
+                        attached,
+                        powered: !powered,
+                    },
+                );
+                true
+            }
+            Some(Component::Button {
+                attached,
+                powered: false,
+                wooden,
+            }) => {
+                self.set_component(
+                    world,
+                    pos,
+                    Component::Button {
+                        attached,
+                        powered: true,
+                        wooden,
+                    },
+                );
+                self.schedule(pos, if wooden { 15 } else { 10 });
+                true
+            }
+            Some(Component::Button { .. }) => true,
+            _ => false,
+        }
+    }
+
+    fn schedule(&mut self, pos: BlockPos, delay: u64) {
+        if self.pending.insert(pos) {
+            self.scheduled
+                .insert((self.tick + delay, self.schedule_count), pos);
+            self.schedule_count += 1;
+        }
+    }
+
+    fn set_component(&mut self, world: &mut World, pos: BlockPos, component: Component) {
+        self.components.insert(pos, component);
+        let block = world.get_block(pos);
+        let block = *self
+            .applied
+            .entry((component, block))
+            .or_insert_with(|| component.apply_to(block));
+        self.set_block(world, pos, block);
+        if let Component::Comparator { output, .. } = component {
+            set_output_signal(world, pos, output);
+        }
+    }
+
+    /// Changes a block in the world, letting any observer watching it know.
+    fn set_block(&mut self, world: &mut World, pos: BlockPos, block: Block) {
+        if world.get_block(pos) == block {
+            return;
+        }
+        world.set_block(pos, block);
+
+        for direction in Direction::ALL.iter() {
+            let observer = pos.relative(*direction);
+            if let Some(Component::Observer { facing, .. }) = self.components.get(&observer) {
+                if *facing == direction.opposite() {
+                    self.schedule(observer, 1);
+                }
+            }
+        }
+    }
+
+    /// The component at a position. Chunks the simulation hasn't taken in
+    /// are read straight from the world, so that circuits can be looked
+    /// at without simulating them.
+    fn component(&self, world: &World, pos: BlockPos) -> Option<Component> {
+        if self.scanned_chunks.contains(&pos.chunk_pos()) {
+            self.components.get(&pos).copied()
+        } else {
+            Component::from_block(world.get_block(pos))
+                .map(|component| component.with_block_entity(world.get_block_entity(pos)))
+        }
+    }
+
+    /// Whether a position holds a block that passes power through it.
+    fn conducts(&self, world: &World, pos: BlockPos) -> bool {
+        match self.component(world, pos) {
+            Some(component) => component.conducts(),
+            None => world.get_block(pos).is_opaque(),
+        }
+    }
+
+    fn is_wire(&self, world: &World, pos: BlockPos) -> bool {
+        matches!(self.component(world, pos), Some(Component::Wire { .. }))
+    }
+
+    /// The horizontal sides a wire connects to, either to other wire or
+    /// to components that wire visibly joins up with.
+    fn wire_connections(&self, world: &World, pos: BlockPos) -> Vec<Direction> {
+        let above_conducts = self.conducts(world, pos.relative(Direction::Up));
+        HORIZONTAL
+            .iter()
+            .copied()
+            .filter(|direction| {
+                let neighbour = pos.relative(*direction);
+                let connects = match self.component(world, neighbour) {
+                    Some(Component::Repeater { facing, .. }) => {
+                        facing == *direction || facing == direction.opposite()
+                    }
+                    Some(Component::Observer { facing, .. }) => facing == *direction,
+                    Some(Component::Lamp { .. }) | Some(Component::Piston { .. }) => false,
+                    Some(_) => true,
+                    None => false,
+                };
+                connects
+                    || (!above_conducts && self.is_wire(world, neighbour.relative(Direction::Up)))
+                    || (!self.conducts(world, neighbour)
+                        && self.is_wire(world, neighbour.relative(Direction::Down)))
+            })
+            .collect()
+    }
+
+    /// Whether wire powers the block on one side of it. It always powers
+    /// the block it sits on.
+    fn wire_points(&self, world: &World, pos: BlockPos, toward: Direction) -> bool {
+        match toward {
+            Direction::Down => true,
+            Direction::Up => false,
+            _ => points_along(&self.wire_connections(world, pos), toward),
+        }
+    }
+
+    /// The full block state of the wire at a position, with the sides it
+    /// joins up with worked out from its neighbours. The world only keeps
+    /// wire's power, but wire placed without block updates, like when a
+    /// schematic is pasted, needs its sides too.
+    pub fn wire_state(&self, world: &World, pos: BlockPos) -> String {
+        let power = match world.get_block(pos) {
+            Block::RedstoneWire { power } => power,
+            _ => 0,
+        };
+        let connections = self.wire_connections(world, pos);
+        let above_conducts = self.conducts(world, pos.relative(Direction::Up));
+        let side = |direction: Direction| {
+            let neighbour = pos.relative(direction);
+            if !points_along(&connections, direction) {
+                "none"
+            } else if !above_conducts
+                && self.conducts(world, neighbour)
+                && self.is_wire(world, neighbour.relative(Direction::Up))
+            {
+                "up"
+            } else {
+                "side"
+            }
+        };
+        format!(
+            "minecraft:redstone_wire[east={},north={},power={},south={},west={}]",
+            side(Direction::East),
+            side(Direction::North),
+            power,
+            side(Direction::South),
+            side(Direction::West)
+        )
+    }
+
+    /// The power a component sends out on one side, as the strength it
+    /// strongly powers a block there with and the strength it sends
+    /// straight into a component there. Torches only strongly power the
+    /// block above them, levers and buttons the block they are on, and
+    /// redstone blocks no block at all.
+    fn emitted(&self, world: &World, pos: BlockPos, toward: Direction) -> (u8, u8) {
+        let component = match self.component(world, pos) {
+            Some(component) => component,
+            None => return (0, 0),
+        };
+        match component {
+            Component::Wire { power } if power > 0 && self.wire_points(world, pos, toward) => {
+                (0, power)
+            }
+            Component::Torch {
+                lit: true,
+                attached,
+            } if toward != attached => {
+                if toward == Direction::Up {
+                    (15, 15)
+                } else {
+                    (0, 15)
+                }
+            }
+            Component::Repeater {
+                facing,
+                powered: true,
+                ..
+            } if toward == facing.opposite() => (15, 15),
+            Component::Comparator { facing, output, .. } if toward == facing.opposite() => {
+                (output, output)
+            }
+            Component::Lever {
+                attached,
+                powered: true,
+            }
+            | Component::Button {
+                attached,
+                powered: true,
+                ..
+            } => {
+                if toward == attached {
+                    (15, 15)
+                } else {
+                    (0, 15)
+                }
+            }
+            Component::Observer {
+                facing,
+                powered: true,
+            } if toward == facing.opposite() => (15, 15),
+            Component::RedstoneBlock => (0, 15),
+            _ => (0, 0),
+        }
+    }
+
+    /// How strongly a solid block is powered by the components around it.
+    /// Wire pointing into a block only weakly powers it, and wire only
+    /// takes power from strongly powered blocks.
+    fn block_power(&self, world: &World, pos: BlockPos, strong_only: bool) -> u8 {
+        Direction::ALL
+            .iter()
+            .map(|direction| {
+                let neighbour = pos.relative(*direction);
+                let (strong, weak) = self.emitted(world, neighbour, direction.opposite());
+                if !strong_only && self.is_wire(world, neighbour) {
+                    weak
+                } else {
+                    strong
+                }
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The power a component takes in from its neighbour on one side,
+    /// whether sent straight into it or through a solid block.
+    fn input(&self, world: &World, pos: BlockPos, side: Direction) -> u8 {
+        let neighbour = pos.relative(side);
+        let (strong, weak) = self.emitted(world, neighbour, side.opposite());
+        let mut power = strong.max(weak);
+        if self.conducts(world, neighbour) {
+            power = power.max(self.block_power(world, neighbour, false));
+        }
+        power
+    }
+
+    /// The power going into the side of a comparator, which only comes
+    /// straight from signal sources next to it and never through solid
+    /// blocks.
+    fn side_input(&self, world: &World, pos: BlockPos, side: Direction) -> u8 {
+        let neighbour = pos.relative(side);
+        match self.component(world, neighbour) {
+            // Wire feeds the side whichever way it points.
+            Some(Component::Wire { power }) => power,
+            Some(Component::Lamp { .. }) | Some(Component::Piston { .. }) | None => 0,
+            Some(_) => {
+                let (strong, weak) = self.emitted(world, neighbour, side.opposite());
+                strong.max(weak)
+            }
+        }
+    }
+
+    fn comparator_output(
+        &self,
+        world: &World,
+        pos: BlockPos,
+        facing: Direction,
+        subtract: bool,
+    ) -> u8 {
+        let rear = self.input(world, pos, facing);
+        let side = sides(facing)
+            .iter()
+            .map(|side| self.side_input(world, pos, *side))
+            .max()
+            .unwrap_or(0);
+        if subtract {
+            rear.saturating_sub(side)
+        } else if rear >= side {
+            rear
+        } else {
+            0
+        }
+    }
+
+    fn is_locked(&self, world: &World, pos: BlockPos, facing: Direction) -> bool {
+        sides(facing).iter().any(|side| {
+            let neighbour = pos.relative(*side);
+            match self.components.get(&neighbour) {
+                Some(Component::Repeater { .. }) | Some(Component::Comparator { .. }) => {
+                    self.emitted(world, neighbour, side.opposite()).0 > 0
+                }
+                _ => false,
+            }
+        })
+    }
+
+    fn is_powered(&self, world: &World, pos: BlockPos, except: Option<Direction>) -> bool {
+        Direction::ALL
+            .iter()
+            .filter(|side| Some(**side) != except)
+            .any(|side| self.input(world, pos, *side) > 0)
+    }
+
+    /// Works out the power of all wire at once. Each piece takes the
+    /// strongest power sent into it from outside, which then spreads
+    /// along connected wire, dropping by one per block.
+    fn update_wires(&mut self, world: &mut World) {
+        let wires: Vec<BlockPos> = self
+            .components
+            .iter()
+            .filter(|(_, component)| matches!(component, Component::Wire { .. }))
+            .map(|(pos, _)| *pos)
+            .collect();
+
+        let mut power: HashMap<BlockPos, u8> = HashMap::new();
+        let mut levels: Vec<Vec<BlockPos>> = vec![Vec::new(); 16];
+        for pos in wires.iter() {
+            let mut source = 0;
+            for direction in Direction::ALL.iter() {
+                let neighbour = pos.relative(*direction);
+                if self.is_wire(world, neighbour) {
+                    continue;
+                }
+                let (strong, weak) = self.emitted(world, neighbour, direction.opposite());
+                source = source.max(strong).max(weak);
+                if self.conducts(world, neighbour) {
+                    source = source.max(self.block_power(world, neighbour, true));
+                }
+            }
+            power.insert(*pos, source);
+            levels[source as usize].push(*pos);
+        }
+
+        for level in (1..16).rev() {
+            let positions = std::mem::take(&mut levels[level]);
+            for pos in positions {
+                if power[&pos] as usize != level {
+                    continue;
+                }
+                for neighbour in self.linked_wires(world, pos) {
+                    let neighbour_power = power.get_mut(&neighbour).unwrap();
+                    if (*neighbour_power as usize) < level - 1 {
+                        *neighbour_power = level as u8 - 1;
+                        levels[level - 1].push(neighbour);
+                    }
+                }
+            }
+        }
+
+        for pos in wires {
+            let new_power = power[&pos];
+            if self.components.get(&pos) != Some(&Component::Wire { power: new_power }) {
+                self.set_component(world, pos, Component::Wire { power: new_power });
+            }
+        }
+    }
+
+    /// Wire that power flows to from a piece of wire, including up and
+    /// down steps that aren't cut off by a solid block.
+    fn linked_wires(&self, world: &World, pos: BlockPos) -> Vec<BlockPos> {
+        let above_conducts = self.conducts(world, pos.relative(Direction::Up));
+        let mut linked = Vec::new();
+        for direction in HORIZONTAL.iter() {
+            let neighbour = pos.relative(*direction);
+            if self.is_wire(world, neighbour) {
+                linked.push(neighbour);
+            }
+            let up = neighbour.relative(Direction::Up);
+            if !above_conducts && self.is_wire(world, up) {
+                linked.push(up);
+            }
+            let down = neighbour.relative(Direction::Down);
+            if !self.conducts(world, neighbour) && self.is_wire(world, down) {
+                linked.push(down);
+            }
+        }
+        linked
+    }
+
+    /// Checks every component against its inputs, scheduling those that
+    /// need to change.
+    fn update_components(&mut self, world: &mut World) {
+        let positions: Vec<BlockPos> = self.components.keys().copied().collect();
+        for pos in positions {
+            let component = match self.components.get(&pos) {
+                Some(component) => *component,
+                None => continue,
+            };
+            match component {
+                Component::Torch { lit, attached } => {
+                    let attached_power = self.input(world, pos, attached) > 0;
+                    if lit == attached_power {
+                        self.schedule(pos, 1);
+                    }
+                }
+                Component::Repeater {
+                    facing,
+                    delay,
+                    powered,
+                    locked,
+                } => {
+                    let now_locked = self.is_locked(world, pos, facing);
+                    if now_locked != locked {
+                        self.set_component(
+                            world,
+                            pos,
+                            Component::Repeater {
+                                facing,
+                                delay,
+                                powered,
+                                locked: now_locked,
+                            },
+                        );
+                    }
+                    let input = self.input(world, pos, facing) > 0;
+                    if !now_locked && input != powered {
+                        self.schedule(pos, delay as u64);
+                    }
+                }
+                Component::Comparator {
+                    facing,
+                    subtract,
+                    output,
+                } if self.comparator_output(world, pos, facing, subtract) != output => {
+                    self.schedule(pos, 1);
+                }
+                Component::Lamp { lit } => {
+                    let powered = self.is_powered(world, pos, None);
+                    if powered && !lit {
+                        self.set_component(world, pos, Component::Lamp { lit: true });
+                    } else if !powered && lit {
+                        self.schedule(pos, 2);
+                    }
+                }
+                Component::Piston {
+                    facing,
+                    extended,
+                    sticky,
+                } if self.is_powered(world, pos, Some(facing)) != extended => {
+                    self.move_piston(world, pos, facing, !extended, sticky);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn run_scheduled(&mut self, world: &mut World, pos: BlockPos) {
+        let component = match self.components.get(&pos) {
+            Some(component) => *component,
+            None => return,
+        };
+        match component {
+            Component::Torch { lit, attached } => {
+                let lit_now = self.input(world, pos, attached) == 0;
+                if lit_now != lit {
+                    self.set_component(
+                        world,
+                        pos,
+                        Component::Torch {
+                            lit: lit_now,
+                            attached,
+                        },
+                    );
+                }
+            }
+            Component::Repeater {
+                facing,
+                delay,
+                powered,
+                locked: false,
+            } => {
+                // Once a repeater has started turning on it finishes, which
+                // stretches short pulses out to its delay.
+                let powered_now = !powered || self.input(world, pos, facing) > 0;
+                if powered_now != powered {
+                    self.set_component(
+                        world,
+                        pos,
+                        Component::Repeater {
+                            facing,
+                            delay,
+                            powered: powered_now,
+                            locked: false,
+                        },
+                    );
+                }
+            }
+            Component::Comparator {
+                facing, subtract, ..
+            } => {
+                let output = self.comparator_output(world, pos, facing, subtract);
+                self.set_component(
+                    world,
+                    pos,
+                    Component::Comparator {
+                        facing,
+                        subtract,
+                        output,
+                    },
+                );
+            }
+            Component::Lamp { lit: true } if !self.is_powered(world, pos, None) => {
+                self.set_component(world, pos, Component::Lamp { lit: false });
+            }
+            Component::Button {
+                attached,
+                powered: true,
+                wooden,
+            } => {
+                self.set_component(
+                    world,
+                    pos,
+                    Component::Button {
+                        attached,
+                        powered: false,
+                        wooden,
+                    },
+                );
+            }
+            Component::Observer { facing, powered } => {
+                self.set_component(
+                    world,
+                    pos,
+                    Component::Observer {
+                        facing,
+                        powered: !powered,
+                    },
+                );
+                // An observer sends out a pulse one tick long.
+                if !powered {
+                    self.schedule(pos, 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_movable(&self, world: &World, pos: BlockPos) -> bool {
+        if let Some(Component::Piston { extended: true, .. }) = self.components.get(&pos) {
+            return false;
+        }
+        let (name, _) = world.get_block(pos).name_and_properties();
+        !matches!(
+            name.as_str(),
+            "minecraft:obsidian"
+                | "minecraft:bedrock"
+                | "minecraft:barrier"
+                | "minecraft:piston_head"
+                | "minecraft:moving_piston"
+        )
+    }
+
+    /// Moves a block by one, taking its component with it.
+    fn move_block(&mut self, world: &mut World, from: BlockPos, to: BlockPos) {
+        let block = world.get_block(from);
+        self.set_block(world, to, block);
+        self.set_block(world, from, Block::Air);
+        self.pending.remove(&from);
+        self.scheduled.retain(|_, pos| *pos != from);
+        match self.components.remove(&from) {
+            Some(component) => self.components.insert(to, component),
+            None => self.components.remove(&to),
+        };
+    }
+
+    /// Extends or retracts a piston. Extending fails if there are more
+    /// blocks in the way than a piston can push, or one that can't move.
+    fn move_piston(
+        &mut self,
+        world: &mut World,
+        pos: BlockPos,
+        facing: Direction,
+        extend: bool,
+        sticky: bool,
+    ) {
+        let head = pos.relative(facing);
+        if extend {
+            let mut pushed = Vec::new();
+            let mut next = head;
+            while !world.get_block(next).is_air() {
+                if pushed.len() == PUSH_LIMIT || !self.is_movable(world, next) {
+                    return;
+                }
+                pushed.push(next);
+                next = next.relative(facing);
+            }
+            for from in pushed.iter().rev() {
+                self.move_block(world, *from, from.relative(facing));
+            }
+            let head_block = Block::from_state(&format!(
+                "minecraft:piston_head[facing={},short=false,type={}]",
+                direction_name(facing),
+                if sticky { "sticky" } else { "normal" }
+            ));
+            self.set_block(world, head, head_block);
+        } else {
+            let (name, _) = world.get_block(head).name_and_properties();
+            if name == "minecraft:piston_head" {
+                self.set_block(world, head, Block::Air);
+            }
+            let pulled = head.relative(facing);
+            if sticky && !world.get_block(pulled).is_air() && self.is_movable(world, pulled) {
+                self.move_block(world, pulled, head);
+            }
+        }
+        self.set_component(
+            world,
+            pos,
+            Component::Piston {
+                facing,
+                extended: extend,
+                sticky,
+            },
+        );
+    }
+}
+
+/// A run of connected wire, with the components feeding power into it
+/// and those it powers.
+pub struct Network {
+    pub wires: BTreeSet<BlockPos>,
+    /// Solid blocks power passes through on its way in or out.
+    pub blocks: BTreeSet<BlockPos>,
+    pub inputs: BTreeSet<BlockPos>,
+    pub outputs: BTreeSet<BlockPos>,
+}
+
+impl Network {
+    pub fn is_empty(&self) -> bool {
+        self.wires.is_empty()
+    }
+}
+
+/// Whether a component can send power out of one side, whatever state it
+/// is in right now. Only strong power passes through solid blocks into
+/// wire.
+fn can_output(component: Component, toward: Direction, strong_only: bool) -> bool {
+    match component {
+        Component::Torch { attached, .. } => {
+            if strong_only {
+                toward == Direction::Up
+            } else {
+                toward != attached
+            }
+        }
+        Component::Repeater { facing, .. }
+        | Component::Comparator { facing, .. }
+        | Component::Observer { facing, .. } => toward == facing.opposite(),
+        Component::Lever { attached, .. } | Component::Button { attached, .. } => {
+            !strong_only || toward == attached
+        }
+        Component::RedstoneBlock => !strong_only,
+        _ => false,
+    }
+}
+
+/// Whether a component takes power in from one side, either straight from
+/// wire or through a solid block.
+fn can_input(component: Component, side: Direction, through_block: bool) -> bool {
+    match component {
+        Component::Torch { attached, .. } => through_block && side == attached,
+        Component::Repeater { facing, .. } => side == facing,
+        Component::Comparator { facing, .. } => {
+            side == facing || (!through_block && sides(facing).contains(&side))
+        }
+        Component::Lamp { .. } => true,
+        Component::Piston { facing, .. } => side != facing,
+        _ => false,
+    }
+}
+
+impl RedstoneSimulation {
+    /// Finds the wire network a block belongs to: the wire itself, or the
+    /// wire next to a component or block. Components are sorted into
+    /// inputs and outputs by which way they face, not by whether they are
+    /// powered right now, so that broken circuits can be traced too.
+    pub fn trace_network(&self, world: &World, start: BlockPos) -> Network {
+        let mut network = Network {
+            wires: BTreeSet::new(),
+            blocks: BTreeSet::new(),
+            inputs: BTreeSet::new(),
+            outputs: BTreeSet::new(),
+        };
+
+        let mut queue = VecDeque::new();
+        if self.is_wire(world, start) {
+            queue.push_back(start);
+        } else {
+            for direction in Direction::ALL.iter() {
+                let neighbour = start.relative(*direction);
+                if self.is_wire(world, neighbour) {
+                    queue.push_back(neighbour);
+                } else if self.conducts(world, neighbour) && !self.conducts(world, start) {
+                    // A component powering wire through a block.
+                    for side in Direction::ALL.iter() {
+                        let beyond = neighbour.relative(*side);
+                        if self.is_wire(world, beyond) {
+                            queue.push_back(beyond);
+                        }
+                    }
+                }
+            }
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            if network.wires.insert(pos) {
+                queue.extend(self.linked_wires(world, pos));
+            }
+        }
+
+        for wire in network.wires.iter() {
+            for direction in Direction::ALL.iter() {
+                let neighbour = wire.relative(*direction);
+                let points = self.wire_points(world, *wire, *direction);
+                match self.component(world, neighbour) {
+                    Some(Component::Wire { .. }) => {}
+                    Some(component) => {
+                        if can_output(component, direction.opposite(), false) {
+                            network.inputs.insert(neighbour);
+                        }
+                        if points && can_input(component, direction.opposite(), false) {
+                            network.outputs.insert(neighbour);
+                        }
+                    }
+                    None if self.conducts(world, neighbour) => {
+                        for side in Direction::ALL.iter() {
+                            let beyond = neighbour.relative(*side);
+                            if beyond == *wire {
+                                continue;
+                            }
+                            let component = match self.component(world, beyond) {
+                                Some(Component::Wire { .. }) | None => continue,
+                                Some(component) => component,
+                            };
+                            if can_output(component, side.opposite(), true) {
+                                network.inputs.insert(beyond);
+                                network.blocks.insert(neighbour);
+                            }
+                            if points && can_input(component, side.opposite(), true) {
+                                network.outputs.insert(beyond);
+                                network.blocks.insert(neighbour);
+                            }
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+        network
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Down => "down",
+        Direction::Up => "up",
+        Direction::North => "north",
+        Direction::South => "south",
+        Direction::West => "west",
+        Direction::East => "east",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_with(blocks: &[(i32, i32, i32, &str)]) -> (World, RedstoneSimulation) {
+        let mut world = World::new();
+        for (x, y, z, state) in blocks {
+            world.set_block(BlockPos::new(*x, *y, *z), Block::from_state(state));
+        }
+        let mut simulation = RedstoneSimulation::new(1.0);
+        simulation.sync_chunks(&world);
+        (world, simulation)
+    }
+
+    fn block(world: &World, x: i32, y: i32, z: i32) -> Block {
+        world.get_block(BlockPos::new(x, y, z))
+    }
+
+    fn property(world: &World, x: i32, y: i32, z: i32, key: &str) -> Option<String> {
+        let (_, properties) = block(world, x, y, z).name_and_properties();
+        properties
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    fn output_signal(world: &World, x: i32, y: i32, z: i32) -> Option<i32> {
+        world
+            .get_block_entity(BlockPos::new(x, y, z))?
+            .get("OutputSignal")?
+            .as_i32()
+    }
+
+    fn run(simulation: &mut RedstoneSimulation, world: &mut World, ticks: u32) {
+        for _ in 0..ticks {
+            simulation.tick(world);
+        }
+    }
+
+    #[test]
+    fn wire_loses_one_power_per_block() {
+        let mut blocks = vec![
+            (0, 0, 0, "stone"),
+            (0, 1, 0, "lever[face=floor,facing=north,powered=false]"),
+        ];
+        for x in 1..=16 {
+            blocks.push((x, 0, 0, "stone"));
+            blocks.push((x, 1, 0, "redstone_wire"));
+        }
+        let (mut world, mut simulation) = world_with(&blocks);
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(block(&world, 1, 1, 0), Block::RedstoneWire { power: 0 });
+
+        assert!(simulation.use_block(&mut world, BlockPos::new(0, 1, 0)));
+        run(&mut simulation, &mut world, 1);
+        for x in 1..=15 {
+            assert_eq!(
+                block(&world, x, 1, 0),
+                Block::RedstoneWire {
+                    power: 16 - x as u8
+                }
+            );
+        }
+        assert_eq!(block(&world, 16, 1, 0), Block::RedstoneWire { power: 0 });
+
+        simulation.use_block(&mut world, BlockPos::new(0, 1, 0));
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(block(&world, 1, 1, 0), Block::RedstoneWire { power: 0 });
+    }
+
+    /// Whether a lamp lights up with a stone block between it and a
+    /// source, which only happens if the source powers the stone.
+    fn lights_lamp_through_block(source: &str) -> bool {
+        let (mut world, mut simulation) = world_with(&[
+            (0, 0, 0, "redstone_lamp[lit=false]"),
+            (1, 0, 0, "stone"),
+            (2, 0, 0, source),
+        ]);
+        run(&mut simulation, &mut world, 3);
+        block(&world, 0, 0, 0) == Block::RedstoneLamp { lit: true }
+    }
+
+    #[test]
+    fn torch_does_not_power_block_beside_it() {
+        assert!(!lights_lamp_through_block("redstone_torch[lit=true]"));
+    }
+
+    #[test]
+    fn redstone_block_does_not_power_block_beside_it() {
+        assert!(!lights_lamp_through_block("redstone_block"));
+    }
+
+    #[test]
+    fn lever_only_powers_block_it_is_on() {
+        assert!(!lights_lamp_through_block(
+            "lever[face=floor,facing=north,powered=true]"
+        ));
+        assert!(lights_lamp_through_block(
+            "lever[face=wall,facing=east,powered=true]"
+        ));
+    }
+
+    #[test]
+    fn torch_inverts_after_one_tick() {
+        // A lever on a block with a torch on its far side.
+        let (mut world, mut simulation) = world_with(&[
+            (0, 0, 0, "lever[face=wall,facing=west,powered=false]"),
+            (1, 0, 0, "stone"),
+            (2, 0, 0, "redstone_wall_torch[facing=east,lit=true]"),
+            (3, 0, 0, "redstone_lamp[lit=false]"),
+        ]);
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(block(&world, 3, 0, 0), Block::RedstoneLamp { lit: true });
+
+        simulation.use_block(&mut world, BlockPos::new(0, 0, 0));
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(property(&world, 2, 0, 0, "lit").as_deref(), Some("true"));
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(property(&world, 2, 0, 0, "lit").as_deref(), Some("false"));
+        // Lamps take two ticks to go out.
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(block(&world, 3, 0, 0), Block::RedstoneLamp { lit: true });
+        run(&mut simulation, &mut world, 2);
+        assert_eq!(block(&world, 3, 0, 0), Block::RedstoneLamp { lit: false });
+    }
+
+    #[test]
+    fn repeater_waits_for_its_delay() {
+        let (mut world, mut simulation) = world_with(&[
+            (0, 0, 0, "redstone_block"),
+            (
+                1,
+                0,
+                0,
+                "repeater[delay=3,facing=west,locked=false,powered=false]",
+            ),
+            (2, 0, 0, "redstone_wire"),
+        ]);
+        for _ in 0..3 {
+            assert_eq!(
+                property(&world, 1, 0, 0, "powered").as_deref(),
+                Some("false")
+            );
+            run(&mut simulation, &mut world, 1);
+        }
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(
+            property(&world, 1, 0, 0, "powered").as_deref(),
+            Some("true")
+        );
+        assert_eq!(block(&world, 2, 0, 0), Block::RedstoneWire { power: 15 });
+    }
+
+    #[test]
+    fn locked_repeater_holds_its_state() {
+        let (mut world, mut simulation) = world_with(&[
+            (0, 0, 0, "lever[face=floor,facing=north,powered=true]"),
+            (
+                1,
+                0,
+                0,
+                "repeater[delay=1,facing=west,locked=false,powered=true]",
+            ),
+            (
+                1,
+                0,
+                1,
+                "repeater[delay=1,facing=south,locked=false,powered=false]",
+            ),
+            (1, 0, 2, "redstone_block"),
+        ]);
+        run(&mut simulation, &mut world, 3);
+        assert_eq!(
+            property(&world, 1, 0, 1, "powered").as_deref(),
+            Some("true")
+        );
+        assert_eq!(property(&world, 1, 0, 0, "locked").as_deref(), Some("true"));
+
+        simulation.use_block(&mut world, BlockPos::new(0, 0, 0));
+        run(&mut simulation, &mut world, 5);
+        assert_eq!(
+            property(&world, 1, 0, 0, "powered").as_deref(),
+            Some("true")
+        );
+    }
+
+    #[test]
+    fn comparator_subtracts_side_input() {
+        let mut blocks = vec![
+            (0, 0, 0, "redstone_block"),
+            (
+                1,
+                0,
+                0,
+                "comparator[facing=west,mode=subtract,powered=false]",
+            ),
+            (2, 0, 0, "redstone_wire"),
+        ];
+        // Wire reaching the side of the comparator at strength 12.
+        blocks.push((1, 0, 5, "redstone_block"));
+        for z in 1..=4 {
+            blocks.push((1, 0, z, "redstone_wire"));
+        }
+        let (mut world, mut simulation) = world_with(&blocks);
+        run(&mut simulation, &mut world, 3);
+        assert_eq!(block(&world, 1, 0, 1), Block::RedstoneWire { power: 12 });
+        assert_eq!(block(&world, 2, 0, 0), Block::RedstoneWire { power: 3 });
+        assert_eq!(output_signal(&world, 1, 0, 0), Some(3));
+    }
+
+    #[test]
+    fn comparator_starts_from_its_block_entity_output() {
+        let mut world = World::new();
+        world.set_block(
+            BlockPos::new(1, 0, 0),
+            Block::from_state("comparator[facing=west,mode=compare,powered=true]"),
+        );
+        world.set_block(BlockPos::new(2, 0, 0), Block::from_state("redstone_wire"));
+        let mut data = HashMap::new();
+        data.insert(
+            "id".to_owned(),
+            Tag::String("minecraft:comparator".to_owned()),
+        );
+        data.insert("x".to_owned(), Tag::Int(1));
+        data.insert("y".to_owned(), Tag::Int(0));
+        data.insert("z".to_owned(), Tag::Int(0));
+        data.insert("OutputSignal".to_owned(), Tag::Int(5));
+        world.insert_block_entity(Tag::Compound(data));
+        let mut simulation = RedstoneSimulation::new(1.0);
+        simulation.sync_chunks(&world);
+
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(block(&world, 2, 0, 0), Block::RedstoneWire { power: 5 });
+        // With nothing behind it, it turns off a tick later.
+        run(&mut simulation, &mut world, 2);
+        assert_eq!(output_signal(&world, 1, 0, 0), Some(0));
+        assert_eq!(block(&world, 2, 0, 0), Block::RedstoneWire { power: 0 });
+    }
+
+    #[test]
+    fn comparator_takes_side_input_from_lever() {
+        let (mut world, mut simulation) = world_with(&[
+            (0, 0, 0, "redstone_block"),
+            (
+                1,
+                0,
+                0,
+                "comparator[facing=west,mode=subtract,powered=false]",
+            ),
+            (2, 0, 0, "redstone_wire"),
+            (1, 0, 1, "lever[face=floor,facing=north,powered=true]"),
+        ]);
+        run(&mut simulation, &mut world, 3);
+        assert_eq!(block(&world, 2, 0, 0), Block::RedstoneWire { power: 0 });
+    }
+
+    #[test]
+    fn button_releases_after_ten_ticks() {
+        let (mut world, mut simulation) = world_with(&[
+            (0, 0, 0, "stone_button[face=wall,facing=west,powered=false]"),
+            (1, 0, 0, "redstone_lamp[lit=false]"),
+        ]);
+        simulation.use_block(&mut world, BlockPos::new(0, 0, 0));
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(block(&world, 1, 0, 0), Block::RedstoneLamp { lit: true });
+        run(&mut simulation, &mut world, 8);
+        assert_eq!(
+            property(&world, 0, 0, 0, "powered").as_deref(),
+            Some("true")
+        );
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(
+            property(&world, 0, 0, 0, "powered").as_deref(),
+            Some("false")
+        );
+    }
+
+    #[test]
+    fn observer_pulses_on_change() {
+        let (mut world, mut simulation) = world_with(&[
+            (0, 0, 0, "lever[face=floor,facing=north,powered=false]"),
+            (1, 0, 0, "observer[facing=west,powered=false]"),
+            (2, 0, 0, "redstone_wire"),
+        ]);
+        simulation.use_block(&mut world, BlockPos::new(0, 0, 0));
+        let mut powered_ticks = 0;
+        for _ in 0..5 {
+            run(&mut simulation, &mut world, 1);
+            if block(&world, 2, 0, 0) == (Block::RedstoneWire { power: 15 }) {
+                powered_ticks += 1;
+            }
+        }
+        assert_eq!(powered_ticks, 1);
+    }
+
+    #[test]
+    fn torch_clock_is_deterministic() {
+        // A torch on the side of a block, looped back into that block
+        // through wire and a repeater.
+        let blocks = [
+            (0, 0, 0, "stone"),
+            (1, 0, 0, "redstone_wall_torch[facing=east,lit=true]"),
+            (2, 0, 0, "redstone_wire"),
+            (2, 0, 1, "redstone_wire"),
+            (2, 0, 2, "redstone_wire"),
+            (1, 0, 2, "redstone_wire"),
+            (0, 0, 2, "redstone_wire"),
+            (
+                0,
+                0,
+                1,
+                "repeater[delay=1,facing=south,locked=false,powered=false]",
+            ),
+        ];
+        let trace = || {
+            let (mut world, mut simulation) = world_with(&blocks);
+            (0..40)
+                .map(|_| {
+                    simulation.tick(&mut world);
+                    block(&world, 1, 0, 0)
+                })
+                .collect::<Vec<Block>>()
+        };
+        let first = trace();
+        assert_eq!(first, trace());
+        // On for two ticks, then off for two.
+        assert_ne!(first[10], first[12]);
+        for i in 10..36 {
+            assert_eq!(first[i], first[i + 4]);
+        }
+    }
+
+    #[test]
+    fn trace_sorts_inputs_from_outputs() {
+        let (world, simulation) = world_with(&[
+            (0, 0, 0, "lever[face=floor,facing=north,powered=false]"),
+            (1, 0, 0, "redstone_wire"),
+            (2, 0, 0, "redstone_wire"),
+            (
+                3,
+                0,
+                0,
+                "repeater[delay=1,facing=west,locked=false,powered=false]",
+            ),
+            (2, 0, 1, "redstone_wire"),
+            (2, 0, 2, "stone"),
+            (2, 0, 3, "redstone_wall_torch[facing=south,lit=true]"),
+            (5, 0, 0, "redstone_wire"),
+        ]);
+        let network = simulation.trace_network(&world, BlockPos::new(2, 0, 1));
+        let positions = |positions: &[(i32, i32, i32)]| {
+            positions
+                .iter()
+                .map(|(x, y, z)| BlockPos::new(*x, *y, *z))
+                .collect::<BTreeSet<BlockPos>>()
+        };
+        assert_eq!(network.wires, positions(&[(1, 0, 0), (2, 0, 0), (2, 0, 1)]));
+        assert_eq!(network.inputs, positions(&[(0, 0, 0)]));
+        assert_eq!(network.outputs, positions(&[(3, 0, 0), (2, 0, 3)]));
+        assert_eq!(network.blocks, positions(&[(2, 0, 2)]));
+    }
+
+    #[test]
+    fn piston_pushes_and_pulls() {
+        let (mut world, mut simulation) = world_with(&[
+            (0, 0, 0, "lever[face=floor,facing=north,powered=false]"),
+            (1, 0, 0, "sticky_piston[extended=false,facing=east]"),
+            (2, 0, 0, "glass"),
+        ]);
+        simulation.use_block(&mut world, BlockPos::new(0, 0, 0));
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(block(&world, 3, 0, 0), Block::Glass);
+        assert_eq!(
+            block(&world, 2, 0, 0).name_and_properties().0,
+            "minecraft:piston_head"
+        );
+
+        simulation.use_block(&mut world, BlockPos::new(0, 0, 0));
+        run(&mut simulation, &mut world, 1);
+        assert_eq!(block(&world, 2, 0, 0), Block::Glass);
+        assert_eq!(block(&world, 3, 0, 0), Block::Air);
+    }
+}
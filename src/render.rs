@@ -1,182 +1,567 @@
-use gl::types::*;
-use std::ffi::CString;
-use std::os::raw::c_void;
-use std::str::from_utf8;
+use config::Config;
+use nalgebra::{Matrix4, Vector3, Vector4};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::thread;
 use std::time::{Duration, Instant};
-use std::{mem, ptr};
 
+use crate::entity::EntityRegistry;
 use crate::render_camera::Camera;
+use crate::render_chunk::{SectionRender, TranslucentMesh};
+use crate::render_entity::{set_model_matrix, EntityRenderer};
+use crate::render_framebuffer::Framebuffer;
+use crate::render_lines::LineRenderer;
+use crate::render_mesh_workers::{section_center, MeshResult, MeshWorkerPool};
+use crate::render_mesher::{build_section_mesh, Mesh};
+use crate::render_shader::{compile_program, get_uniform_location};
+use crate::render_state::RenderState;
+use crate::render_text::TextRenderer;
+use crate::screenshot::{read_pixels, read_pixels_into, Image};
+use crate::window::{invalid_setting, unset_setting};
+use crate::world::{ChunkPos, SectionPos, SectionSnapshot, SECTIONS_PER_CHUNK};
+
+// How far the camera has to move before translucent faces are sorted again.
+const TRANSLUCENT_SORT_DISTANCE: f32 = 1.0;
+const ALPHA_CUTOFF: f32 = 0.5;
 
 const VERTEX_SHADER_SOURCE: &str = r#"
     #version 330 core
     layout (location = 0) in vec3 aPos;
+    layout (location = 1) in vec4 aColor;
+    layout (location = 2) in float aShade;
+    layout (location = 3) in float aOcclusion;
+    layout (location = 4) in vec2 aLight;
 
     uniform mat4 transform;
+    uniform mat4 model;
+    uniform bool lighting;
+    uniform bool ambientOcclusion;
+    uniform float skyBrightness;
+
+    out vec4 vertexColor;
+    out float worldY;
 
     void main() {
-       gl_Position = transform * vec4(aPos, 1.0);
+       vec4 worldPos = model * vec4(aPos, 1.0);
+       gl_Position = transform * worldPos;
+       worldY = worldPos.y;
+
+       float brightness = 1.0;
+       if (lighting) {
+          // Like vanilla, each light level is a fixed fraction dimmer
+          // than the one above it, with the sky scaled by time of day.
+          float level = max(aLight.x * skyBrightness, aLight.y) * 15.0;
+          brightness = aShade * mix(0.05, 1.0, pow(0.8, 15.0 - level));
+       }
+       if (ambientOcclusion) {
+          brightness *= aOcclusion;
+       }
+       // Block colours are picked in sRGB, lighting happens in linear.
+       vertexColor = vec4(pow(aColor.rgb, vec3(2.2)) * brightness, aColor.a);
     }
 "#;
 
 const FRAGMENT_SHADER_SOURCE: &str = r#"
     #version 330 core
+    in vec4 vertexColor;
+    in float worldY;
     out vec4 FragColor;
+
+    uniform float alphaCutoff;
+    // The heights left showing by the view filter's slice.
+    uniform float clipBottom;
+    uniform float clipTop;
+
     void main() {
-       FragColor = vec4(1.0f, 0.5f, 0.2f, 1.0f);
+       if (vertexColor.a < alphaCutoff || worldY < clipBottom || worldY > clipTop) {
+          discard;
+       }
+       FragColor = vertexColor;
     }
 "#;
 
 pub struct Renderer {
     pub program: u32,
-    pub vao: u32,
     pub transform_uniform: i32,
+    pub model_uniform: i32,
+    pub lighting_uniform: i32,
+    pub ambient_occlusion_uniform: i32,
+    pub sky_brightness_uniform: i32,
+    pub alpha_cutoff_uniform: i32,
+    pub clip_bottom_uniform: i32,
+    pub clip_top_uniform: i32,
+    pub lighting: bool,
+    pub ambient_occlusion: bool,
+    pub sky_brightness: f32,
+    /// The heights the world is clipped to, bottom and top.
+    pub clip_range: (f32, f32),
     pub camera: Camera,
+    pub state: RenderState,
+    pub text: TextRenderer,
+    pub lines: LineRenderer,
+    pub width: u32,
+    pub height: u32,
+    entity_renderer: EntityRenderer,
     pub total_frames: i32,
     pub frame_count: i32,
     pub last_return: Instant,
+    chunk_meshes: HashMap<SectionPos, SectionRender>,
+    mesh_workers: MeshWorkerPool,
+    // The newest generation queued for each section. Results from older
+    // generations are stale and get thrown away.
+    mesh_generations: HashMap<SectionPos, u64>,
+    next_mesh_generation: u64,
+    pending_uploads: Vec<MeshResult>,
+    mesh_upload_budget: usize,
+    // The layer the view filter's slice cuts through, meshed on its own
+    // and drawn unclipped to close off the cut.
+    slice_meshes: HashMap<SectionPos, SectionRender>,
+    // A schematic laid over the world, drawn in a pass of its own. It is
+    // meshed by section like the world.
+    ghost_meshes: HashMap<SectionPos, TranslucentMesh>,
 }
 
 impl Renderer {
-    pub fn init(window: &mut glfw::Window) -> Renderer {
+    pub fn init(window: &mut glfw::Window, config: &Config) -> Renderer {
         gl::load_with(|s| window.get_proc_address(s) as *const _);
-        let (shader_program, vao, transform_uniform) = unsafe {
-            let vertex_shader = gl::CreateShader(gl::VERTEX_SHADER);
-            let c_str_vert = CString::new(VERTEX_SHADER_SOURCE.as_bytes()).unwrap();
-            gl::ShaderSource(vertex_shader, 1, &c_str_vert.as_ptr(), ptr::null());
-            gl::CompileShader(vertex_shader);
-
-            let mut success = gl::FALSE as GLint;
-            let mut info_log = Vec::with_capacity(512);
-            info_log.set_len(512 - 1);
-            gl::GetShaderiv(vertex_shader, gl::COMPILE_STATUS, &mut success);
-            if success != gl::TRUE as GLint {
-                gl::GetShaderInfoLog(
-                    vertex_shader,
-                    512,
-                    ptr::null_mut(),
-                    info_log.as_mut_ptr() as *mut GLchar,
-                );
-                println!(
-                    "ERROR::SHADER::VERTEX::COMPILATION_FAILED\n{}",
-                    from_utf8(&info_log).unwrap()
-                );
-            }
-
-            let fragment_shader = gl::CreateShader(gl::FRAGMENT_SHADER);
-            let c_str_frag = CString::new(FRAGMENT_SHADER_SOURCE.as_bytes()).unwrap();
-            gl::ShaderSource(fragment_shader, 1, &c_str_frag.as_ptr(), ptr::null());
-            gl::CompileShader(fragment_shader);
-            gl::GetShaderiv(fragment_shader, gl::COMPILE_STATUS, &mut success);
-            if success != gl::TRUE as GLint {
-                gl::GetShaderInfoLog(
-                    fragment_shader,
-                    512,
-                    ptr::null_mut(),
-                    info_log.as_mut_ptr() as *mut GLchar,
-                );
-                println!(
-                    "ERROR::SHADER::FRAGMENT::COMPILATION_FAILED\n{}",
-                    from_utf8(&info_log).unwrap()
-                );
-            }
+        let (width, height) = window.get_framebuffer_size();
+        Renderer::new(width as u32, height as u32, config)
+    }
 
-            let shader_program = gl::CreateProgram();
-            gl::AttachShader(shader_program, vertex_shader);
-            gl::AttachShader(shader_program, fragment_shader);
-            gl::LinkProgram(shader_program);
-            gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut success);
-            if success != gl::TRUE as GLint {
-                gl::GetProgramInfoLog(
-                    shader_program,
-                    512,
-                    ptr::null_mut(),
-                    info_log.as_mut_ptr() as *mut GLchar,
-                );
-                println!(
-                    "ERROR::SHADER::PROGRAM::COMPILATION_FAILED\n{}",
-                    from_utf8(&info_log).unwrap()
-                );
-            }
+    /// Sets up a renderer for a context that is already current, with its
+    /// functions loaded, drawing to an area of the given size.
+    pub fn new(width: u32, height: u32, config: &Config) -> Renderer {
+        let mesh_workers = config
+            .get_int("mesh_workers")
+            .expect(unset_setting("mesh_workers").as_ref());
+        let mesh_workers =
+            usize::try_from(mesh_workers).expect(invalid_setting("mesh_workers").as_ref());
+        let mesh_upload_budget = config
+            .get_int("mesh_upload_budget")
+            .expect(unset_setting("mesh_upload_budget").as_ref());
+        let mesh_upload_budget = usize::try_from(mesh_upload_budget)
+            .expect(invalid_setting("mesh_upload_budget").as_ref());
 
-            // Grab the uniforms from our shader program
-            let c_str_transform_uniform = CString::new("transform").unwrap();
-            let transform_uniform =
-                gl::GetUniformLocation(shader_program, c_str_transform_uniform.as_ptr());
-
-            gl::DeleteShader(vertex_shader);
-            gl::DeleteShader(fragment_shader);
-
-            let vertices: [f32; 9] = [
-                -0.5, -0.5, 0.0, // left
-                0.5, -0.5, 0.0, // right
-                0.0, 0.5, 0.0, // top
-            ];
-            let (mut vbo, mut vao) = (0, 0);
-            gl::GenVertexArrays(1, &mut vao);
-            gl::GenBuffers(1, &mut vbo);
-            gl::BindVertexArray(vao);
-
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (vertices.len() * mem::size_of::<GLfloat>()) as GLsizeiptr,
-                &vertices[0] as *const f32 as *const c_void,
-                gl::STATIC_DRAW,
-            );
+        let lighting = config
+            .get_bool("lighting")
+            .expect(unset_setting("lighting").as_ref());
+        let ambient_occlusion = config
+            .get_bool("ambient_occlusion")
+            .expect(unset_setting("ambient_occlusion").as_ref());
 
-            gl::VertexAttribPointer(
-                0,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                3 * mem::size_of::<GLfloat>() as GLsizei,
-                ptr::null(),
-            );
-            gl::EnableVertexAttribArray(0);
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            gl::BindVertexArray(0);
-            gl::DrawArrays(gl::TRIANGLES, 0, 3);
-            (shader_program, vao, transform_uniform)
-        };
+        let shader_program = compile_program(VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE);
+        // Grab the uniforms from our shader program
+        let transform_uniform = get_uniform_location(shader_program, "transform");
+        let model_uniform = get_uniform_location(shader_program, "model");
+        let lighting_uniform = get_uniform_location(shader_program, "lighting");
+        let ambient_occlusion_uniform = get_uniform_location(shader_program, "ambientOcclusion");
+        let sky_brightness_uniform = get_uniform_location(shader_program, "skyBrightness");
+        let alpha_cutoff_uniform = get_uniform_location(shader_program, "alphaCutoff");
+        let clip_bottom_uniform = get_uniform_location(shader_program, "clipBottom");
+        let clip_top_uniform = get_uniform_location(shader_program, "clipTop");
 
         let camera = Camera::new(1280.0 / 720.0, 70.0);
+        let mut state = RenderState::init();
+        let text = TextRenderer::init(&mut state);
+        let lines = LineRenderer::init(&mut state);
 
-        return Renderer {
+        let mut renderer = Renderer {
             program: shader_program,
-            vao,
             transform_uniform,
+            model_uniform,
+            lighting_uniform,
+            ambient_occlusion_uniform,
+            sky_brightness_uniform,
+            alpha_cutoff_uniform,
+            clip_bottom_uniform,
+            clip_top_uniform,
+            lighting,
+            ambient_occlusion,
+            sky_brightness: 1.0,
+            clip_range: (f32::MIN, f32::MAX),
             camera,
+            state,
+            text,
+            lines,
+            width: 0,
+            height: 0,
+            entity_renderer: EntityRenderer::new(),
             total_frames: 0,
             last_return: Instant::now(),
             frame_count: 0,
+            chunk_meshes: HashMap::new(),
+            mesh_workers: MeshWorkerPool::new(mesh_workers),
+            mesh_generations: HashMap::new(),
+            next_mesh_generation: 0,
+            pending_uploads: Vec::new(),
+            mesh_upload_budget,
+            slice_meshes: HashMap::new(),
+            ghost_meshes: HashMap::new(),
         };
+        renderer.set_viewport_size(width, height);
+        renderer
     }
 
-    pub fn update(&mut self) {
+    /// Sets the size of the area being drawn to, normally the window's
+    /// framebuffer.
+    pub fn set_viewport_size(&mut self, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) || width == 0 || height == 0 {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        self.camera.set_aspect_ratio(width as f32 / height as f32);
         unsafe {
-            //let frame_time = (self.total_frames as f32) / 60.0;
-            // Update the camera, and then construct the world space
-            // matrix.
-            //self.camera.borrow_spatial_mut().set_rotation(
-            //    &Vector3::new(
-            //        (frame_time * 1.265).sin() * 10.0,
-            //        (frame_time * 1.567).sin() * 10.0,
-            //        0.0));
-            let mut world_space_matrix = self.camera.get_projection().clone_owned();
-
-            let camera_model_space_matrix = self
-                .camera
-                .borrow_spatial_mut()
-                .get_model_space_matrix()
-                .try_inverse();
-
-            if let Some(x) = camera_model_space_matrix {
-                world_space_matrix *= x;
+            gl::Viewport(0, 0, width as i32, height as i32);
+        }
+    }
+
+    /// Queues a section to be (re)meshed on the worker threads.
+    pub fn queue_section_mesh(&mut self, snapshot: SectionSnapshot) {
+        let generation = self.next_mesh_generation;
+        self.next_mesh_generation += 1;
+        self.mesh_generations.insert(snapshot.pos, generation);
+        self.mesh_workers.submit(snapshot, generation);
+    }
+
+    /// Frees the meshes of every section in a chunk and forgets about any
+    /// meshes of it still being built.
+    pub fn remove_chunk_meshes(&mut self, pos: ChunkPos) {
+        for y in 0..SECTIONS_PER_CHUNK as i32 {
+            let section = SectionPos::new(pos.x, y, pos.z);
+            self.chunk_meshes.remove(&section);
+            self.slice_meshes.remove(&section);
+            self.mesh_generations.remove(&section);
+            self.mesh_workers.cancel(section);
+        }
+        self.state.forget_vertex_array();
+        self.pending_uploads
+            .retain(|result| result.pos.x != pos.x || result.pos.z != pos.z);
+    }
+
+    /// The number of bytes used by the meshes of the world, the view
+    /// filter's slice and the ghost overlay.
+    pub fn mesh_memory_usage(&self) -> usize {
+        let sections: usize = self
+            .chunk_meshes
+            .values()
+            .chain(self.slice_meshes.values())
+            .map(|mesh| mesh.size_bytes())
+            .sum();
+        let ghost: usize = self
+            .ghost_meshes
+            .values()
+            .map(|mesh| mesh.size_bytes())
+            .sum();
+        sections + ghost
+    }
+
+    /// Waits for every queued mesh to be built and uploads them all, for
+    /// when a complete picture matters more than a smooth frame rate.
+    /// If the workers run out of work first, e.g. because meshing a
+    /// section panicked, the sections still waiting are given up on.
+    pub fn flush_meshes(&mut self) {
+        loop {
+            // Checked before collecting results, so that anything sent
+            // before the workers went idle is collected below.
+            let has_work = self.mesh_workers.has_work();
+            self.upload_meshes(usize::MAX);
+            if self.mesh_generations.is_empty() {
+                break;
             }
+            if !has_work {
+                eprintln!(
+                    "Gave up on meshing {} sections",
+                    self.mesh_generations.len()
+                );
+                self.mesh_generations.clear();
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
 
-            gl::ClearColor(0.2, 0.3, 0.3, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT);
-            gl::UseProgram(self.program);
+    /// Uploads finished meshes to the GPU, nearest to the camera first.
+    /// At most `budget` meshes are uploaded, so that a large batch of
+    /// chunks doesn't stall a single frame.
+    fn upload_meshes(&mut self, budget: usize) {
+        let camera_position = self.camera.borrow_spatial().get_translation().clone_owned();
+        self.mesh_workers.set_focus(&camera_position);
+
+        self.pending_uploads
+            .extend(self.mesh_workers.poll_results());
+        // Results that have been queued again since, or whose section has
+        // been unloaded, are dropped here so they don't use up the budget.
+        let generations = &self.mesh_generations;
+        self.pending_uploads
+            .retain(|result| generations.get(&result.pos) == Some(&result.generation));
+
+        // Sort furthest first so the nearest meshes can be popped off the end.
+        self.pending_uploads.sort_by(|a, b| {
+            let distance_a = (section_center(a.pos) - camera_position).norm_squared();
+            let distance_b = (section_center(b.pos) - camera_position).norm_squared();
+            distance_b.partial_cmp(&distance_a).unwrap()
+        });
+
+        for _ in 0..budget {
+            let result = match self.pending_uploads.pop() {
+                Some(result) => result,
+                None => break,
+            };
+            self.mesh_generations.remove(&result.pos);
+
+            if result.mesh.is_empty() {
+                self.chunk_meshes.remove(&result.pos);
+            } else {
+                let mesh = SectionRender::upload(result.mesh, &camera_position, &mut self.state);
+                self.chunk_meshes.insert(result.pos, mesh);
+            }
+        }
+        // Replacing or removing meshes deletes their vertex arrays.
+        self.state.forget_vertex_array();
+    }
+
+    /// Draws the sections of the world, then the slice layer over them
+    /// with the clipping turned off.
+    fn draw_sections(&mut self) {
+        let camera_position = self.camera.borrow_spatial().get_translation().clone_owned();
+        draw_section_renders(
+            &mut self.chunk_meshes,
+            &camera_position,
+            self.alpha_cutoff_uniform,
+            &mut self.state,
+        );
+
+        unsafe {
+            gl::Uniform1f(self.clip_bottom_uniform, f32::MIN);
+            gl::Uniform1f(self.clip_top_uniform, f32::MAX);
+        }
+        draw_section_renders(
+            &mut self.slice_meshes,
+            &camera_position,
+            self.alpha_cutoff_uniform,
+            &mut self.state,
+        );
+        // The ghost is clipped along with the world.
+        unsafe {
+            gl::Uniform1f(self.clip_bottom_uniform, self.clip_range.0);
+            gl::Uniform1f(self.clip_top_uniform, self.clip_range.1);
+        }
+    }
+
+    /// Meshes a section of the slice layer straight away, rather than on
+    /// the workers, so that the layer never lags behind the clipping.
+    pub fn set_slice_section(&mut self, snapshot: &SectionSnapshot) {
+        let mesh = build_section_mesh(snapshot);
+        if mesh.is_empty() {
+            self.slice_meshes.remove(&snapshot.pos);
+        } else {
+            let camera_position = self.camera.borrow_spatial().get_translation().clone_owned();
+            let render = SectionRender::upload(mesh, &camera_position, &mut self.state);
+            self.slice_meshes.insert(snapshot.pos, render);
+        }
+        self.state.forget_vertex_array();
+    }
+
+    pub fn clear_slice_sections(&mut self) {
+        self.slice_meshes.clear();
+        self.state.forget_vertex_array();
+    }
+
+    /// Replaces one section of the ghost overlay, or removes it if the
+    /// mesh is empty.
+    pub fn set_ghost_mesh(&mut self, pos: SectionPos, mesh: Mesh) {
+        if mesh.is_empty() {
+            self.ghost_meshes.remove(&pos);
+        } else {
+            let camera_position = self.camera.borrow_spatial().get_translation().clone_owned();
+            let mesh = TranslucentMesh::upload(mesh, &camera_position, &mut self.state);
+            self.ghost_meshes.insert(pos, mesh);
+        }
+        self.state.forget_vertex_array();
+    }
+
+    /// Draws the ghost overlay over the finished world. It is blended like
+    /// translucent blocks, back-to-front, but after all of them, so that
+    /// the world shows through it.
+    fn draw_ghost(&mut self) {
+        let camera_position = self.camera.borrow_spatial().get_translation().clone_owned();
+        let mut meshes: Vec<_> = self.ghost_meshes.iter_mut().collect();
+        meshes.sort_by(|a, b| {
+            let distance_a = (section_center(*a.0) - camera_position).norm_squared();
+            let distance_b = (section_center(*b.0) - camera_position).norm_squared();
+            distance_b.partial_cmp(&distance_a).unwrap()
+        });
+
+        self.state.set_blend(true);
+        self.state.set_depth_write(false);
+        for (_, mesh) in meshes {
+            mesh.sort(&camera_position, TRANSLUCENT_SORT_DISTANCE, &mut self.state);
+            mesh.draw(&mut self.state);
+        }
+        self.state.set_depth_write(true);
+        self.state.set_blend(false);
+    }
+
+    pub fn update(&mut self, entities: &EntityRegistry) {
+        self.draw_world(entities);
+        self.finish_frame();
+    }
+
+    /// Draws the frame like `update`, reading it back before the line and
+    /// text overlays go over it so that they are left out.
+    pub fn update_and_capture(&mut self, entities: &EntityRegistry) -> Image {
+        self.draw_world(entities);
+        let image = self.capture_frame();
+        self.finish_frame();
+        image
+    }
+
+    fn draw_world(&mut self, entities: &EntityRegistry) {
+        self.upload_meshes(self.mesh_upload_budget);
+
+        let projection = self.camera.get_projection().clone_owned();
+        self.draw_scene(entities, &projection);
+    }
+
+    fn finish_frame(&mut self) {
+        // Overlays queued during the frame go over the top of the scene.
+        let projection = self.camera.get_projection().clone_owned();
+        let world_space_matrix = self.world_space_matrix(&projection);
+        self.lines.draw(&mut self.state, &world_space_matrix);
+        self.text.draw(&mut self.state, self.width, self.height);
+        self.lines.clear();
+
+        if self.last_return.elapsed() >= Duration::from_secs(1) {
+            println!("FPS: {}", self.frame_count);
+            self.frame_count = 0;
+            self.last_return = Instant::now();
+        }
+
+        self.frame_count += 1;
+        self.total_frames += 1;
+    }
+
+    /// Reads back what has been drawn to the window so far this frame.
+    pub fn capture_frame(&self) -> Image {
+        read_pixels(self.width, self.height)
+    }
+
+    /// Renders the scene at `scale` times the window resolution. The view
+    /// is split into a grid of window sized tiles, each drawn through its
+    /// own slice of the camera's frustum and read back in turn. Overlays
+    /// are left out. Fails if the image would be too large to hold.
+    pub fn capture_high_res(&mut self, entities: &EntityRegistry, scale: u32) -> io::Result<Image> {
+        let image = match (
+            self.width.checked_mul(scale),
+            self.height.checked_mul(scale),
+        ) {
+            (Some(width), Some(height)) => Image::try_new(width, height),
+            _ => None,
+        };
+        let mut image = image.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} times the window size is too large", scale),
+            )
+        })?;
+        self.upload_meshes(self.mesh_upload_budget);
+
+        for row in 0..scale {
+            for column in 0..scale {
+                let projection = self.camera.get_tile_projection(scale, column, row);
+                self.draw_scene(entities, &projection);
+                let tile = read_pixels(self.width, self.height);
+                // Tile rows count up from the bottom, image rows down from
+                // the top.
+                image.blit(&tile, column * self.width, (scale - 1 - row) * self.height);
+            }
+        }
+        Ok(image)
+    }
+
+    /// Renders the scene into an offscreen framebuffer of the given size,
+    /// rather than to the window, once every queued mesh has been built.
+    /// Fails if the GPU or memory can't hold an image that large.
+    pub fn render_offscreen(
+        &mut self,
+        entities: &EntityRegistry,
+        width: u32,
+        height: u32,
+    ) -> io::Result<Image> {
+        let mut image = Image::try_new(width, height).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}x{} is too large to hold", width, height),
+            )
+        })?;
+        let framebuffer = Framebuffer::new(width, height)?;
+        self.flush_meshes();
+
+        let (window_width, window_height) = (self.width, self.height);
+        framebuffer.bind();
+        self.set_viewport_size(width, height);
+
+        let projection = self.camera.get_projection().clone_owned();
+        self.draw_scene(entities, &projection);
+        read_pixels_into(&mut image);
+
+        framebuffer.unbind();
+        self.set_viewport_size(window_width, window_height);
+        Ok(image)
+    }
+
+    /// Where a point in the world appears in the window, in pixels from
+    /// the top left, or None if it is behind the camera.
+    pub fn project_to_screen(&mut self, point: &Vector3<f32>) -> Option<(f32, f32)> {
+        let view = self
+            .camera
+            .borrow_spatial_mut()
+            .get_model_space_matrix()
+            .try_inverse()?;
+        let clip =
+            self.camera.get_projection() * view * Vector4::new(point.x, point.y, point.z, 1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let x = (clip.x / clip.w + 1.0) / 2.0 * self.width as f32;
+        let y = (1.0 - clip.y / clip.w) / 2.0 * self.height as f32;
+        Some((x, y))
+    }
+
+    /// Combines `projection` with the camera's view of the world.
+    fn world_space_matrix(&mut self, projection: &Matrix4<f32>) -> Matrix4<f32> {
+        //let frame_time = (self.total_frames as f32) / 60.0;
+        // Update the camera, and then construct the world space
+        // matrix.
+        //self.camera.borrow_spatial_mut().set_rotation(
+        //    &Vector3::new(
+        //        (frame_time * 1.265).sin() * 10.0,
+        //        (frame_time * 1.567).sin() * 10.0,
+        //        0.0));
+        let mut world_space_matrix = projection.clone_owned();
+
+        let camera_model_space_matrix = self
+            .camera
+            .borrow_spatial_mut()
+            .get_model_space_matrix()
+            .try_inverse();
+
+        if let Some(x) = camera_model_space_matrix {
+            world_space_matrix *= x;
+        }
+        world_space_matrix
+    }
+
+    /// Clears the screen and draws the world and entities as seen from the
+    /// camera through `projection`. Overlays are drawn separately, by
+    /// `finish_frame`.
+    fn draw_scene(&mut self, entities: &EntityRegistry, projection: &Matrix4<f32>) {
+        let world_space_matrix = self.world_space_matrix(projection);
+        unsafe {
+            self.state.clear([0.2, 0.3, 0.3]);
+            self.state.use_program(self.program);
 
             let world_space_matrix_data = world_space_matrix.as_slice();
 
@@ -186,18 +571,78 @@ impl Renderer {
                 gl::FALSE,
                 world_space_matrix_data.as_ptr(),
             );
+            gl::Uniform1i(self.lighting_uniform, self.lighting as i32);
+            gl::Uniform1i(
+                self.ambient_occlusion_uniform,
+                self.ambient_occlusion as i32,
+            );
+            gl::Uniform1f(self.sky_brightness_uniform, self.sky_brightness);
+            gl::Uniform1f(self.clip_bottom_uniform, self.clip_range.0);
+            gl::Uniform1f(self.clip_top_uniform, self.clip_range.1);
 
-            gl::BindVertexArray(self.vao);
-            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            // Entities go before the sections, whose translucent pass has
+            // to come last.
+            self.entity_renderer
+                .draw(entities, self.model_uniform, &mut self.state);
+            set_model_matrix(self.model_uniform, &Matrix4::identity());
+            self.draw_sections();
+            self.draw_ghost();
+        }
+    }
+}
 
-            if self.last_return.elapsed() >= Duration::from_secs(1) {
-                println!("FPS: {}", self.frame_count);
-                self.frame_count = 0;
-                self.last_return = Instant::now();
-            }
+/// Draws sections in three passes: opaque geometry first, then
+/// alpha-tested cutout geometry, then translucent geometry blended
+/// back-to-front.
+fn draw_section_renders(
+    sections: &mut HashMap<SectionPos, SectionRender>,
+    camera_position: &Vector3<f32>,
+    alpha_cutoff_uniform: i32,
+    state: &mut RenderState,
+) {
+    unsafe {
+        gl::Uniform1f(alpha_cutoff_uniform, 0.0);
+    }
+    for section in sections.values() {
+        if let Some(mesh) = &section.opaque {
+            mesh.draw(state);
+        }
+    }
 
-            self.frame_count += 1;
-            self.total_frames += 1;
+    // Cutout faces can be seen from behind, e.g. through the gaps of the
+    // face in front of them.
+    state.set_cull_face(false);
+    unsafe {
+        gl::Uniform1f(alpha_cutoff_uniform, ALPHA_CUTOFF);
+    }
+    for section in sections.values() {
+        if let Some(mesh) = &section.cutout {
+            mesh.draw(state);
         }
     }
+    state.set_cull_face(true);
+
+    // Translucent faces don't write depth, so that faces behind them still
+    // get drawn, but are tested against the opaque depth.
+    let mut translucent: Vec<_> = sections
+        .iter_mut()
+        .filter_map(|(pos, section)| section.translucent.as_mut().map(|mesh| (*pos, mesh)))
+        .collect();
+    translucent.sort_by(|a, b| {
+        let distance_a = (section_center(a.0) - camera_position).norm_squared();
+        let distance_b = (section_center(b.0) - camera_position).norm_squared();
+        distance_b.partial_cmp(&distance_a).unwrap()
+    });
+
+    unsafe {
+        gl::Uniform1f(alpha_cutoff_uniform, 0.0);
+    }
+    state.set_blend(true);
+    state.set_depth_write(false);
+    for (_, mesh) in translucent {
+        mesh.sort(camera_position, TRANSLUCENT_SORT_DISTANCE, state);
+        mesh.draw(state);
+    }
+    state.set_depth_write(true);
+    state.set_blend(false);
 }
@@ -31,6 +31,18 @@ impl Camera {
         &mut self.spatial
     }
 
+    /// The vertical field of view in degrees.
+    pub fn vfov(&self) -> f32 {
+        self.vfov
+    }
+
+    pub fn set_vfov(&mut self, vfov: f32) {
+        if self.vfov != vfov {
+            self.vfov = vfov;
+            self.mat_projection_dirty = true;
+        }
+    }
+
     pub fn get_projection(&mut self) -> &Matrix4<f32> {
         if self.mat_projection_dirty {
             self.rebuild_projection_mat();
@@ -39,7 +51,34 @@ impl Camera {
         &self.mat_projection
     }
 
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        if self.aspect_ratio != aspect_ratio {
+            self.aspect_ratio = aspect_ratio;
+            self.mat_projection_dirty = true;
+        }
+    }
+
+    /// The projection for one tile of a `tiles` by `tiles` grid covering
+    /// the camera's view, with columns counted from the left and rows from
+    /// the bottom. Drawn at the normal resolution, the tiles together make
+    /// up an image `tiles` times larger.
+    pub fn get_tile_projection(&mut self, tiles: u32, column: u32, row: u32) -> Matrix4<f32> {
+        let tile_size = 2.0 / tiles as f32;
+        let left = -1.0 + tile_size * column as f32;
+        let bottom = -1.0 + tile_size * row as f32;
+
+        // Stretch the tile's part of clip space out to fill all of it.
+        let mut crop = Matrix4::identity();
+        crop[(0, 0)] = tiles as f32;
+        crop[(1, 1)] = tiles as f32;
+        crop[(0, 3)] = -(2.0 * left + tile_size) / tile_size;
+        crop[(1, 3)] = -(2.0 * bottom + tile_size) / tile_size;
+
+        crop * self.get_projection()
+    }
+
     fn rebuild_projection_mat(&mut self) {
-        self.mat_projection = Matrix4::new_perspective(self.aspect_ratio, self.vfov, 0.1, 4096.0);
+        self.mat_projection =
+            Matrix4::new_perspective(self.aspect_ratio, self.vfov.to_radians(), 0.1, 4096.0);
     }
 }
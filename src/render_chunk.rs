@@ -0,0 +1,196 @@
+use gl::types::*;
+use std::os::raw::c_void;
+use std::{mem, ptr};
+
+use crate::render_mesher::{Mesh, MeshVertex, SectionMesh};
+use crate::render_state::RenderState;
+use nalgebra::Vector3;
+
+/// The GPU side of a section mesh.
+pub struct ChunkMesh {
+    vao: u32,
+    vbo: u32,
+    ebo: u32,
+    index_count: i32,
+    size_bytes: usize,
+}
+
+impl ChunkMesh {
+    pub fn upload(mesh: &Mesh, state: &mut RenderState) -> ChunkMesh {
+        let vertex_bytes = mesh.vertices.len() * mem::size_of::<MeshVertex>();
+        let index_bytes = mesh.indices.len() * mem::size_of::<u32>();
+        let (mut vao, mut vbo, mut ebo) = (0, 0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::GenBuffers(1, &mut ebo);
+            state.bind_vertex_array(vao);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                vertex_bytes as GLsizeiptr,
+                mesh.vertices.as_ptr() as *const c_void,
+                gl::STATIC_DRAW,
+            );
+
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                index_bytes as GLsizeiptr,
+                mesh.indices.as_ptr() as *const c_void,
+                gl::STATIC_DRAW,
+            );
+
+            let stride = mem::size_of::<MeshVertex>() as GLsizei;
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(0);
+            // Colour, shade, occlusion and light follow the position.
+            let attributes: [(u32, i32, usize); 4] = [(1, 4, 3), (2, 1, 7), (3, 1, 8), (4, 2, 9)];
+            for (index, size, offset) in attributes.iter() {
+                gl::VertexAttribPointer(
+                    *index,
+                    *size,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    stride,
+                    (offset * mem::size_of::<GLfloat>()) as *const c_void,
+                );
+                gl::EnableVertexAttribArray(*index);
+            }
+        }
+
+        ChunkMesh {
+            vao,
+            vbo,
+            ebo,
+            index_count: mesh.indices.len() as i32,
+            size_bytes: vertex_bytes + index_bytes,
+        }
+    }
+
+    /// Replaces the index buffer with a reordering of the same indices.
+    pub fn update_indices(&self, indices: &[u32], state: &mut RenderState) {
+        state.bind_vertex_array(self.vao);
+        unsafe {
+            gl::BufferSubData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                0,
+                (indices.len() * mem::size_of::<u32>()) as GLsizeiptr,
+                indices.as_ptr() as *const c_void,
+            );
+        }
+    }
+
+    pub fn draw(&self, state: &mut RenderState) {
+        state.bind_vertex_array(self.vao);
+        unsafe {
+            gl::DrawElements(
+                gl::TRIANGLES,
+                self.index_count,
+                gl::UNSIGNED_INT,
+                ptr::null(),
+            );
+        }
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
+}
+
+impl Drop for ChunkMesh {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.ebo);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+/// Translucent faces have to be drawn back-to-front, so we keep the CPU
+/// side of the mesh around to re-sort its faces as the camera moves.
+pub struct TranslucentMesh {
+    gpu_mesh: ChunkMesh,
+    mesh: Mesh,
+    sorted_from: Vector3<f32>,
+}
+
+impl TranslucentMesh {
+    pub fn upload(mut mesh: Mesh, eye: &Vector3<f32>, state: &mut RenderState) -> TranslucentMesh {
+        mesh.indices = mesh.sorted_indices(eye);
+        TranslucentMesh {
+            gpu_mesh: ChunkMesh::upload(&mesh, state),
+            mesh,
+            sorted_from: eye.clone_owned(),
+        }
+    }
+
+    /// Re-sorts the faces if the eye has moved at least `threshold` blocks
+    /// since they were last sorted.
+    pub fn sort(&mut self, eye: &Vector3<f32>, threshold: f32, state: &mut RenderState) {
+        if (eye - self.sorted_from).norm() < threshold {
+            return;
+        }
+        self.mesh.indices = self.mesh.sorted_indices(eye);
+        self.gpu_mesh.update_indices(&self.mesh.indices, state);
+        self.sorted_from = eye.clone_owned();
+    }
+
+    pub fn draw(&self, state: &mut RenderState) {
+        self.gpu_mesh.draw(state);
+    }
+
+    /// The size of the uploaded mesh, along with the CPU copy kept for
+    /// sorting.
+    pub fn size_bytes(&self) -> usize {
+        self.gpu_mesh.size_bytes() * 2
+    }
+}
+
+/// Everything needed to draw a section, split by render layer.
+pub struct SectionRender {
+    pub opaque: Option<ChunkMesh>,
+    pub cutout: Option<ChunkMesh>,
+    pub translucent: Option<TranslucentMesh>,
+}
+
+impl SectionRender {
+    pub fn upload(mesh: SectionMesh, eye: &Vector3<f32>, state: &mut RenderState) -> SectionRender {
+        let upload = |mesh: &Mesh, state: &mut RenderState| {
+            if mesh.is_empty() {
+                None
+            } else {
+                Some(ChunkMesh::upload(mesh, state))
+            }
+        };
+        let opaque = upload(&mesh.opaque, state);
+        let cutout = upload(&mesh.cutout, state);
+        let translucent = if mesh.translucent.is_empty() {
+            None
+        } else {
+            Some(TranslucentMesh::upload(mesh.translucent, eye, state))
+        };
+
+        SectionRender {
+            opaque,
+            cutout,
+            translucent,
+        }
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        let mut size = 0;
+        if let Some(mesh) = &self.opaque {
+            size += mesh.size_bytes();
+        }
+        if let Some(mesh) = &self.cutout {
+            size += mesh.size_bytes();
+        }
+        if let Some(mesh) = &self.translucent {
+            size += mesh.size_bytes();
+        }
+        size
+    }
+}
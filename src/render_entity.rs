@@ -0,0 +1,150 @@
+use crate::entity::{Entity, EntityKind, EntityRegistry};
+use crate::render_chunk::ChunkMesh;
+use crate::render_mesher::Mesh;
+use crate::render_state::RenderState;
+use nalgebra::{Matrix4, Vector3};
+use std::collections::HashMap;
+
+// Player models are built in pixels of the vanilla skin, and scaled down so
+// the player ends up a little under two blocks tall like in vanilla.
+const PIXEL: f32 = 0.9375 / 16.0;
+const NECK_HEIGHT: f32 = 24.0 * PIXEL;
+
+const SKIN_COLOR: [f32; 4] = [0.96, 0.76, 0.6, 1.0];
+const HAIR_COLOR: [f32; 4] = [0.25, 0.16, 0.1, 1.0];
+const TROUSERS_COLOR: [f32; 4] = [0.2, 0.2, 0.45, 1.0];
+const OTHER_ENTITY_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
+
+/// Entities are lit as if standing in full daylight.
+const ENTITY_LIGHT: (u8, u8) = (15, 0);
+
+struct EntityModel {
+    body: ChunkMesh,
+    // The head turns separately from the body, so it gets its own mesh
+    // built around the neck.
+    head: Option<ChunkMesh>,
+}
+
+/// Draws entities as simple box models. Models are built the first time an
+/// entity is drawn and freed once the entity is gone.
+pub struct EntityRenderer {
+    models: HashMap<i32, EntityModel>,
+}
+
+impl EntityRenderer {
+    pub fn new() -> EntityRenderer {
+        EntityRenderer {
+            models: HashMap::new(),
+        }
+    }
+
+    pub fn draw(&mut self, entities: &EntityRegistry, model_uniform: i32, state: &mut RenderState) {
+        // Drop the models of entities that no longer exist.
+        let model_count = self.models.len();
+        self.models.retain(|id, _| entities.get(*id).is_some());
+        if self.models.len() != model_count {
+            state.forget_vertex_array();
+        }
+
+        for entity in entities.iter() {
+            let model = self
+                .models
+                .entry(entity.id)
+                .or_insert_with(|| build_model(entity, state));
+
+            let rotation = entity.borrow_spatial().get_rotation();
+            let translation = Matrix4::new_translation(entity.borrow_spatial().get_translation());
+
+            // Minecraft yaw turns clockwise looking down, starting from +Z.
+            let body_matrix = translation * yaw_matrix(rotation[1]);
+            set_model_matrix(model_uniform, &body_matrix);
+            model.body.draw(state);
+
+            if let Some(head) = &model.head {
+                let head_matrix = translation
+                    * yaw_matrix(entity.head_yaw)
+                    * Matrix4::new_translation(&Vector3::new(0.0, NECK_HEIGHT, 0.0))
+                    * Matrix4::from_axis_angle(&Vector3::x_axis(), rotation[0].to_radians());
+                set_model_matrix(model_uniform, &head_matrix);
+                head.draw(state);
+            }
+        }
+    }
+}
+
+fn yaw_matrix(yaw: f32) -> Matrix4<f32> {
+    Matrix4::from_axis_angle(&Vector3::y_axis(), -yaw.to_radians())
+}
+
+pub fn set_model_matrix(model_uniform: i32, matrix: &Matrix4<f32>) {
+    unsafe {
+        gl::UniformMatrix4fv(model_uniform, 1, gl::FALSE, matrix.as_slice().as_ptr());
+    }
+}
+
+/// Adds a box given in skin pixels.
+fn push_pixel_box(mesh: &mut Mesh, min: [f32; 3], max: [f32; 3], color: [f32; 4]) {
+    let scale = |v: [f32; 3]| [v[0] * PIXEL, v[1] * PIXEL, v[2] * PIXEL];
+    mesh.push_box(scale(min), scale(max), color, ENTITY_LIGHT, [false; 6]);
+}
+
+/// Picks a shirt colour from the player's UUID so that players can be told
+/// apart at a glance.
+fn shirt_color(uuid: u128) -> [f32; 4] {
+    let hash = (uuid ^ (uuid >> 64)) as u64;
+    let hash = hash.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    [
+        0.2 + ((hash >> 40) & 0xff) as f32 / 320.0,
+        0.2 + ((hash >> 48) & 0xff) as f32 / 320.0,
+        0.2 + ((hash >> 56) & 0xff) as f32 / 320.0,
+        1.0,
+    ]
+}
+
+fn build_model(entity: &Entity, state: &mut RenderState) -> EntityModel {
+    match entity.kind {
+        EntityKind::Player => {
+            // Facing +Z, with the feet at the origin.
+            let shirt = shirt_color(entity.uuid);
+            let mut body = Mesh::new();
+            push_pixel_box(
+                &mut body,
+                [-4.0, 0.0, -2.0],
+                [0.0, 12.0, 2.0],
+                TROUSERS_COLOR,
+            );
+            push_pixel_box(
+                &mut body,
+                [0.0, 0.0, -2.0],
+                [4.0, 12.0, 2.0],
+                TROUSERS_COLOR,
+            );
+            push_pixel_box(&mut body, [-4.0, 12.0, -2.0], [4.0, 24.0, 2.0], shirt);
+            push_pixel_box(&mut body, [-8.0, 12.0, -2.0], [-4.0, 24.0, 2.0], SKIN_COLOR);
+            push_pixel_box(&mut body, [4.0, 12.0, -2.0], [8.0, 24.0, 2.0], SKIN_COLOR);
+
+            // Built around the neck so it can pitch in place.
+            let mut head = Mesh::new();
+            push_pixel_box(&mut head, [-4.0, 0.0, -4.0], [4.0, 7.0, 4.0], SKIN_COLOR);
+            push_pixel_box(&mut head, [-4.0, 7.0, -4.0], [4.0, 8.0, 4.0], HAIR_COLOR);
+
+            EntityModel {
+                body: ChunkMesh::upload(&body, state),
+                head: Some(ChunkMesh::upload(&head, state)),
+            }
+        }
+        EntityKind::Other(_) => {
+            let mut body = Mesh::new();
+            push_pixel_box(
+                &mut body,
+                [-4.0, 0.0, -4.0],
+                [4.0, 8.0, 4.0],
+                OTHER_ENTITY_COLOR,
+            );
+            EntityModel {
+                body: ChunkMesh::upload(&body, state),
+                head: None,
+            }
+        }
+    }
+}
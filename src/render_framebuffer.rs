@@ -0,0 +1,113 @@
+use std::io;
+
+/// An offscreen framebuffer with an sRGB colour buffer and a depth buffer,
+/// for rendering without drawing to a window.
+pub struct Framebuffer {
+    framebuffer: u32,
+    color: u32,
+    depth: u32,
+}
+
+impl Framebuffer {
+    /// The widest or tallest framebuffer the GPU can make.
+    pub fn max_size() -> u32 {
+        let mut size = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_RENDERBUFFER_SIZE, &mut size);
+        }
+        size.max(0) as u32
+    }
+
+    /// Fails if the size is beyond what the GPU supports, rather than
+    /// leaving the framebuffer incomplete.
+    pub fn new(width: u32, height: u32) -> io::Result<Framebuffer> {
+        let max_size = Framebuffer::max_size();
+        if width == 0 || height == 0 || width > max_size || height > max_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}x{} is not a framebuffer size between 1x1 and {}x{}",
+                    width, height, max_size, max_size
+                ),
+            ));
+        }
+
+        let mut framebuffer = 0;
+        let mut renderbuffers = [0; 2];
+        unsafe {
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[0]);
+            gl::RenderbufferStorage(
+                gl::RENDERBUFFER,
+                gl::SRGB8_ALPHA8,
+                width as i32,
+                height as i32,
+            );
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::RENDERBUFFER,
+                renderbuffers[0],
+            );
+
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[1]);
+            gl::RenderbufferStorage(
+                gl::RENDERBUFFER,
+                gl::DEPTH_COMPONENT24,
+                width as i32,
+                height as i32,
+            );
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::RENDERBUFFER,
+                renderbuffers[1],
+            );
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            // Built first so that dropping it frees everything on failure.
+            let framebuffer = Framebuffer {
+                framebuffer,
+                color: renderbuffers[0],
+                depth: renderbuffers[1],
+            };
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("Offscreen framebuffer is incomplete: {:#x}", status),
+                ));
+            }
+            Ok(framebuffer)
+        }
+    }
+
+    /// Makes this the framebuffer that gets drawn to and read from.
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        }
+    }
+
+    /// Goes back to drawing to the window.
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteRenderbuffers(1, &self.color);
+            gl::DeleteRenderbuffers(1, &self.depth);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use gl::types::*;
+use nalgebra::Matrix4;
+use std::mem;
+use std::os::raw::c_void;
+
+use crate::render_shader::{compile_program, get_uniform_location};
+use crate::render_state::RenderState;
+
+const VERTEX_SHADER_SOURCE: &str = r#"
+    #version 330 core
+    layout (location = 0) in vec3 aPos;
+    layout (location = 1) in vec4 aColor;
+
+    uniform mat4 transform;
+    uniform float alphaScale;
+
+    out vec4 vertexColor;
+
+    void main() {
+       gl_Position = transform * vec4(aPos, 1.0);
+       vertexColor = vec4(pow(aColor.rgb, vec3(2.2)), aColor.a * alphaScale);
+    }
+"#;
+
+const FRAGMENT_SHADER_SOURCE: &str = r#"
+    #version 330 core
+    in vec4 vertexColor;
+    out vec4 FragColor;
+
+    void main() {
+       FragColor = vertexColor;
+    }
+"#;
+
+// How visible lines hidden behind blocks are, so that highlights can still
+// be followed through walls.
+const HIDDEN_ALPHA: f32 = 0.3;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+/// Draws coloured lines in the world, for outlining blocks and regions.
+/// Lines are queued during the frame and drawn with the scene until they
+/// are cleared at the end of it.
+pub struct LineRenderer {
+    program: u32,
+    transform_uniform: i32,
+    alpha_scale_uniform: i32,
+    vao: u32,
+    vbo: u32,
+    vertices: Vec<LineVertex>,
+}
+
+impl LineRenderer {
+    pub fn init(state: &mut RenderState) -> LineRenderer {
+        let program = compile_program(VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE);
+        let transform_uniform = get_uniform_location(program, "transform");
+        let alpha_scale_uniform = get_uniform_location(program, "alphaScale");
+
+        let (mut vao, mut vbo) = (0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            state.bind_vertex_array(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let stride = mem::size_of::<LineVertex>() as GLsizei;
+            let attributes: [(u32, i32, usize); 2] = [(0, 3, 0), (1, 4, 3)];
+            for (index, size, offset) in attributes.iter() {
+                gl::VertexAttribPointer(
+                    *index,
+                    *size,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    stride,
+                    (offset * mem::size_of::<GLfloat>()) as *const c_void,
+                );
+                gl::EnableVertexAttribArray(*index);
+            }
+        }
+
+        LineRenderer {
+            program,
+            transform_uniform,
+            alpha_scale_uniform,
+            vao,
+            vbo,
+            vertices: Vec::new(),
+        }
+    }
+
+    pub fn queue_line(&mut self, from: [f32; 3], to: [f32; 3], color: [f32; 4]) {
+        self.vertices.push(LineVertex {
+            position: from,
+            color,
+        });
+        self.vertices.push(LineVertex {
+            position: to,
+            color,
+        });
+    }
+
+    /// Queues the twelve edges of a box.
+    pub fn queue_box(&mut self, min: [f32; 3], max: [f32; 3], color: [f32; 4]) {
+        let corner = |i: usize| {
+            [
+                if i & 1 == 0 { min[0] } else { max[0] },
+                if i & 2 == 0 { min[1] } else { max[1] },
+                if i & 4 == 0 { min[2] } else { max[2] },
+            ]
+        };
+        // Each edge joins two corners differing in one axis.
+        for i in 0..8 {
+            for axis in [1, 2, 4].iter() {
+                if i & axis == 0 {
+                    self.queue_line(corner(i), corner(i | axis), color);
+                }
+            }
+        }
+    }
+
+    /// Draws the queued lines through `transform`, faintly where they are
+    /// hidden and fully where they are not.
+    pub fn draw(&mut self, state: &mut RenderState, transform: &Matrix4<f32>) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        state.use_program(self.program);
+        state.bind_vertex_array(self.vao);
+        state.set_blend(true);
+        state.set_depth_write(false);
+        unsafe {
+            gl::UniformMatrix4fv(
+                self.transform_uniform,
+                1,
+                gl::FALSE,
+                transform.as_slice().as_ptr(),
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertices.len() * mem::size_of::<LineVertex>()) as GLsizeiptr,
+                self.vertices.as_ptr() as *const c_void,
+                gl::STREAM_DRAW,
+            );
+
+            state.set_depth_test(false);
+            gl::Uniform1f(self.alpha_scale_uniform, HIDDEN_ALPHA);
+            gl::DrawArrays(gl::LINES, 0, self.vertices.len() as i32);
+
+            state.set_depth_test(true);
+            gl::Uniform1f(self.alpha_scale_uniform, 1.0);
+            gl::DrawArrays(gl::LINES, 0, self.vertices.len() as i32);
+        }
+        state.set_depth_write(true);
+        state.set_blend(false);
+    }
+
+    /// Drops everything queued this frame.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+}
@@ -0,0 +1,188 @@
+use crate::render_mesher::{build_section_mesh, SectionMesh};
+use crate::world::{SectionPos, SectionSnapshot, SECTION_SIZE};
+use nalgebra::Vector3;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+
+pub struct MeshResult {
+    pub pos: SectionPos,
+    pub generation: u64,
+    pub mesh: SectionMesh,
+}
+
+struct MeshJob {
+    snapshot: SectionSnapshot,
+    generation: u64,
+}
+
+struct JobQueue {
+    // Keyed by section so a section that is dirtied again before a worker
+    // picks it up is only meshed once, from the newest snapshot.
+    jobs: HashMap<SectionPos, MeshJob>,
+    focus: Vector3<f32>,
+    shutdown: bool,
+    // Jobs taken off the queue whose results haven't been sent yet.
+    busy: usize,
+    // Workers still running. One that panics while meshing is gone.
+    workers: usize,
+}
+
+/// A pool of threads that turn section snapshots into meshes. Jobs closest
+/// to the focus point (normally the camera) are meshed first.
+pub struct MeshWorkerPool {
+    queue: Arc<(Mutex<JobQueue>, Condvar)>,
+    results: Receiver<MeshResult>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl MeshWorkerPool {
+    pub fn new(worker_count: usize) -> MeshWorkerPool {
+        let queue = Arc::new((
+            Mutex::new(JobQueue {
+                jobs: HashMap::new(),
+                focus: Vector3::new(0.0, 0.0, 0.0),
+                shutdown: false,
+                busy: 0,
+                workers: worker_count.max(1),
+            }),
+            Condvar::new(),
+        ));
+        let (result_sender, results) = channel();
+
+        let workers = (0..worker_count.max(1))
+            .map(|i| {
+                let queue = queue.clone();
+                let result_sender = result_sender.clone();
+                thread::Builder::new()
+                    .name(format!("mesh-worker-{}", i))
+                    .spawn(move || worker_loop(queue, result_sender))
+                    .expect("Failed to spawn mesh worker thread")
+            })
+            .collect();
+
+        MeshWorkerPool {
+            queue,
+            results,
+            workers,
+        }
+    }
+
+    pub fn submit(&self, snapshot: SectionSnapshot, generation: u64) {
+        let (lock, condvar) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+        queue.jobs.insert(
+            snapshot.pos,
+            MeshJob {
+                snapshot,
+                generation,
+            },
+        );
+        condvar.notify_one();
+    }
+
+    /// Updates the point that job priority is measured from.
+    pub fn set_focus(&self, focus: &Vector3<f32>) {
+        let (lock, _) = &*self.queue;
+        lock.lock().unwrap().focus = focus.clone_owned();
+    }
+
+    /// Drops any queued job for the section, e.g. once it is unloaded.
+    pub fn cancel(&self, pos: SectionPos) {
+        let (lock, _) = &*self.queue;
+        lock.lock().unwrap().jobs.remove(&pos);
+    }
+
+    /// Whether any job is being meshed, or is queued with a worker left to
+    /// take it. Once this is false, every result there will be has been
+    /// sent.
+    pub fn has_work(&self) -> bool {
+        let (lock, _) = &*self.queue;
+        let queue = lock.lock().unwrap();
+        queue.busy > 0 || (!queue.jobs.is_empty() && queue.workers > 0)
+    }
+
+    /// Collects every mesh finished since the last call.
+    pub fn poll_results(&self) -> Vec<MeshResult> {
+        self.results.try_iter().collect()
+    }
+}
+
+impl Drop for MeshWorkerPool {
+    fn drop(&mut self) {
+        {
+            let (lock, condvar) = &*self.queue;
+            lock.lock().unwrap().shutdown = true;
+            condvar.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+pub fn section_center(pos: SectionPos) -> Vector3<f32> {
+    let origin = pos.origin();
+    let half = SECTION_SIZE as f32 / 2.0;
+    Vector3::new(
+        origin.x as f32 + half,
+        origin.y as f32 + half,
+        origin.z as f32 + half,
+    )
+}
+
+/// Marks a job as done once dropped, which happens even if meshing it
+/// panics, so that nothing waits on a result that will never come.
+struct InFlight<'a>(&'a Mutex<JobQueue>);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut queue = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.busy -= 1;
+        if thread::panicking() {
+            queue.workers -= 1;
+        }
+    }
+}
+
+fn worker_loop(queue: Arc<(Mutex<JobQueue>, Condvar)>, results: Sender<MeshResult>) {
+    let (lock, condvar) = &*queue;
+    loop {
+        let job = {
+            let mut queue = lock.lock().unwrap();
+            while queue.jobs.is_empty() && !queue.shutdown {
+                queue = condvar.wait(queue).unwrap();
+            }
+            if queue.shutdown {
+                return;
+            }
+
+            // The queue is small enough that a linear scan for the nearest
+            // job is cheaper than keeping a heap ordered as the focus moves.
+            let focus = queue.focus.clone_owned();
+            let nearest = *queue
+                .jobs
+                .keys()
+                .min_by(|a, b| {
+                    let distance_a = (section_center(**a) - focus).norm_squared();
+                    let distance_b = (section_center(**b) - focus).norm_squared();
+                    distance_a.partial_cmp(&distance_b).unwrap()
+                })
+                .unwrap();
+            queue.busy += 1;
+            queue.jobs.remove(&nearest).unwrap()
+        };
+        let _in_flight = InFlight(lock);
+
+        let mesh = build_section_mesh(&job.snapshot);
+        let result = MeshResult {
+            pos: job.snapshot.pos,
+            generation: job.generation,
+            mesh,
+        };
+        if results.send(result).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use crate::block::{Block, Direction, RenderLayer};
+use crate::world::{SectionSnapshot, SECTION_SIZE};
+use nalgebra::Vector3;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+    /// Brightness from the direction the face points in.
+    pub shade: f32,
+    /// Brightness from ambient occlusion, 1 being unoccluded.
+    pub occlusion: f32,
+    /// Sky and block light, scaled to between 0 and 1.
+    pub light: [f32; 2],
+}
+
+/// Per-corner lighting for a quad, in the same order as its corners.
+#[derive(Clone, Copy)]
+struct QuadLighting {
+    occlusion: [f32; 4],
+    light: [[f32; 2]; 4],
+}
+
+impl QuadLighting {
+    fn flat(light: (u8, u8)) -> QuadLighting {
+        QuadLighting {
+            occlusion: [1.0; 4],
+            light: [light_to_float(light); 4],
+        }
+    }
+}
+
+pub struct Mesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new() -> Mesh {
+        Mesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns the indices reordered so that quads further from the eye
+    /// come first, as needed for blending translucent faces correctly.
+    pub fn sorted_indices(&self, eye: &Vector3<f32>) -> Vec<u32> {
+        // Every quad is four consecutive vertices and six consecutive
+        // indices, so quads can be moved around as whole chunks of indices.
+        let mut quads: Vec<(f32, &[u32])> = self
+            .indices
+            .chunks(6)
+            .map(|quad| {
+                let base = *quad.iter().min().unwrap() as usize;
+                let mut center = Vector3::new(0.0, 0.0, 0.0);
+                for vertex in &self.vertices[base..base + 4] {
+                    center += Vector3::from(vertex.position);
+                }
+                center /= 4.0;
+                ((center - eye).norm_squared(), quad)
+            })
+            .collect();
+        quads.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+        quads
+            .iter()
+            .flat_map(|(_, quad)| quad.iter().copied())
+            .collect()
+    }
+
+    /// Adds a quad from four corners given in counter-clockwise order when
+    /// viewed from the front.
+    fn push_quad(
+        &mut self,
+        corners: [[f32; 3]; 4],
+        color: [f32; 4],
+        direction: Direction,
+        lighting: QuadLighting,
+    ) {
+        let base = self.vertices.len() as u32;
+        for (i, corner) in corners.iter().enumerate() {
+            self.vertices.push(MeshVertex {
+                position: *corner,
+                color,
+                shade: face_shade(direction),
+                occlusion: lighting.occlusion[i],
+                light: lighting.light[i],
+            });
+        }
+
+        // Split the quad along its darker diagonal, otherwise the
+        // interpolated occlusion shows up as a bright seam across the face.
+        let occlusion = lighting.occlusion;
+        if occlusion[0] + occlusion[2] > occlusion[1] + occlusion[3] {
+            self.indices.extend_from_slice(&[
+                base + 1,
+                base + 2,
+                base + 3,
+                base + 1,
+                base + 3,
+                base,
+            ]);
+        } else {
+            self.indices
+                .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+    }
+
+    /// Adds the faces of an axis aligned box with flat lighting. Faces
+    /// flagged in `skip` are left out.
+    pub fn push_box(
+        &mut self,
+        min: [f32; 3],
+        max: [f32; 3],
+        color: [f32; 4],
+        light: (u8, u8),
+        skip: [bool; 6],
+    ) {
+        for (i, direction) in Direction::ALL.iter().enumerate() {
+            if skip[i] {
+                continue;
+            }
+            self.push_quad(
+                box_face(min, max, *direction),
+                color,
+                *direction,
+                QuadLighting::flat(light),
+            );
+        }
+    }
+}
+
+/// The meshes of a section, one for each render layer.
+pub struct SectionMesh {
+    pub opaque: Mesh,
+    pub cutout: Mesh,
+    pub translucent: Mesh,
+}
+
+impl SectionMesh {
+    pub fn new() -> SectionMesh {
+        SectionMesh {
+            opaque: Mesh::new(),
+            cutout: Mesh::new(),
+            translucent: Mesh::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.opaque.is_empty() && self.cutout.is_empty() && self.translucent.is_empty()
+    }
+
+    fn layer_mut(&mut self, layer: RenderLayer) -> &mut Mesh {
+        match layer {
+            RenderLayer::Opaque => &mut self.opaque,
+            RenderLayer::Cutout => &mut self.cutout,
+            RenderLayer::Translucent => &mut self.translucent,
+        }
+    }
+}
+
+fn block_color(block: Block) -> [f32; 4] {
+    let [r, g, b] = block.color();
+    [r, g, b, block.alpha()]
+}
+
+/// Vanilla's fixed brightness for each face direction, which gives blocks
+/// some shape even under uniform light.
+fn face_shade(direction: Direction) -> f32 {
+    match direction {
+        Direction::Up => 1.0,
+        Direction::Down => 0.5,
+        Direction::North | Direction::South => 0.8,
+        Direction::West | Direction::East => 0.6,
+    }
+}
+
+fn light_to_float(light: (u8, u8)) -> [f32; 2] {
+    [light.0 as f32 / 15.0, light.1 as f32 / 15.0]
+}
+
+/// Returns the corners of one face of a box, wound counter-clockwise when
+/// looking at the face from outside of the box.
+fn box_face(min: [f32; 3], max: [f32; 3], direction: Direction) -> [[f32; 3]; 4] {
+    let [x0, y0, z0] = min;
+    let [x1, y1, z1] = max;
+    match direction {
+        Direction::Down => [[x0, y0, z0], [x1, y0, z0], [x1, y0, z1], [x0, y0, z1]],
+        Direction::Up => [[x0, y1, z1], [x1, y1, z1], [x1, y1, z0], [x0, y1, z0]],
+        Direction::North => [[x1, y0, z0], [x0, y0, z0], [x0, y1, z0], [x1, y1, z0]],
+        Direction::South => [[x0, y0, z1], [x1, y0, z1], [x1, y1, z1], [x0, y1, z1]],
+        Direction::West => [[x0, y0, z0], [x0, y0, z1], [x0, y1, z1], [x0, y1, z0]],
+        Direction::East => [[x1, y0, z1], [x1, y0, z0], [x1, y1, z0], [x1, y1, z1]],
+    }
+}
+
+/// Computes smooth lighting for a full block face. Each corner looks at the
+/// three blocks touching it in front of the face: the two sides and the
+/// diagonal. Opaque blocks there darken the corner, and the light of the
+/// transparent ones is averaged.
+fn face_lighting(
+    snapshot: &SectionSnapshot,
+    block: (i32, i32, i32),
+    direction: Direction,
+    corners: &[[f32; 3]; 4],
+    min: [f32; 3],
+) -> QuadLighting {
+    let (dx, dy, dz) = direction.offset();
+    let front = (block.0 + dx, block.1 + dy, block.2 + dz);
+    let front_light = snapshot.get_light(front.0, front.1, front.2);
+
+    let mut lighting = QuadLighting::flat(front_light);
+    for (i, corner) in corners.iter().enumerate() {
+        // Which way the corner leans along each axis, ignoring the axis the
+        // face points along.
+        let lean = |axis: usize, offset: i32| -> i32 {
+            if offset != 0 {
+                0
+            } else if corner[axis] > min[axis] {
+                1
+            } else {
+                -1
+            }
+        };
+        let (lx, ly, lz) = (lean(0, dx), lean(1, dy), lean(2, dz));
+
+        // Split the lean into the two side offsets.
+        let (side1, side2) = if dx != 0 {
+            ((0, ly, 0), (0, 0, lz))
+        } else if dy != 0 {
+            ((lx, 0, 0), (0, 0, lz))
+        } else {
+            ((lx, 0, 0), (0, ly, 0))
+        };
+
+        let sample = |offset: (i32, i32, i32)| {
+            let (x, y, z) = (front.0 + offset.0, front.1 + offset.1, front.2 + offset.2);
+            (
+                snapshot.get_block(x, y, z).is_opaque(),
+                snapshot.get_light(x, y, z),
+            )
+        };
+        let (side1_opaque, side1_light) = sample(side1);
+        let (side2_opaque, side2_light) = sample(side2);
+        let (corner_opaque, corner_light) = sample((lx, ly, lz));
+
+        let level = if side1_opaque && side2_opaque {
+            0
+        } else {
+            3 - side1_opaque as u8 - side2_opaque as u8 - corner_opaque as u8
+        };
+        lighting.occlusion[i] = 0.4 + 0.2 * level as f32;
+
+        let mut total = light_to_float(front_light);
+        let mut count = 1.0;
+        for (opaque, light) in [
+            (side1_opaque, side1_light),
+            (side2_opaque, side2_light),
+            (corner_opaque, corner_light),
+        ]
+        .iter()
+        {
+            if !opaque {
+                let light = light_to_float(*light);
+                total[0] += light[0];
+                total[1] += light[1];
+                count += 1.0;
+            }
+        }
+        lighting.light[i] = [total[0] / count, total[1] / count];
+    }
+    lighting
+}
+
+/// Builds the mesh for a single section. Vertex positions are in world
+/// space so every section can be drawn with the same transform.
+pub fn build_section_mesh(snapshot: &SectionSnapshot) -> SectionMesh {
+    let mut section_mesh = SectionMesh::new();
+    let origin = snapshot.pos.origin();
+
+    for y in 0..SECTION_SIZE {
+        for z in 0..SECTION_SIZE {
+            for x in 0..SECTION_SIZE {
+                let block = snapshot.get_block(x, y, z);
+                if block.is_air() {
+                    continue;
+                }
+                let mesh = section_mesh.layer_mut(block.render_layer());
+
+                let min = [
+                    (origin.x + x) as f32,
+                    (origin.y + y) as f32,
+                    (origin.z + z) as f32,
+                ];
+
+                if block.is_full_cube() {
+                    let max = [min[0] + 1.0, min[1] + 1.0, min[2] + 1.0];
+                    for direction in Direction::ALL.iter() {
+                        let (dx, dy, dz) = direction.offset();
+                        let neighbour = snapshot.get_block(x + dx, y + dy, z + dz);
+                        if face_hidden(block, neighbour) {
+                            continue;
+                        }
+                        let corners = box_face(min, max, *direction);
+                        let lighting =
+                            face_lighting(snapshot, (x, y, z), *direction, &corners, min);
+                        mesh.push_quad(corners, block_color(block), *direction, lighting);
+                    }
+                } else {
+                    push_partial_block(mesh, block, min, snapshot.get_light(x, y, z));
+                }
+            }
+        }
+    }
+    section_mesh
+}
+
+/// A face is hidden when the neighbour is opaque, or when both blocks are
+/// the same see-through block (e.g. the faces between two glass blocks).
+fn face_hidden(block: Block, neighbour: Block) -> bool {
+    neighbour.is_opaque() || (neighbour == block && neighbour.is_full_cube())
+}
+
+fn push_partial_block(mesh: &mut Mesh, block: Block, min: [f32; 3], light: (u8, u8)) {
+    let [x, y, z] = min;
+    match block {
+        Block::RedstoneWire { .. } => {
+            let height = 1.0 / 16.0;
+            mesh.push_box(
+                [x, y, z],
+                [x + 1.0, y + height, z + 1.0],
+                block_color(block),
+                light,
+                [true, false, true, true, true, true],
+            );
+        }
+        Block::RedstoneTorch { .. } => {
+            let half_width = 1.0 / 16.0;
+            mesh.push_box(
+                [x + 0.5 - half_width, y, z + 0.5 - half_width],
+                [x + 0.5 + half_width, y + 10.0 / 16.0, z + 0.5 + half_width],
+                block_color(block),
+                light,
+                [false; 6],
+            );
+        }
+        _ => {
+            if let Some((low, high)) = block.partial_bounds() {
+                mesh.push_box(
+                    [x + low[0], y + low[1], z + low[2]],
+                    [x + high[0], y + high[1], z + high[2]],
+                    block_color(block),
+                    light,
+                    [false; 6],
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+/// Tracks the GL state we change while rendering, so that state which is
+/// already set doesn't get set again. All GL state changes made during a
+/// frame should go through here, otherwise the tracked state goes stale.
+pub struct RenderState {
+    program: u32,
+    vertex_array: u32,
+    depth_test: bool,
+    depth_write: bool,
+    cull_face: bool,
+    blend: bool,
+}
+
+impl RenderState {
+    /// Puts GL into the state the rest of the renderer expects: depth
+    /// testing and back-face culling on, blending off, and output converted
+    /// to sRGB.
+    pub fn init() -> RenderState {
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::DepthFunc(gl::LEQUAL);
+            gl::DepthMask(gl::TRUE);
+
+            gl::Enable(gl::CULL_FACE);
+            gl::CullFace(gl::BACK);
+            gl::FrontFace(gl::CCW);
+
+            gl::Disable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            // Shaders work in linear colour, GL converts it to sRGB when
+            // writing to the framebuffer.
+            gl::Enable(gl::FRAMEBUFFER_SRGB);
+
+            gl::UseProgram(0);
+            gl::BindVertexArray(0);
+        }
+
+        RenderState {
+            program: 0,
+            vertex_array: 0,
+            depth_test: true,
+            depth_write: true,
+            cull_face: true,
+            blend: false,
+        }
+    }
+
+    /// Clears the colour and depth buffers. The colour is given in sRGB.
+    pub fn clear(&mut self, color: [f32; 3]) {
+        // Clearing the depth buffer is skipped while depth writes are off,
+        // so make sure they are on.
+        self.set_depth_write(true);
+        unsafe {
+            gl::ClearColor(
+                srgb_to_linear(color[0]),
+                srgb_to_linear(color[1]),
+                srgb_to_linear(color[2]),
+                1.0,
+            );
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+    }
+
+    pub fn use_program(&mut self, program: u32) {
+        if self.program != program {
+            unsafe {
+                gl::UseProgram(program);
+            }
+            self.program = program;
+        }
+    }
+
+    pub fn bind_vertex_array(&mut self, vertex_array: u32) {
+        if self.vertex_array != vertex_array {
+            unsafe {
+                gl::BindVertexArray(vertex_array);
+            }
+            self.vertex_array = vertex_array;
+        }
+    }
+
+    /// Call after deleting vertex arrays. GL unbinds a deleted vertex array,
+    /// and its name may be handed out again, so the tracked binding can no
+    /// longer be trusted.
+    pub fn forget_vertex_array(&mut self) {
+        self.vertex_array = u32::MAX;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_depth_test(&mut self, enabled: bool) {
+        if self.depth_test != enabled {
+            set_capability(gl::DEPTH_TEST, enabled);
+            self.depth_test = enabled;
+        }
+    }
+
+    pub fn set_depth_write(&mut self, enabled: bool) {
+        if self.depth_write != enabled {
+            unsafe {
+                gl::DepthMask(if enabled { gl::TRUE } else { gl::FALSE });
+            }
+            self.depth_write = enabled;
+        }
+    }
+
+    pub fn set_cull_face(&mut self, enabled: bool) {
+        if self.cull_face != enabled {
+            set_capability(gl::CULL_FACE, enabled);
+            self.cull_face = enabled;
+        }
+    }
+
+    pub fn set_blend(&mut self, enabled: bool) {
+        if self.blend != enabled {
+            set_capability(gl::BLEND, enabled);
+            self.blend = enabled;
+        }
+    }
+}
+
+fn set_capability(capability: u32, enabled: bool) {
+    unsafe {
+        if enabled {
+            gl::Enable(capability);
+        } else {
+            gl::Disable(capability);
+        }
+    }
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
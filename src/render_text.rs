@@ -0,0 +1,453 @@
+use gl::types::*;
+use nalgebra::Matrix4;
+use std::mem;
+use std::os::raw::c_void;
+
+use crate::render_shader::{compile_program, get_uniform_location};
+use crate::render_state::RenderState;
+
+const VERTEX_SHADER_SOURCE: &str = r#"
+    #version 330 core
+    layout (location = 0) in vec2 aPos;
+    layout (location = 1) in vec2 aTexCoord;
+    layout (location = 2) in vec4 aColor;
+
+    uniform mat4 projection;
+
+    out vec2 texCoord;
+    out vec4 vertexColor;
+
+    void main() {
+       gl_Position = projection * vec4(aPos, 0.0, 1.0);
+       texCoord = aTexCoord;
+       vertexColor = vec4(pow(aColor.rgb, vec3(2.2)), aColor.a);
+    }
+"#;
+
+const FRAGMENT_SHADER_SOURCE: &str = r#"
+    #version 330 core
+    in vec2 texCoord;
+    in vec4 vertexColor;
+    out vec4 FragColor;
+
+    uniform sampler2D font;
+
+    void main() {
+       FragColor = vec4(vertexColor.rgb, vertexColor.a * texture(font, texCoord).r);
+    }
+"#;
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+
+// Glyphs are laid out in the font texture in cells with a pixel of spacing
+// to the right and below, which is also the spacing between characters.
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: usize = GLYPH_HEIGHT + 1;
+const ATLAS_COLUMNS: usize = 16;
+const ATLAS_ROWS: usize = 6;
+const ATLAS_WIDTH: usize = ATLAS_COLUMNS * CELL_WIDTH;
+const ATLAS_HEIGHT: usize = ATLAS_ROWS * CELL_HEIGHT;
+// The cell after the last glyph is filled solid and used for rectangles.
+const SOLID_CELL: usize = FONT.len();
+
+/// The text scale of the info panels, and the space around their text and
+/// between them and the edges of the screen.
+pub const PANEL_SCALE: f32 = 1.0;
+pub const PANEL_PADDING: f32 = 4.0;
+const PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+/// Where on the screen a panel goes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    /// Centred on a point, such as a block projected onto the screen.
+    Center(f32, f32),
+    /// Halfway down the left edge.
+    Left {
+        screen_height: u32,
+    },
+    TopRight {
+        screen_width: u32,
+    },
+    /// Halfway along the bottom edge.
+    Bottom {
+        screen_width: u32,
+        screen_height: u32,
+    },
+    BottomLeft {
+        screen_height: u32,
+    },
+    BottomRight {
+        screen_width: u32,
+        screen_height: u32,
+    },
+}
+
+impl Anchor {
+    /// The top left corner of a panel of the given size.
+    fn place(self, width: f32, height: f32) -> (f32, f32) {
+        let right = |screen_width: u32| screen_width as f32 - width - PANEL_PADDING;
+        let bottom = |screen_height: u32| screen_height as f32 - height - PANEL_PADDING;
+        match self {
+            Anchor::Center(x, y) => (x - width / 2.0, y - height / 2.0),
+            Anchor::Left { screen_height } => {
+                (PANEL_PADDING, (screen_height as f32 - height) / 2.0)
+            }
+            Anchor::TopRight { screen_width } => (right(screen_width), PANEL_PADDING),
+            Anchor::Bottom {
+                screen_width,
+                screen_height,
+            } => ((screen_width as f32 - width) / 2.0, bottom(screen_height)),
+            Anchor::BottomLeft { screen_height } => (PANEL_PADDING, bottom(screen_height)),
+            Anchor::BottomRight {
+                screen_width,
+                screen_height,
+            } => (right(screen_width), bottom(screen_height)),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct TextVertex {
+    position: [f32; 2],
+    tex_coord: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Draws text and flat rectangles on top of the frame, in screen pixels
+/// with the origin at the top left. Text and rectangles are queued during
+/// the frame and drawn together at the end of it.
+pub struct TextRenderer {
+    program: u32,
+    projection_uniform: i32,
+    vao: u32,
+    vbo: u32,
+    texture: u32,
+    vertices: Vec<TextVertex>,
+}
+
+impl TextRenderer {
+    pub fn init(state: &mut RenderState) -> TextRenderer {
+        let program = compile_program(VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE);
+        let projection_uniform = get_uniform_location(program, "projection");
+
+        let mut atlas = vec![0u8; ATLAS_WIDTH * ATLAS_HEIGHT];
+        for cell in 0..=SOLID_CELL {
+            let cell_x = (cell % ATLAS_COLUMNS) * CELL_WIDTH;
+            let cell_y = (cell / ATLAS_COLUMNS) * CELL_HEIGHT;
+            for y in 0..GLYPH_HEIGHT {
+                for x in 0..GLYPH_WIDTH {
+                    let set = if cell == SOLID_CELL {
+                        true
+                    } else {
+                        FONT[cell][y] & (1 << (GLYPH_WIDTH - 1 - x)) != 0
+                    };
+                    if set {
+                        atlas[(cell_y + y) * ATLAS_WIDTH + cell_x + x] = 0xff;
+                    }
+                }
+            }
+        }
+
+        let (mut vao, mut vbo, mut texture) = (0, 0, 0);
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::R8 as i32,
+                ATLAS_WIDTH as i32,
+                ATLAS_HEIGHT as i32,
+                0,
+                gl::RED,
+                gl::UNSIGNED_BYTE,
+                atlas.as_ptr() as *const c_void,
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            state.bind_vertex_array(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+            let stride = mem::size_of::<TextVertex>() as GLsizei;
+            let attributes: [(u32, i32, usize); 3] = [(0, 2, 0), (1, 2, 2), (2, 4, 4)];
+            for (index, size, offset) in attributes.iter() {
+                gl::VertexAttribPointer(
+                    *index,
+                    *size,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    stride,
+                    (offset * mem::size_of::<GLfloat>()) as *const c_void,
+                );
+                gl::EnableVertexAttribArray(*index);
+            }
+        }
+
+        TextRenderer {
+            program,
+            projection_uniform,
+            vao,
+            vbo,
+            texture,
+            vertices: Vec::new(),
+        }
+    }
+
+    /// The width in pixels of a line of text drawn at the given scale.
+    pub fn text_width(text: &str, scale: f32) -> f32 {
+        (text.chars().count() * CELL_WIDTH) as f32 * scale
+    }
+
+    /// The height in pixels of a line of text, including spacing.
+    pub fn line_height(scale: f32) -> f32 {
+        CELL_HEIGHT as f32 * scale
+    }
+
+    /// Queues a line of text with its top left corner at (x, y). Characters
+    /// outside of printable ASCII are drawn as '?'.
+    pub fn queue_text(&mut self, x: f32, y: f32, scale: f32, color: [f32; 4], text: &str) {
+        let mut cursor = x;
+        for character in text.chars() {
+            let code = character as usize;
+            let glyph = if code >= 32 && code - 32 < FONT.len() {
+                code - 32
+            } else {
+                '?' as usize - 32
+            };
+            if glyph != 0 {
+                self.queue_cell(cursor, y, scale, color, glyph);
+            }
+            cursor += CELL_WIDTH as f32 * scale;
+        }
+    }
+
+    /// Queues lines of text, each in its own colour, on a dark panel placed
+    /// by `anchor`.
+    pub fn queue_panel(&mut self, lines: &[(String, [f32; 4])], anchor: Anchor) {
+        let line_height = TextRenderer::line_height(PANEL_SCALE);
+        let width = lines
+            .iter()
+            .map(|(line, _)| TextRenderer::text_width(line, PANEL_SCALE))
+            .fold(0.0, f32::max);
+        let height = line_height * lines.len() as f32;
+        let (x, y) = self.queue_panel_background(width, height, anchor);
+        for (i, (line, color)) in lines.iter().enumerate() {
+            self.queue_text(x, y + line_height * i as f32, PANEL_SCALE, *color, line);
+        }
+    }
+
+    /// Queues the dark background of a panel with room for `width` by
+    /// `height` pixels of contents, for panels that draw more than text.
+    /// Returns where the contents go.
+    pub fn queue_panel_background(
+        &mut self,
+        width: f32,
+        height: f32,
+        anchor: Anchor,
+    ) -> (f32, f32) {
+        let (width, height) = (width + PANEL_PADDING * 2.0, height + PANEL_PADDING * 2.0);
+        let (x, y) = anchor.place(width, height);
+        self.queue_rect(x, y, width, height, PANEL_COLOR);
+        (x + PANEL_PADDING, y + PANEL_PADDING)
+    }
+
+    /// Queues a solid rectangle.
+    pub fn queue_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+        // Sample only the middle of the solid cell so its edges don't bleed
+        // in from the neighbouring empty pixels.
+        let (u, v) = cell_tex_coord(SOLID_CELL);
+        let middle = [u + 0.5 / ATLAS_WIDTH as f32, v + 0.5 / ATLAS_HEIGHT as f32];
+        self.push_quad([x, y], [x + width, y + height], middle, middle, color);
+    }
+
+    fn queue_cell(&mut self, x: f32, y: f32, scale: f32, color: [f32; 4], cell: usize) {
+        let (u, v) = cell_tex_coord(cell);
+        let uv_max = [
+            u + GLYPH_WIDTH as f32 / ATLAS_WIDTH as f32,
+            v + GLYPH_HEIGHT as f32 / ATLAS_HEIGHT as f32,
+        ];
+        let max = [
+            x + GLYPH_WIDTH as f32 * scale,
+            y + GLYPH_HEIGHT as f32 * scale,
+        ];
+        self.push_quad([x, y], max, [u, v], uv_max, color);
+    }
+
+    fn push_quad(
+        &mut self,
+        min: [f32; 2],
+        max: [f32; 2],
+        uv_min: [f32; 2],
+        uv_max: [f32; 2],
+        color: [f32; 4],
+    ) {
+        let corners = [
+            ([min[0], min[1]], [uv_min[0], uv_min[1]]),
+            ([max[0], min[1]], [uv_max[0], uv_min[1]]),
+            ([max[0], max[1]], [uv_max[0], uv_max[1]]),
+            ([min[0], max[1]], [uv_min[0], uv_max[1]]),
+        ];
+        for i in [0, 1, 2, 0, 2, 3].iter() {
+            let (position, tex_coord) = corners[*i];
+            self.vertices.push(TextVertex {
+                position,
+                tex_coord,
+                color,
+            });
+        }
+    }
+
+    /// Draws everything queued this frame over the top of the scene.
+    pub fn draw(&mut self, state: &mut RenderState, width: u32, height: u32) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        // Pixel coordinates with y pointing down the screen.
+        let projection =
+            Matrix4::new_orthographic(0.0, width as f32, height as f32, 0.0, -1.0, 1.0);
+
+        state.use_program(self.program);
+        state.bind_vertex_array(self.vao);
+        state.set_depth_test(false);
+        state.set_cull_face(false);
+        state.set_blend(true);
+        unsafe {
+            gl::UniformMatrix4fv(
+                self.projection_uniform,
+                1,
+                gl::FALSE,
+                projection.as_slice().as_ptr(),
+            );
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertices.len() * mem::size_of::<TextVertex>()) as GLsizeiptr,
+                self.vertices.as_ptr() as *const c_void,
+                gl::STREAM_DRAW,
+            );
+            gl::DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as i32);
+        }
+        state.set_blend(false);
+        state.set_cull_face(true);
+        state.set_depth_test(true);
+
+        self.vertices.clear();
+    }
+}
+
+fn cell_tex_coord(cell: usize) -> (f32, f32) {
+    (
+        ((cell % ATLAS_COLUMNS) * CELL_WIDTH) as f32 / ATLAS_WIDTH as f32,
+        ((cell / ATLAS_COLUMNS) * CELL_HEIGHT) as f32 / ATLAS_HEIGHT as f32,
+    )
+}
+
+/// A 5x7 pixel font covering printable ASCII, starting from the space. Each
+/// glyph is seven rows from top to bottom, with the leftmost pixel of a row
+/// in bit 4.
+#[rustfmt::skip]
+const FONT: [[u8; GLYPH_HEIGHT]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // '!'
+    [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], // '#'
+    [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // '%'
+    [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], // '&'
+    [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // "'"
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // ')'
+    [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // '/'
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], // '0'
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], // '1'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], // '2'
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], // '3'
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], // '4'
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], // '5'
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], // '6'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // '7'
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], // '8'
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // '<'
+    [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // '>'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], // '@'
+    [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'A'
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], // 'B'
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], // 'C'
+    [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], // 'D'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], // 'E'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], // 'F'
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], // 'G'
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'H'
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], // 'L'
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // 'N'
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'O'
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], // 'P'
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], // 'Q'
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], // 'R'
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], // 'S'
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], // 'W'
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], // 'X'
+    [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], // 'Y'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], // 'Z'
+    [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // '\\'
+    [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], // ']'
+    [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], // '_'
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f], // 'a'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e], // 'b'
+    [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e], // 'c'
+    [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f], // 'd'
+    [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e], // 'e'
+    [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08], // 'f'
+    [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e], // 'g'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], // 'h'
+    [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e], // 'i'
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c], // 'j'
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], // 'k'
+    [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'l'
+    [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11], // 'm'
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], // 'n'
+    [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e], // 'o'
+    [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10], // 'p'
+    [0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01], // 'q'
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], // 'r'
+    [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e], // 's'
+    [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06], // 't'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d], // 'u'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'v'
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a], // 'w'
+    [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11], // 'x'
+    [0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e], // 'y'
+    [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f], // 'z'
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], // '{'
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // '|'
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], // '}'
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], // '~'
+];
@@ -0,0 +1,431 @@
+use crate::packet::{ClientboundPacket, PacketReader, PacketWriter, RawPacket};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use nalgebra::Vector3;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+
+// Replays are a gzipped stream of records following this header. Records
+// use the same primitive encodings as the protocol.
+const MAGIC: &[u8; 4] = b"MCRP";
+const VERSION: u8 = 1;
+
+const PACKET_RECORD: u8 = 0;
+const PLAYER_RECORD: u8 = 1;
+
+// The local player is recorded once per tick.
+const PLAYER_RECORD_INTERVAL: f32 = 0.05;
+
+/// The entity id the recorded local player is shown with during playback,
+/// chosen so that it can't clash with an id handed out by the server.
+pub const REPLAY_PLAYER_ID: i32 = i32::MIN;
+
+const MIN_SPEED: f32 = 0.125;
+const MAX_SPEED: f32 = 8.0;
+
+#[derive(Clone, Debug)]
+pub enum ReplayEvent {
+    Packet(RawPacket),
+    /// Where the local player was, as the position of their feet and a
+    /// (pitch, yaw) in Minecraft's conventions.
+    Player {
+        position: Vector3<f32>,
+        pitch: f32,
+        yaw: f32,
+    },
+}
+
+/// Turns a recorded position of the local player into the packets that
+/// show them like any other player. `spawned` says whether they have been
+/// spawned already.
+pub fn replay_player_packets(
+    position: &Vector3<f32>,
+    pitch: f32,
+    yaw: f32,
+    spawned: bool,
+) -> [ClientboundPacket; 2] {
+    let (x, y, z) = (position[0] as f64, position[1] as f64, position[2] as f64);
+    let movement = if spawned {
+        ClientboundPacket::EntityTeleport {
+            entity_id: REPLAY_PLAYER_ID,
+            x,
+            y,
+            z,
+            yaw,
+            pitch,
+            on_ground: false,
+        }
+    } else {
+        ClientboundPacket::SpawnPlayer {
+            entity_id: REPLAY_PLAYER_ID,
+            uuid: 0,
+            x,
+            y,
+            z,
+            yaw,
+            pitch,
+        }
+    };
+    let head_look = ClientboundPacket::EntityHeadLook {
+        entity_id: REPLAY_PLAYER_ID,
+        head_yaw: yaw,
+    };
+    [movement, head_look]
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplayRecord {
+    /// Seconds since the start of the recording.
+    pub time: f32,
+    pub event: ReplayEvent,
+}
+
+/// Writes everything the server sends us, along with where we were, to a
+/// replay file.
+pub struct ReplayRecorder {
+    encoder: GzEncoder<BufWriter<File>>,
+    start: Instant,
+    last_player_record: Option<f32>,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: &Path) -> io::Result<ReplayRecorder> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(MAGIC)?;
+        encoder.write_all(&[VERSION])?;
+        Ok(ReplayRecorder {
+            encoder,
+            start: Instant::now(),
+            last_player_record: None,
+        })
+    }
+
+    pub fn record_packet(&mut self, raw: &RawPacket) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f32();
+        self.encoder.write_all(&packet_record(time, raw))
+    }
+
+    /// Records the local player, at most once per tick.
+    pub fn record_player(
+        &mut self,
+        position: &Vector3<f32>,
+        pitch: f32,
+        yaw: f32,
+    ) -> io::Result<()> {
+        let time = self.start.elapsed().as_secs_f32();
+        if let Some(last) = self.last_player_record {
+            if time - last < PLAYER_RECORD_INTERVAL {
+                return Ok(());
+            }
+        }
+        self.last_player_record = Some(time);
+
+        self.encoder
+            .write_all(&player_record(time, position, pitch, yaw))
+    }
+}
+
+fn packet_record(time: f32, raw: &RawPacket) -> Vec<u8> {
+    let mut writer = begin_record(PACKET_RECORD, time);
+    writer.write_varint(raw.id);
+    writer.write_varint(raw.data.len() as i32);
+    writer.write_bytes(&raw.data);
+    writer.finish()
+}
+
+fn player_record(time: f32, position: &Vector3<f32>, pitch: f32, yaw: f32) -> Vec<u8> {
+    let mut writer = begin_record(PLAYER_RECORD, time);
+    for &value in [position[0], position[1], position[2], pitch, yaw].iter() {
+        writer.write_f32(value);
+    }
+    writer.finish()
+}
+
+/// Starts writing a record of the given kind, stamped with `time`.
+fn begin_record(kind: u8, time: f32) -> PacketWriter {
+    let mut writer = PacketWriter::new();
+    writer.write_u8(kind);
+    writer.write_f32(time);
+    writer
+}
+
+/// Reads a whole replay file. A recording cut short, e.g. by a crash, is
+/// read up to its last complete record.
+pub fn read_replay(path: &Path) -> io::Result<Vec<ReplayRecord>> {
+    let mut data = Vec::new();
+    let mut decoder = GzDecoder::new(BufReader::new(File::open(path)?));
+    if let Err(error) = decoder.read_to_end(&mut data) {
+        if data.is_empty() {
+            return Err(error);
+        }
+    }
+
+    read_records(&data)
+}
+
+/// Reads the records of a decompressed replay, up to the last complete
+/// one.
+fn read_records(data: &[u8]) -> io::Result<Vec<ReplayRecord>> {
+    if data.len() < 5 || &data[..4] != MAGIC || data[4] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a replay file, or from an unsupported version",
+        ));
+    }
+
+    let mut reader = PacketReader::new(&data[5..]);
+    let mut records = Vec::new();
+    while !reader.is_empty() {
+        match read_record(&mut reader) {
+            Some(record) => records.push(record),
+            None => break,
+        }
+    }
+    Ok(records)
+}
+
+fn read_record(reader: &mut PacketReader) -> Option<ReplayRecord> {
+    let kind = reader.read_u8()?;
+    let time = reader.read_f32()?;
+    let event = match kind {
+        PACKET_RECORD => {
+            let id = reader.read_varint()?;
+            let length = reader.read_varint()?;
+            if length < 0 {
+                return None;
+            }
+            let data = reader.read_bytes(length as usize)?.to_vec();
+            ReplayEvent::Packet(RawPacket { id, data })
+        }
+        PLAYER_RECORD => ReplayEvent::Player {
+            position: Vector3::new(reader.read_f32()?, reader.read_f32()?, reader.read_f32()?),
+            pitch: reader.read_f32()?,
+            yaw: reader.read_f32()?,
+        },
+        _ => return None,
+    };
+    Some(ReplayRecord { time, event })
+}
+
+/// Steps through a recording in time, at an adjustable speed.
+pub struct ReplayPlayer {
+    records: Vec<ReplayRecord>,
+    // The index of the next record to hand out.
+    cursor: usize,
+    time: f32,
+    speed: f32,
+    paused: bool,
+}
+
+impl ReplayPlayer {
+    pub fn open(path: &Path) -> io::Result<ReplayPlayer> {
+        Ok(ReplayPlayer::new(read_replay(path)?))
+    }
+
+    fn new(records: Vec<ReplayRecord>) -> ReplayPlayer {
+        ReplayPlayer {
+            records,
+            cursor: 0,
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.records.last().map(|record| record.time).unwrap_or(0.0)
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Doubles or halves the playback speed once per step.
+    pub fn change_speed(&mut self, steps: i32) {
+        self.speed = (self.speed * 2f32.powi(steps)).clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    /// The first recorded position of the local player, if there is one.
+    pub fn first_player_position(&self) -> Option<Vector3<f32>> {
+        self.records.iter().find_map(|record| match record.event {
+            ReplayEvent::Player { position, .. } => Some(position),
+            _ => None,
+        })
+    }
+
+    /// Moves playback to `time`. Returns true if playback went backwards,
+    /// in which case the caller has to reset its state, as the events are
+    /// handed out again from the start.
+    pub fn seek(&mut self, time: f32) -> bool {
+        let time = time.max(0.0).min(self.duration());
+        let rewound = time < self.time;
+        if rewound {
+            self.cursor = 0;
+        }
+        self.time = time;
+        rewound
+    }
+
+    /// Advances playback by `delta` seconds of real time, returning the
+    /// events that happened in between.
+    pub fn advance(&mut self, delta: f32) -> Vec<ReplayEvent> {
+        if !self.paused {
+            self.time = (self.time + delta * self.speed).min(self.duration());
+        }
+
+        let mut events = Vec::new();
+        while let Some(record) = self.records.get(self.cursor) {
+            if record.time > self.time {
+                break;
+            }
+            events.push(record.event.clone());
+            self.cursor += 1;
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(id: i32, data: &[u8]) -> RawPacket {
+        RawPacket {
+            id,
+            data: data.to_vec(),
+        }
+    }
+
+    /// A record of the player at `x`, so that records can be told apart.
+    fn player_at(time: f32, x: f32) -> Vec<u8> {
+        player_record(time, &Vector3::new(x, 64.0, 0.0), 10.0, 90.0)
+    }
+
+    fn replay_data(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    /// Advances playback, naming the player records handed out by the x
+    /// they were recorded at, which the tests set to their time.
+    fn advance_times(player: &mut ReplayPlayer, delta: f32) -> Vec<f32> {
+        player
+            .advance(delta)
+            .into_iter()
+            .map(|event| match event {
+                ReplayEvent::Player { position, .. } => position[0],
+                event => panic!("expected the player, got {:?}", event),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_packet_and_player_records() {
+        let data = replay_data(&[
+            packet_record(0.0, &packet(0x24, &[1, 2, 3])),
+            player_at(0.05, 8.0),
+        ]);
+        let records = read_records(&data).unwrap();
+        assert_eq!(records.len(), 2);
+        match &records[0].event {
+            ReplayEvent::Packet(raw) => {
+                assert_eq!(raw.id, 0x24);
+                assert_eq!(raw.data, vec![1, 2, 3]);
+            }
+            event => panic!("expected a packet, got {:?}", event),
+        }
+        assert_eq!(records[1].time, 0.05);
+        match records[1].event {
+            ReplayEvent::Player {
+                position,
+                pitch,
+                yaw,
+            } => {
+                assert_eq!(position, Vector3::new(8.0, 64.0, 0.0));
+                assert_eq!((pitch, yaw), (10.0, 90.0));
+            }
+            ref event => panic!("expected the player, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn stream_cut_off_mid_record_keeps_complete_records() {
+        let data = replay_data(&[
+            packet_record(0.0, &packet(0x24, &[1, 2, 3])),
+            player_at(0.05, 8.0),
+        ]);
+        // Everything but the last value of the player record.
+        let records = read_records(&data[..data.len() - 4]).unwrap();
+        assert_eq!(records.len(), 1);
+        // Cut off inside a packet's data.
+        let data = replay_data(&[packet_record(0.0, &packet(0x24, &[1, 2, 3]))]);
+        assert!(read_records(&data[..data.len() - 1]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(read_records(b"MCRP").is_err());
+        assert!(read_records(&[b'M', b'C', b'R', b'P', VERSION + 1]).is_err());
+    }
+
+    #[test]
+    fn advance_hands_out_each_record_once() {
+        let records = (0..4)
+            .map(|i| player_at(i as f32, i as f32))
+            .collect::<Vec<_>>();
+        let mut player = ReplayPlayer::new(read_records(&replay_data(&records)).unwrap());
+        assert_eq!(advance_times(&mut player, 0.0), vec![0.0]);
+        assert_eq!(advance_times(&mut player, 1.5), vec![1.0]);
+        assert_eq!(advance_times(&mut player, 0.25), Vec::<f32>::new());
+        // Playback stops at the last record.
+        assert_eq!(advance_times(&mut player, 10.0), vec![2.0, 3.0]);
+        assert_eq!(player.time(), 3.0);
+    }
+
+    #[test]
+    fn seeking_backwards_rewinds_the_cursor() {
+        let records = (0..4)
+            .map(|i| player_at(i as f32, i as f32))
+            .collect::<Vec<_>>();
+        let mut player = ReplayPlayer::new(read_records(&replay_data(&records)).unwrap());
+        assert_eq!(advance_times(&mut player, 2.5), vec![0.0, 1.0, 2.0]);
+
+        assert!(player.seek(1.0));
+        assert_eq!(advance_times(&mut player, 0.0), vec![0.0, 1.0]);
+
+        // Seeking forwards hands out the records skipped over.
+        assert!(!player.seek(3.0));
+        assert_eq!(advance_times(&mut player, 0.0), vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn paused_playback_stays_put() {
+        let records = (0..2)
+            .map(|i| player_at(i as f32, i as f32))
+            .collect::<Vec<_>>();
+        let mut player = ReplayPlayer::new(read_records(&replay_data(&records)).unwrap());
+        player.toggle_pause();
+        assert_eq!(advance_times(&mut player, 5.0), vec![0.0]);
+        assert_eq!(player.time(), 0.0);
+    }
+}
@@ -0,0 +1,568 @@
+use crate::block::Block;
+use crate::chunk_manager::ChunkSource;
+use crate::legacy_block::legacy_block;
+use crate::nbt::{read_nbt_file, write_nbt_file, Tag};
+use crate::packet::{PacketReader, PacketWriter};
+use crate::redstone::RedstoneSimulation;
+use crate::world::{BlockPos, Chunk, ChunkPos, World, SECTION_SIZE};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The data version of 1.16.5, which the block states we have come from.
+const DATA_VERSION: i32 = 2586;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A box of blocks loaded from a WorldEdit schematic. Blocks are indexed
+/// the way both schematic formats store them: x first, then z, then y.
+pub struct Schematic {
+    pub width: i32,
+    pub height: i32,
+    pub length: i32,
+    blocks: Vec<Block>,
+    // Block entity data by position within the schematic, with its id in
+    // `id` like in the world but without a position of its own.
+    block_entities: BTreeMap<BlockPos, HashMap<String, Tag>>,
+}
+
+impl Schematic {
+    /// Loads a Sponge schematic (versions 1 to 3, usually `.schem`) or an
+    /// MCEdit schematic (usually `.schematic`).
+    pub fn load(path: &Path) -> io::Result<Schematic> {
+        let (_, root) = read_nbt_file(path)?;
+        Schematic::from_nbt(&root)
+    }
+
+    fn from_nbt(root: &Tag) -> io::Result<Schematic> {
+        // Version 3 nests everything in a compound inside the root.
+        let root = match root.get("Schematic") {
+            Some(schematic @ Tag::Compound(_)) => schematic,
+            _ => root,
+        };
+
+        if root.get("Version").is_some() {
+            Schematic::from_sponge(root)
+        } else if root.get("Materials").is_some() {
+            Schematic::from_mcedit(root)
+        } else {
+            Err(invalid_data("not a Sponge or MCEdit schematic"))
+        }
+    }
+
+    fn new(width: i32, height: i32, length: i32) -> io::Result<Schematic> {
+        Ok(Schematic {
+            width,
+            height,
+            length,
+            blocks: vec![Block::Air; volume(width, height, length)?],
+            block_entities: BTreeMap::new(),
+        })
+    }
+
+    /// Reads the width, height and length, which are stored as unsigned
+    /// shorts.
+    fn read_size(root: &Tag) -> io::Result<(i32, i32, i32)> {
+        let dimension = |name| {
+            root.get(name)
+                .and_then(Tag::as_i32)
+                .map(|value| value as u16 as i32)
+                .ok_or_else(|| invalid_data("schematic is missing its size"))
+        };
+        Ok((
+            dimension("Width")?,
+            dimension("Height")?,
+            dimension("Length")?,
+        ))
+    }
+
+    fn from_sponge(root: &Tag) -> io::Result<Schematic> {
+        let version = root.get("Version").and_then(Tag::as_i32).unwrap_or(0);
+        let (width, height, length) = Schematic::read_size(root)?;
+        let (palette, data, block_entities) = match version {
+            1 => (
+                root.get("Palette"),
+                root.get("BlockData"),
+                root.get("TileEntities"),
+            ),
+            2 => (
+                root.get("Palette"),
+                root.get("BlockData"),
+                root.get("BlockEntities"),
+            ),
+            3 => {
+                let blocks = root.get("Blocks");
+                (
+                    blocks.and_then(|blocks| blocks.get("Palette")),
+                    blocks.and_then(|blocks| blocks.get("Data")),
+                    blocks.and_then(|blocks| blocks.get("BlockEntities")),
+                )
+            }
+            _ => return Err(invalid_data("unsupported Sponge schematic version")),
+        };
+        let palette = palette
+            .and_then(Tag::as_compound)
+            .ok_or_else(|| invalid_data("schematic is missing its palette"))?;
+        let data = data
+            .and_then(Tag::as_bytes)
+            .ok_or_else(|| invalid_data("schematic is missing its block data"))?;
+
+        // The palette maps block states to ids, we want it the other way
+        // around. Ids number the entries from zero, so one past the end is
+        // as bad as a negative one and would only make room for nothing.
+        let mut blocks_by_id = vec![Block::Air; palette.len()];
+        for (state, id) in palette {
+            let id = id
+                .as_i32()
+                .filter(|id| *id >= 0 && (*id as usize) < palette.len())
+                .ok_or_else(|| invalid_data("invalid palette id"))? as usize;
+            blocks_by_id[id] = Block::from_state(state);
+        }
+
+        // Every block takes at least a byte, so data too short to hold
+        // them all is caught before making room for them.
+        if data.len() < volume(width, height, length)? {
+            return Err(invalid_data("schematic block data is too short"));
+        }
+        let mut schematic = Schematic::new(width, height, length)?;
+        let mut reader = PacketReader::new(data);
+        for block in schematic.blocks.iter_mut() {
+            let id = reader
+                .read_varint()
+                .ok_or_else(|| invalid_data("schematic block data is too short"))?;
+            *block = blocks_by_id
+                .get(id as usize)
+                .copied()
+                .ok_or_else(|| invalid_data("block id missing from the palette"))?;
+        }
+
+        let block_entities = block_entities.and_then(Tag::as_list).unwrap_or(&[]);
+        for entity in block_entities {
+            if let Some((pos, data)) = read_sponge_block_entity(entity, version) {
+                schematic.block_entities.insert(pos, data);
+            }
+        }
+        Ok(schematic)
+    }
+
+    fn from_mcedit(root: &Tag) -> io::Result<Schematic> {
+        let (width, height, length) = Schematic::read_size(root)?;
+        let ids = root
+            .get("Blocks")
+            .and_then(Tag::as_bytes)
+            .ok_or_else(|| invalid_data("schematic is missing its blocks"))?;
+        let data = root
+            .get("Data")
+            .and_then(Tag::as_bytes)
+            .ok_or_else(|| invalid_data("schematic is missing its block data"))?;
+        // The high four bits of ids above 255, two blocks to a byte.
+        let add_blocks = root.get("AddBlocks").and_then(Tag::as_bytes);
+
+        let volume = volume(width, height, length)?;
+        if ids.len() < volume || data.len() < volume {
+            return Err(invalid_data("schematic block data is too short"));
+        }
+        let mut schematic = Schematic::new(width, height, length)?;
+        for (i, block) in schematic.blocks.iter_mut().enumerate() {
+            let mut id = ids[i] as u16;
+            if let Some(add) = add_blocks.and_then(|add| add.get(i >> 1)) {
+                // Even blocks are in the low four bits, odd ones the high.
+                let high = if i & 1 == 0 { add & 0xf } else { add >> 4 };
+                id |= (high as u16) << 8;
+            }
+            *block = legacy_block(id, data[i] & 0xf);
+        }
+        Ok(schematic)
+    }
+
+    /// Copies the blocks between two corners of the world, inclusive,
+    /// along with their block entities.
+    pub fn from_world(world: &World, min: BlockPos, max: BlockPos) -> io::Result<Schematic> {
+        let mut schematic =
+            Schematic::new(max.x - min.x + 1, max.y - min.y + 1, max.z - min.z + 1)?;
+        let mut i = 0;
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    let pos = BlockPos::new(x, y, z);
+                    schematic.blocks[i] = world.get_block(pos);
+                    if let Some(data) = world.get_block_entity(pos).and_then(Tag::as_compound) {
+                        let mut data = data.clone();
+                        for name in ["x", "y", "z"].iter() {
+                            data.remove(*name);
+                        }
+                        schematic
+                            .block_entities
+                            .insert(pos.offset(-min.x, -min.y, -min.z), data);
+                    }
+                    i += 1;
+                }
+            }
+        }
+        Ok(schematic)
+    }
+
+    /// Saves the schematic in the Sponge format, version 2, which
+    /// WorldEdit reads as `.schem`. `offset` is recorded as where the
+    /// blocks came from. Sides longer than 65535 blocks can't be saved.
+    pub fn save(&self, path: &Path, offset: BlockPos) -> io::Result<()> {
+        write_nbt_file(path, "Schematic", &self.to_nbt(offset)?)
+    }
+
+    fn to_nbt(&self, offset: BlockPos) -> io::Result<Tag> {
+        let dimension = |value: i32| {
+            u16::try_from(value)
+                .map(|value| Tag::Short(value as i16))
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "schematics can be at most 65535 blocks across",
+                    )
+                })
+        };
+        let (width, height, length) = (
+            dimension(self.width)?,
+            dimension(self.height)?,
+            dimension(self.length)?,
+        );
+
+        // WorldEdit pastes without block updates, so wire has to be saved
+        // with the sides it joins up with in the schematic.
+        let wires = self.wire_states();
+
+        let mut palette: HashMap<String, i32> = HashMap::new();
+        let mut ids: HashMap<Block, i32> = HashMap::new();
+        let mut palette_id = |state: String| {
+            let next_id = palette.len() as i32;
+            *palette.entry(state).or_insert(next_id)
+        };
+        let mut data = PacketWriter::new();
+        for (i, block) in self.blocks.iter().enumerate() {
+            let id = match wires.get(&i) {
+                Some(state) => palette_id(state.clone()),
+                None => *ids
+                    .entry(*block)
+                    .or_insert_with(|| palette_id(block.state())),
+            };
+            data.write_varint(id);
+        }
+        let palette: HashMap<String, Tag> = palette
+            .into_iter()
+            .map(|(state, id)| (state, Tag::Int(id)))
+            .collect();
+
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as i64)
+            .unwrap_or(0);
+        let mut metadata = HashMap::new();
+        metadata.insert("Date".to_owned(), Tag::Long(date));
+        // Pasting puts the lowest corner where the player stands.
+        for name in ["WEOffsetX", "WEOffsetY", "WEOffsetZ"].iter() {
+            metadata.insert((*name).to_owned(), Tag::Int(0));
+        }
+
+        let mut root = HashMap::new();
+        root.insert("Version".to_owned(), Tag::Int(2));
+        root.insert("DataVersion".to_owned(), Tag::Int(DATA_VERSION));
+        root.insert("Width".to_owned(), width);
+        root.insert("Height".to_owned(), height);
+        root.insert("Length".to_owned(), length);
+        root.insert(
+            "Offset".to_owned(),
+            Tag::IntArray(vec![offset.x, offset.y, offset.z]),
+        );
+        root.insert("PaletteMax".to_owned(), Tag::Int(palette.len() as i32));
+        root.insert("Palette".to_owned(), Tag::Compound(palette));
+        root.insert("BlockData".to_owned(), Tag::ByteArray(data.finish()));
+        root.insert(
+            "BlockEntities".to_owned(),
+            Tag::List(self.sponge_block_entities()),
+        );
+        root.insert("Metadata".to_owned(), Tag::Compound(metadata));
+        Ok(Tag::Compound(root))
+    }
+
+    /// The block entities in the layout of Sponge version 2, with their
+    /// position in `Pos`, their id in `Id` and their data alongside.
+    fn sponge_block_entities(&self) -> Vec<Tag> {
+        self.block_entities
+            .iter()
+            .map(|(pos, data)| {
+                let mut entity = data.clone();
+                let id = entity
+                    .remove("id")
+                    .unwrap_or_else(|| Tag::String(String::new()));
+                entity.insert("Id".to_owned(), id);
+                entity.insert("Pos".to_owned(), Tag::IntArray(vec![pos.x, pos.y, pos.z]));
+                Tag::Compound(entity)
+            })
+            .collect()
+    }
+
+    /// The full state of each piece of wire, by index, with its sides
+    /// worked out from the blocks around it in the schematic.
+    fn wire_states(&self) -> HashMap<usize, String> {
+        let mut states = HashMap::new();
+        let has_wire = self
+            .blocks
+            .iter()
+            .any(|block| matches!(block, Block::RedstoneWire { .. }));
+        if !has_wire {
+            return states;
+        }
+
+        let mut world = World::new();
+        let mut i = 0;
+        for y in 0..self.height {
+            for z in 0..self.length {
+                for x in 0..self.width {
+                    world.set_block(BlockPos::new(x, y, z), self.blocks[i]);
+                    i += 1;
+                }
+            }
+        }
+        // An empty simulation reads the circuit straight from the world.
+        let simulation = RedstoneSimulation::new(1.0);
+        let mut i = 0;
+        for y in 0..self.height {
+            for z in 0..self.length {
+                for x in 0..self.width {
+                    if let Block::RedstoneWire { .. } = self.blocks[i] {
+                        states.insert(i, simulation.wire_state(&world, BlockPos::new(x, y, z)));
+                    }
+                    i += 1;
+                }
+            }
+        }
+        states
+    }
+
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Block {
+        if x < 0 || y < 0 || z < 0 || x >= self.width || y >= self.height || z >= self.length {
+            return Block::Air;
+        }
+        self.blocks[((y * self.length + z) * self.width + x) as usize]
+    }
+}
+
+/// Reads a Sponge block entity into its position and its data in the
+/// world's layout. Version 3 nests the data in `Data`, where earlier
+/// versions keep it alongside the position and id.
+fn read_sponge_block_entity(
+    entity: &Tag,
+    version: i32,
+) -> Option<(BlockPos, HashMap<String, Tag>)> {
+    let pos = match entity.get("Pos")? {
+        Tag::IntArray(pos) if pos.len() == 3 => BlockPos::new(pos[0], pos[1], pos[2]),
+        _ => return None,
+    };
+    let id = entity.get("Id")?.clone();
+    let mut data = if version >= 3 {
+        entity
+            .get("Data")
+            .and_then(Tag::as_compound)
+            .cloned()
+            .unwrap_or_default()
+    } else {
+        let mut data = entity.as_compound()?.clone();
+        data.remove("Pos");
+        data.remove("Id");
+        data
+    };
+    data.insert("id".to_owned(), id);
+    Some((pos, data))
+}
+
+/// The number of blocks in a box of the given size, or an error if it
+/// is too many to hold.
+fn volume(width: i32, height: i32, length: i32) -> io::Result<usize> {
+    let dimension = |value: i32| {
+        usize::try_from(value).map_err(|_| invalid_data("schematic has a negative size"))
+    };
+    let (width, height, length) = (dimension(width)?, dimension(height)?, dimension(length)?);
+    width
+        .checked_mul(height)
+        .and_then(|area| area.checked_mul(length))
+        .ok_or_else(|| invalid_data("schematic is too large"))
+}
+
+/// Serves a schematic as chunks, with its lowest corner at `origin`, so
+/// that it can be looked around without a server.
+pub struct SchematicSource {
+    schematic: Schematic,
+    origin: BlockPos,
+}
+
+impl SchematicSource {
+    pub fn new(schematic: Schematic, origin: BlockPos) -> SchematicSource {
+        SchematicSource { schematic, origin }
+    }
+
+    /// Every chunk the schematic covers.
+    pub fn chunk_positions(&self) -> Vec<ChunkPos> {
+        let min = self.origin.chunk_pos();
+        let max = self
+            .origin
+            .offset(self.schematic.width - 1, 0, self.schematic.length - 1)
+            .chunk_pos();
+        let mut positions = Vec::new();
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                positions.push(ChunkPos::new(x, z));
+            }
+        }
+        positions
+    }
+}
+
+impl ChunkSource for SchematicSource {
+    fn load_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        let chunk_x = pos.x * SECTION_SIZE - self.origin.x;
+        let chunk_z = pos.z * SECTION_SIZE - self.origin.z;
+        let schematic = &self.schematic;
+        if chunk_x + SECTION_SIZE <= 0
+            || chunk_z + SECTION_SIZE <= 0
+            || chunk_x >= schematic.width
+            || chunk_z >= schematic.length
+        {
+            return None;
+        }
+
+        let mut chunk = Chunk::new();
+        for y in 0..schematic.height {
+            for z in 0..SECTION_SIZE {
+                for x in 0..SECTION_SIZE {
+                    let block = schematic.get_block(chunk_x + x, y, chunk_z + z);
+                    if !block.is_air() {
+                        chunk.set_block(x, self.origin.y + y, z, block);
+                    }
+                }
+            }
+        }
+        for (pos, data) in schematic.block_entities.iter() {
+            let (x, z) = (pos.x - chunk_x, pos.z - chunk_z);
+            if x < 0 || z < 0 || x >= SECTION_SIZE || z >= SECTION_SIZE {
+                continue;
+            }
+            let mut data = data.clone();
+            let world_pos = self.origin.offset(pos.x, pos.y, pos.z);
+            data.insert("x".to_owned(), Tag::Int(world_pos.x));
+            data.insert("y".to_owned(), Tag::Int(world_pos.y));
+            data.insert("z".to_owned(), Tag::Int(world_pos.z));
+            chunk.insert_block_entity(Tag::Compound(data));
+        }
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mcedit_add_blocks_put_even_blocks_in_the_low_bits() {
+        let mut root = HashMap::new();
+        root.insert("Width".to_owned(), Tag::Short(2));
+        root.insert("Height".to_owned(), Tag::Short(1));
+        root.insert("Length".to_owned(), Tag::Short(1));
+        root.insert("Materials".to_owned(), Tag::String("Alpha".to_owned()));
+        root.insert("Blocks".to_owned(), Tag::ByteArray(vec![0x10, 0x20]));
+        root.insert("Data".to_owned(), Tag::ByteArray(vec![0, 0]));
+        root.insert("AddBlocks".to_owned(), Tag::ByteArray(vec![0x21]));
+
+        let schematic = Schematic::from_mcedit(&Tag::Compound(root)).unwrap();
+        assert_eq!(schematic.get_block(0, 0, 0), legacy_block(0x110, 0));
+        assert_eq!(schematic.get_block(1, 0, 0), legacy_block(0x220, 0));
+    }
+
+    fn sponge_root(palette: &[(&str, i32)], data: Vec<u8>) -> Tag {
+        let mut root = HashMap::new();
+        root.insert("Version".to_owned(), Tag::Int(2));
+        root.insert("Width".to_owned(), Tag::Short(data.len() as i16));
+        root.insert("Height".to_owned(), Tag::Short(1));
+        root.insert("Length".to_owned(), Tag::Short(1));
+        let palette = palette
+            .iter()
+            .map(|(state, id)| ((*state).to_owned(), Tag::Int(*id)))
+            .collect();
+        root.insert("Palette".to_owned(), Tag::Compound(palette));
+        root.insert("BlockData".to_owned(), Tag::ByteArray(data));
+        Tag::Compound(root)
+    }
+
+    #[test]
+    fn sponge_palette_ids_map_to_their_states() {
+        let root = sponge_root(&[("minecraft:stone", 1), ("minecraft:air", 0)], vec![1, 0]);
+        let schematic = Schematic::from_sponge(&root).unwrap();
+        assert_eq!(schematic.get_block(0, 0, 0), Block::Stone);
+        assert_eq!(schematic.get_block(1, 0, 0), Block::Air);
+    }
+
+    #[test]
+    fn sponge_palette_ids_past_the_palette_are_rejected() {
+        let root = sponge_root(&[("minecraft:stone", i32::MAX)], vec![0]);
+        assert!(Schematic::from_sponge(&root).is_err());
+        let root = sponge_root(&[("minecraft:stone", 0), ("minecraft:air", 2)], vec![0]);
+        assert!(Schematic::from_sponge(&root).is_err());
+        let root = sponge_root(&[("minecraft:stone", -1)], vec![0]);
+        assert!(Schematic::from_sponge(&root).is_err());
+    }
+
+    #[test]
+    fn block_entities_round_trip_relative_to_the_selection() {
+        let mut world = World::new();
+        let comparator = BlockPos::new(10, 64, -3);
+        world.set_block(
+            comparator,
+            Block::from_state("minecraft:comparator[facing=north,mode=compare,powered=true]"),
+        );
+        let mut data = HashMap::new();
+        data.insert(
+            "id".to_owned(),
+            Tag::String("minecraft:comparator".to_owned()),
+        );
+        data.insert("OutputSignal".to_owned(), Tag::Int(7));
+        let mut tag = data.clone();
+        tag.insert("x".to_owned(), Tag::Int(comparator.x));
+        tag.insert("y".to_owned(), Tag::Int(comparator.y));
+        tag.insert("z".to_owned(), Tag::Int(comparator.z));
+        let mut chunk = world.remove_chunk(comparator.chunk_pos()).unwrap();
+        chunk.insert_block_entity(Tag::Compound(tag));
+        world.insert_chunk(comparator.chunk_pos(), chunk);
+
+        let min = BlockPos::new(9, 63, -5);
+        let schematic = Schematic::from_world(&world, min, BlockPos::new(11, 65, -2)).unwrap();
+        let loaded = Schematic::from_nbt(&schematic.to_nbt(min).unwrap()).unwrap();
+        let relative = BlockPos::new(1, 1, 2);
+        assert_eq!(loaded.block_entities.len(), 1);
+        assert_eq!(loaded.block_entities[&relative], data);
+        assert_eq!(
+            loaded.get_block(relative.x, relative.y, relative.z),
+            world.get_block(comparator)
+        );
+    }
+
+    #[test]
+    fn saved_wire_joins_up_with_its_neighbours() {
+        let mut world = World::new();
+        world.set_block(BlockPos::new(0, 0, 0), Block::RedstoneWire { power: 0 });
+        world.set_block(BlockPos::new(1, 0, 0), Block::RedstoneWire { power: 0 });
+        world.set_block(BlockPos::new(1, 0, 1), Block::RedstoneWire { power: 0 });
+        let schematic =
+            Schematic::from_world(&world, BlockPos::new(0, 0, 0), BlockPos::new(1, 0, 1)).unwrap();
+        let states = schematic.wire_states();
+        assert_eq!(
+            states[&0],
+            "minecraft:redstone_wire[east=side,north=none,power=0,south=none,west=side]"
+        );
+        assert_eq!(
+            states[&1],
+            "minecraft:redstone_wire[east=none,north=none,power=0,south=side,west=side]"
+        );
+    }
+}
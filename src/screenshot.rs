@@ -0,0 +1,120 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An 8 bit RGBA image, stored row by row from the top.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+// The most pixels an image can be captured with, about a gigabyte.
+const MAX_PIXELS: usize = 1 << 28;
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Image {
+        Image::try_new(width, height).expect("Image too large")
+    }
+
+    /// Creates a blank image, or returns None if it would be too large to
+    /// hold.
+    pub fn try_new(width: u32, height: u32) -> Option<Image> {
+        let pixels = (width as usize)
+            .checked_mul(height as usize)
+            .filter(|pixels| *pixels <= MAX_PIXELS)?;
+        Some(Image {
+            width,
+            height,
+            pixels: vec![0; pixels * 4],
+        })
+    }
+
+    /// Copies `source` into this image with its top left corner at (x, y).
+    /// Whatever falls outside of this image is cut off.
+    pub fn blit(&mut self, source: &Image, x: u32, y: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let row_length = (source.width.min(self.width - x) * 4) as usize;
+        for row in 0..source.height.min(self.height - y) {
+            let from = (row * source.width * 4) as usize;
+            let to = (((y + row) * self.width + x) * 4) as usize;
+            self.pixels[to..to + row_length]
+                .copy_from_slice(&source.pixels[from..from + row_length]);
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))
+    }
+}
+
+/// Reads the bottom left `width` by `height` pixels of the framebuffer
+/// being drawn to.
+pub fn read_pixels(width: u32, height: u32) -> Image {
+    let mut image = Image::new(width, height);
+    read_pixels_into(&mut image);
+    image
+}
+
+/// Fills `image` with the bottom left pixels of the framebuffer being
+/// drawn to.
+pub fn read_pixels_into(image: &mut Image) {
+    let (width, height) = (image.width, image.height);
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            0,
+            0,
+            width as i32,
+            height as i32,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            image.pixels.as_mut_ptr() as *mut c_void,
+        );
+    }
+
+    // OpenGL reads rows from the bottom up.
+    let row_length = (width * 4) as usize;
+    for row in 0..(height / 2) as usize {
+        let bottom = (height as usize - 1 - row) * row_length;
+        let (upper, lower) = image.pixels.split_at_mut(bottom);
+        upper[row * row_length..(row + 1) * row_length].swap_with_slice(&mut lower[..row_length]);
+    }
+}
+
+/// Saves an image into `directory` under a name that doesn't clash with
+/// any earlier screenshot, and returns where it went.
+pub fn save_screenshot(directory: &Path, image: &Image) -> io::Result<PathBuf> {
+    let path = unused_path(directory, "png")?;
+    image.save_png(&path)?;
+    Ok(path)
+}
+
+/// Finds a file name in `directory`, creating it if needed, named after
+/// the current time and not yet taken.
+pub fn unused_path(directory: &Path, extension: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    let mut path = directory.join(format!("{}.{}", timestamp, extension));
+    let mut count = 1;
+    while path.exists() {
+        count += 1;
+        path = directory.join(format!("{}_{}.{}", timestamp, count, extension));
+    }
+    Ok(path)
+}
@@ -0,0 +1,121 @@
+use crate::render_lines::LineRenderer;
+use crate::render_text::{Anchor, TextRenderer};
+use crate::world::BlockPos;
+
+const SELECTION_COLOR: [f32; 4] = [1.0, 0.5, 0.1, 1.0];
+const POS1_COLOR: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+const POS2_COLOR: [f32; 4] = [0.2, 0.4, 1.0, 1.0];
+
+/// A cuboid region picked out by its two corners, like a WorldEdit
+/// selection. Either corner may not have been set yet.
+pub struct Selection {
+    pub pos1: Option<BlockPos>,
+    pub pos2: Option<BlockPos>,
+}
+
+impl Selection {
+    pub fn new() -> Selection {
+        Selection {
+            pos1: None,
+            pos2: None,
+        }
+    }
+
+    /// The lowest and highest corners of the selection, once both corners
+    /// have been set.
+    pub fn bounds(&self) -> Option<(BlockPos, BlockPos)> {
+        let (a, b) = (self.pos1?, self.pos2?);
+        Some((
+            BlockPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            BlockPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        ))
+    }
+
+    /// The width, height and length of the selection, in blocks.
+    pub fn size(&self) -> Option<(i32, i32, i32)> {
+        let (min, max) = self.bounds()?;
+        Some((max.x - min.x + 1, max.y - min.y + 1, max.z - min.z + 1))
+    }
+
+    pub fn volume(&self) -> Option<i64> {
+        let (width, height, length) = self.size()?;
+        Some(width as i64 * height as i64 * length as i64)
+    }
+
+    /// Queues an outline of the selection, along with each corner in its
+    /// own colour.
+    pub fn queue_wireframe(&self, lines: &mut LineRenderer) {
+        // Grown a little so the outlines don't fight with the block faces,
+        // the whole selection more than its corners.
+        let outline = |lines: &mut LineRenderer, min: BlockPos, max: BlockPos, grow: f32, color| {
+            lines.queue_box(
+                [
+                    min.x as f32 - grow,
+                    min.y as f32 - grow,
+                    min.z as f32 - grow,
+                ],
+                [
+                    max.x as f32 + 1.0 + grow,
+                    max.y as f32 + 1.0 + grow,
+                    max.z as f32 + 1.0 + grow,
+                ],
+                color,
+            );
+        };
+        if let Some((min, max)) = self.bounds() {
+            outline(lines, min, max, 0.02, SELECTION_COLOR);
+        }
+        if let Some(pos) = self.pos1 {
+            outline(lines, pos, pos, 0.01, POS1_COLOR);
+        }
+        if let Some(pos) = self.pos2 {
+            outline(lines, pos, pos, 0.01, POS2_COLOR);
+        }
+    }
+
+    /// Queues the corners, size and volume of the selection in the top
+    /// right of the screen.
+    pub fn queue_info(&self, text: &mut TextRenderer, screen_width: u32) {
+        if self.pos1.is_none() && self.pos2.is_none() {
+            return;
+        }
+
+        let corner = |pos: Option<BlockPos>| match pos {
+            Some(pos) => format!("{} {} {}", pos.x, pos.y, pos.z),
+            None => "unset".to_owned(),
+        };
+        let mut lines = vec![
+            (format!("Position 1: {}", corner(self.pos1)), POS1_COLOR),
+            (format!("Position 2: {}", corner(self.pos2)), POS2_COLOR),
+        ];
+        if let (Some((width, height, length)), Some(volume)) = (self.size(), self.volume()) {
+            let white = [1.0, 1.0, 1.0, 1.0];
+            lines.push((format!("Size: {} x {} x {}", width, height, length), white));
+            lines.push((format!("Volume: {} blocks", volume), white));
+        }
+        text.queue_panel(&lines, Anchor::TopRight { screen_width });
+    }
+}
+
+/// The WorldEdit command setting one corner of the selection on the
+/// server, `//pos1` or `//pos2`.
+pub fn position_command(corner: u8, pos: BlockPos) -> String {
+    format!("//pos{} {},{},{}", corner, pos.x, pos.y, pos.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_commands_name_the_corner_and_block() {
+        assert_eq!(
+            position_command(1, BlockPos::new(-3, 64, 12)),
+            "//pos1 -3,64,12"
+        );
+        assert_eq!(
+            position_command(2, BlockPos::new(100, 0, -2048)),
+            "//pos2 100,0,-2048"
+        );
+    }
+}
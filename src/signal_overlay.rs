@@ -0,0 +1,187 @@
+use crate::block::Block;
+use crate::nbt::Tag;
+use crate::render::Renderer;
+use crate::render_text::Anchor;
+use crate::view_filter::ViewFilter;
+use crate::world::{BlockPos, World};
+use nalgebra::Vector3;
+
+// How far the crosshair ray reaches, and how far around the block it hits
+// neighbouring components are labelled too.
+const REACH: f32 = 32.0;
+const LABEL_RADIUS: i32 = 3;
+
+/// Blocks that take part in a redstone circuit, and so get labelled when
+/// near the block being looked at.
+fn is_component(name: &str) -> bool {
+    match name.trim_start_matches("minecraft:") {
+        "redstone_wire"
+        | "redstone_torch"
+        | "redstone_wall_torch"
+        | "redstone_lamp"
+        | "redstone_block"
+        | "repeater"
+        | "comparator"
+        | "lever"
+        | "observer"
+        | "piston"
+        | "sticky_piston"
+        | "target"
+        | "daylight_detector"
+        | "note_block" => true,
+        name => name.ends_with("_button") || name.ends_with("_pressure_plate"),
+    }
+}
+
+/// The lines of text shown over a block: its name, then its properties,
+/// with those that matter to signal strength first. A comparator's output
+/// strength is kept in its block entity, if we have one.
+fn label_lines(block: Block, block_entity: Option<&Tag>) -> Vec<String> {
+    let (name, properties) = block.name_and_properties();
+    let name = name.trim_start_matches("minecraft:");
+    let mut lines = vec![name.to_owned()];
+    match block {
+        Block::RedstoneBlock => lines.push("power 15".to_owned()),
+        Block::RedstoneTorch { lit } => lines.push(format!("power {}", if lit { 15 } else { 0 })),
+        _ => {}
+    }
+
+    let property = |key: &str| {
+        properties
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+    if let Some(power) = property("power") {
+        lines.push(format!("power {}", power));
+    }
+    if let Some(delay) = property("delay") {
+        lines.push(format!("delay {} ticks", delay));
+    }
+    if name == "comparator" {
+        let output = block_entity
+            .and_then(|data| data.get("OutputSignal"))
+            .and_then(Tag::as_i32);
+        let output = match output {
+            Some(output) => output.to_string(),
+            // Servers don't send comparator block entities, so only whether
+            // it is on can be told from the state.
+            None if property("powered") == Some("true") => "on".to_owned(),
+            None => "off".to_owned(),
+        };
+        lines.push(format!(
+            "{}, output {}",
+            property("mode").unwrap_or("compare"),
+            output
+        ));
+    }
+    for (key, value) in properties.iter() {
+        let shown = match key.as_str() {
+            "power" | "delay" => true,
+            "mode" | "powered" => name == "comparator",
+            _ => false,
+        };
+        if !shown {
+            lines.push(format!("{}: {}", key, value));
+        }
+    }
+    lines
+}
+
+/// Queues floating labels for the block under the crosshair and any
+/// redstone components around it. Blocks hidden by the filter are looked
+/// past and left unlabelled.
+pub fn queue_signal_overlay(
+    renderer: &mut Renderer,
+    world: &World,
+    filter: &ViewFilter,
+    eye: &Vector3<f32>,
+    direction: &Vector3<f32>,
+) {
+    let visible = |pos: BlockPos, block: Block| !block.is_air() && filter.is_visible(pos, block);
+    let hit = match world.raycast(eye, direction, REACH, visible) {
+        Some(hit) => hit,
+        None => return,
+    };
+
+    // Farther labels go first, so that nearer ones are drawn over them.
+    let mut labelled = Vec::new();
+    for dx in -LABEL_RADIUS..=LABEL_RADIUS {
+        for dy in -LABEL_RADIUS..=LABEL_RADIUS {
+            for dz in -LABEL_RADIUS..=LABEL_RADIUS {
+                let pos = hit.offset(dx, dy, dz);
+                let block = world.get_block(pos);
+                if !visible(pos, block) {
+                    continue;
+                }
+                if pos != hit && !is_component(&block.name_and_properties().0) {
+                    continue;
+                }
+                let center = block_center(pos);
+                labelled.push((pos, block, (center - eye).norm()));
+            }
+        }
+    }
+    labelled.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+
+    for (pos, block, _) in labelled {
+        let (x, y) = match renderer.project_to_screen(&block_center(pos)) {
+            Some(point) => point,
+            None => continue,
+        };
+        let color = if pos == hit {
+            [1.0, 1.0, 0.6, 1.0]
+        } else {
+            [1.0, 1.0, 1.0, 1.0]
+        };
+        let lines: Vec<(String, [f32; 4])> = label_lines(block, world.get_block_entity(pos))
+            .into_iter()
+            .map(|line| (line, color))
+            .collect();
+        renderer.text.queue_panel(&lines, Anchor::Center(x, y));
+    }
+}
+
+fn block_center(pos: BlockPos) -> Vector3<f32> {
+    Vector3::new(pos.x as f32 + 0.5, pos.y as f32 + 0.5, pos.z as f32 + 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn comparator(mode: &str, powered: bool) -> Block {
+        Block::from_state(&format!(
+            "minecraft:comparator[facing=north,mode={},powered={}]",
+            mode, powered
+        ))
+    }
+
+    fn output_signal(output: i32) -> Tag {
+        let mut data = HashMap::new();
+        data.insert(
+            "id".to_owned(),
+            Tag::String("minecraft:comparator".to_owned()),
+        );
+        data.insert("OutputSignal".to_owned(), Tag::Int(output));
+        Tag::Compound(data)
+    }
+
+    #[test]
+    fn comparators_show_the_output_of_their_block_entity() {
+        let lines = label_lines(comparator("subtract", true), Some(&output_signal(7)));
+        assert_eq!(lines[0], "comparator");
+        assert!(lines.contains(&"subtract, output 7".to_owned()));
+        assert!(lines.contains(&"facing: north".to_owned()));
+        assert!(!lines.iter().any(|line| line.starts_with("powered")));
+    }
+
+    #[test]
+    fn comparators_without_a_block_entity_show_whether_they_are_on() {
+        let on = label_lines(comparator("compare", true), None);
+        assert!(on.contains(&"compare, output on".to_owned()));
+        let off = label_lines(comparator("compare", false), None);
+        assert!(off.contains(&"compare, output off".to_owned()));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::block::Block;
+use crate::render_text::{Anchor, TextRenderer};
+use crate::world::{BlockPos, SectionSnapshot};
+use std::collections::HashMap;
+
+// How far inside the slice the clipping planes sit. Faces lying on the
+// planes are clipped from the world, and drawn by the slice layer instead.
+const CLIP_INSET: f32 = 0.001;
+
+/// Which side of the slice level is cut away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliceMode {
+    Off,
+    /// Everything above the level is hidden, leaving a floor plan of it.
+    Below,
+    /// Only the level itself is shown.
+    Layer,
+    /// Everything below the level is hidden.
+    Above,
+}
+
+impl SliceMode {
+    fn next(self) -> SliceMode {
+        match self {
+            SliceMode::Off => SliceMode::Below,
+            SliceMode::Below => SliceMode::Layer,
+            SliceMode::Layer => SliceMode::Above,
+            SliceMode::Above => SliceMode::Off,
+        }
+    }
+}
+
+/// Whether a block name matches a pattern from the x-ray list. Patterns
+/// may start or end with `*` to match any prefix or suffix, e.g.
+/// `*_concrete`.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    if let Some(suffix) = pattern.strip_prefix('*') {
+        name.ends_with(suffix)
+    } else if let Some(prefix) = pattern.strip_suffix('*') {
+        name.starts_with(prefix)
+    } else {
+        name == pattern
+    }
+}
+
+/// Decides which blocks are drawn, so that the inside of dense builds can
+/// be looked at without changing the world itself. X-ray filtering happens
+/// on section snapshots before they are meshed, while slicing clips the
+/// world in the shader, so that the slice can move without meshing the
+/// world again.
+pub struct ViewFilter {
+    pub slice_mode: SliceMode,
+    pub slice_y: i32,
+    /// Whether the blocks in the x-ray list are hidden.
+    pub xray: bool,
+    // Block names without the namespace, or patterns matching them.
+    xray_blocks: Vec<String>,
+}
+
+impl ViewFilter {
+    /// Creates a filter that shows everything, with `xray_blocks` ready
+    /// to be hidden once x-ray is turned on.
+    pub fn new(xray_blocks: Vec<String>) -> ViewFilter {
+        ViewFilter {
+            slice_mode: SliceMode::Off,
+            slice_y: 0,
+            xray: false,
+            xray_blocks,
+        }
+    }
+
+    /// Adds a block's type to the x-ray list and turns x-ray on. Returns
+    /// the name added, or None if the type was hidden already.
+    pub fn hide_type(&mut self, block: Block) -> Option<String> {
+        let name = block_name(block);
+        self.xray = true;
+        if self.is_hidden_type(block) {
+            return None;
+        }
+        self.xray_blocks.push(name.clone());
+        Some(name)
+    }
+
+    /// Takes the most recently added entry off the x-ray list.
+    pub fn unhide_last_type(&mut self) -> Option<String> {
+        self.xray_blocks.pop()
+    }
+
+    /// Whether x-ray is on and hides the block's type.
+    pub fn hides_type(&self, block: Block) -> bool {
+        self.xray && self.is_hidden_type(block)
+    }
+
+    fn is_hidden_type(&self, block: Block) -> bool {
+        if block.is_air() {
+            return false;
+        }
+        let name = block_name(block);
+        self.xray_blocks
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &name))
+    }
+
+    /// Moves on to the next way of slicing, starting at `y` when slicing
+    /// is turned on.
+    pub fn cycle_slice_mode(&mut self, y: i32) {
+        if self.slice_mode == SliceMode::Off {
+            self.slice_y = y;
+        }
+        self.slice_mode = self.slice_mode.next();
+    }
+
+    fn is_in_slice(&self, pos: BlockPos) -> bool {
+        match self.slice_mode {
+            SliceMode::Off => true,
+            SliceMode::Below => pos.y <= self.slice_y,
+            SliceMode::Layer => pos.y == self.slice_y,
+            SliceMode::Above => pos.y >= self.slice_y,
+        }
+    }
+
+    pub fn is_visible(&self, pos: BlockPos, block: Block) -> bool {
+        self.is_in_slice(pos) && !self.hides_type(block)
+    }
+
+    /// The level of the layer the slice cuts through, which is drawn on
+    /// its own to close off the cut. None while not slicing.
+    pub fn slice_layer(&self) -> Option<i32> {
+        match self.slice_mode {
+            SliceMode::Off => None,
+            _ => Some(self.slice_y),
+        }
+    }
+
+    /// The heights the world is clipped to. The slice layer itself lies
+    /// just outside of them when it is at the cut.
+    pub fn clip_range(&self) -> (f32, f32) {
+        let (bottom, top) = (self.slice_y as f32, self.slice_y as f32 + 1.0);
+        match self.slice_mode {
+            SliceMode::Off => (f32::MIN, f32::MAX),
+            SliceMode::Below => (f32::MIN, top - CLIP_INSET),
+            SliceMode::Layer => (bottom + CLIP_INSET, top - CLIP_INSET),
+            SliceMode::Above => (bottom + CLIP_INSET, f32::MAX),
+        }
+    }
+
+    /// Hides the block types x-ray doesn't let through from a snapshot
+    /// about to be meshed.
+    pub fn apply(&self, snapshot: &mut SectionSnapshot) {
+        if self.xray {
+            self.hide_blocks(snapshot, |_| false);
+        }
+    }
+
+    /// Hides everything but the slice layer from a snapshot, along with
+    /// whatever x-ray hides, leaving the layer to be meshed on its own.
+    pub fn apply_layer(&self, snapshot: &mut SectionSnapshot) {
+        let layer = self.slice_y;
+        self.hide_blocks(snapshot, |pos| pos.y != layer);
+    }
+
+    fn hide_blocks<F: Fn(BlockPos) -> bool>(&self, snapshot: &mut SectionSnapshot, hidden: F) {
+        // Matching names is slow next to everything else here, and a
+        // section only has a handful of different blocks.
+        let mut hidden_types = HashMap::new();
+        snapshot.hide_blocks(|pos, block| {
+            hidden(pos)
+                || self.xray
+                    && *hidden_types
+                        .entry(block)
+                        .or_insert_with(|| self.is_hidden_type(block))
+        });
+    }
+
+    /// Queues a note of what is being hidden at the bottom middle of the
+    /// screen.
+    pub fn queue_info(&self, text: &mut TextRenderer, screen_width: u32, screen_height: u32) {
+        let mut parts = Vec::new();
+        match self.slice_mode {
+            SliceMode::Off => {}
+            SliceMode::Below => parts.push(format!("Slice: y <= {}", self.slice_y)),
+            SliceMode::Layer => parts.push(format!("Slice: y = {}", self.slice_y)),
+            SliceMode::Above => parts.push(format!("Slice: y >= {}", self.slice_y)),
+        }
+        if self.xray {
+            parts.push(format!("X-ray: {} types hidden", self.xray_blocks.len()));
+        }
+        if parts.is_empty() {
+            return;
+        }
+        let line = parts.join("  ");
+        text.queue_panel(
+            &[(line, [1.0, 1.0, 0.6, 1.0])],
+            Anchor::Bottom {
+                screen_width,
+                screen_height,
+            },
+        );
+    }
+}
+
+/// A block's name without the `minecraft:` namespace.
+fn block_name(block: Block) -> String {
+    let (name, _) = block.name_and_properties();
+    name.trim_start_matches("minecraft:").to_owned()
+}
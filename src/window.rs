@@ -1,5 +1,5 @@
 use config::Config;
-use glfw::{Action, Context, Glfw, Key, SwapInterval, WindowHint, CursorMode};
+use glfw::{Action, Context, Glfw, Key, MouseButton, SwapInterval, WindowHint, CursorMode};
 use std::convert::TryFrom;
 use std::sync::mpsc::Receiver;
 
@@ -20,6 +20,57 @@ pub struct ControlState {
     pub right: bool,
     pub up: bool,
     pub down: bool,
+    pub sprint: bool,
+    /// Scroll wheel notches since the client last took them.
+    pub scroll: f32,
+    pub memory_report: bool,
+    pub screenshot: bool,
+    pub high_res_screenshot: bool,
+    pub show_player_list: bool,
+    /// Whether redstone components near the crosshair are labelled with
+    /// their signal strength and state.
+    pub signal_overlay: bool,
+    /// Set when the block under the crosshair should be used, like
+    /// flicking a lever.
+    pub use_block: bool,
+    /// Set when the redstone network under the crosshair should be traced.
+    pub trace_network: bool,
+    /// Whether clicks set the corners of the selection, like holding a
+    /// WorldEdit wand, rather than using blocks.
+    pub selection_wand: bool,
+    pub select_pos1: bool,
+    pub select_pos2: bool,
+    pub export_selection: bool,
+    pub toggle_ghost: bool,
+    pub cycle_slice: bool,
+    pub toggle_xray: bool,
+    /// Set when the type of block under the crosshair should be hidden by
+    /// x-ray.
+    pub xray_hide_type: bool,
+    pub xray_unhide_type: bool,
+    /// Layers to move the slice by, negative to go down.
+    pub slice_offset: i32,
+    /// Set when the ghost should be moved to the block under the
+    /// crosshair.
+    pub move_ghost: bool,
+    pub toggle_probe: bool,
+    pub export_probes: bool,
+    pub cycle_perspective: bool,
+    pub toggle_spectator: bool,
+    pub add_keyframe: bool,
+    pub remove_keyframe: bool,
+    pub save_camera_path: bool,
+    pub toggle_camera_path: bool,
+    /// Set when the camera path should be played and recorded as frames.
+    pub record_camera_path: bool,
+    pub toggle_frame_recording: bool,
+    /// Degrees to widen the field of view by, negative to zoom in.
+    pub fov_offset: f32,
+    pub replay_pause: bool,
+    /// Seconds to skip through a replay, negative to go back.
+    pub replay_seek: f32,
+    /// How many times to double the replay speed, negative to halve it.
+    pub replay_speed: i32,
     pub pitch: f32,
     pub yaw: f32,
     pub last_cursor_x: f64,
@@ -49,6 +100,8 @@ impl Window {
         glfw.window_hint(WindowHint::ContextVersion(3, 2));
         glfw.window_hint(WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
         glfw.window_hint(WindowHint::OpenGlForwardCompat(true));
+        glfw.window_hint(WindowHint::DepthBits(Some(24)));
+        glfw.window_hint(WindowHint::SRgbCapable(true));
 
         let (mut window, events) = glfw
             .create_window(x, y, &title, glfw::WindowMode::Windowed)
@@ -65,14 +118,20 @@ impl Window {
 
         window.set_framebuffer_size_polling(true);
         window.set_key_polling(true);
+        window.set_scroll_polling(true);
+        window.set_mouse_button_polling(true);
         window.make_current();
         window.set_cursor_mode(CursorMode::Disabled);
 
         let control_state = ControlState::new();
 
+        // On HiDPI displays the framebuffer is bigger than the window size
+        // asked for.
+        let (width, height) = window.get_framebuffer_size();
+
         return Window {
-            width: x,
-            height: y,
+            width: width.max(0) as u32,
+            height: height.max(0) as u32,
             title,
             glfw,
             glfw_window: window,
@@ -164,6 +223,144 @@ impl Window {
             glfw::WindowEvent::Key(Key::LeftShift, _, Action::Release, _) => {
                 self.control_state.down = false;
             }
+            glfw::WindowEvent::Key(Key::LeftControl, _, Action::Press, _) => {
+                self.control_state.sprint = true;
+            }
+            glfw::WindowEvent::Key(Key::LeftControl, _, Action::Release, _) => {
+                self.control_state.sprint = false;
+            }
+            glfw::WindowEvent::Scroll(_, y) => {
+                self.control_state.scroll += y as f32;
+            }
+            glfw::WindowEvent::MouseButton(MouseButton::Button1, Action::Press, _) => {
+                if self.control_state.selection_wand {
+                    self.control_state.select_pos1 = true;
+                }
+            }
+            glfw::WindowEvent::MouseButton(MouseButton::Button2, Action::Press, _) => {
+                if self.control_state.selection_wand {
+                    self.control_state.select_pos2 = true;
+                } else {
+                    self.control_state.use_block = true;
+                }
+            }
+            glfw::WindowEvent::MouseButton(MouseButton::Button3, Action::Press, _) => {
+                self.control_state.trace_network = true;
+            }
+            glfw::WindowEvent::Key(Key::F2, _, Action::Press, modifiers) => {
+                if modifiers.contains(glfw::Modifiers::Shift) {
+                    self.control_state.high_res_screenshot = true;
+                } else {
+                    self.control_state.screenshot = true;
+                }
+            }
+            glfw::WindowEvent::Key(Key::K, _, Action::Press, modifiers) => {
+                if modifiers.contains(glfw::Modifiers::Shift) {
+                    self.control_state.export_probes = true;
+                } else {
+                    self.control_state.toggle_probe = true;
+                }
+            }
+            glfw::WindowEvent::Key(Key::V, _, Action::Press, modifiers) => {
+                if modifiers.contains(glfw::Modifiers::Shift) {
+                    self.control_state.export_selection = true;
+                } else {
+                    self.control_state.selection_wand = !self.control_state.selection_wand;
+                }
+            }
+            glfw::WindowEvent::Key(Key::G, _, Action::Press, modifiers) => {
+                if modifiers.contains(glfw::Modifiers::Shift) {
+                    self.control_state.move_ghost = true;
+                } else {
+                    self.control_state.toggle_ghost = true;
+                }
+            }
+            glfw::WindowEvent::Key(Key::X, _, Action::Press, modifiers) => {
+                if modifiers.contains(glfw::Modifiers::Shift) {
+                    self.control_state.xray_hide_type = true;
+                } else if modifiers.contains(glfw::Modifiers::Control) {
+                    self.control_state.xray_unhide_type = true;
+                } else {
+                    self.control_state.toggle_xray = true;
+                }
+            }
+            glfw::WindowEvent::Key(Key::L, _, Action::Press, _) => {
+                self.control_state.cycle_slice = true;
+            }
+            glfw::WindowEvent::Key(Key::PageUp, _, Action::Press, _)
+            | glfw::WindowEvent::Key(Key::PageUp, _, Action::Repeat, _) => {
+                self.control_state.slice_offset += 1;
+            }
+            glfw::WindowEvent::Key(Key::PageDown, _, Action::Press, _)
+            | glfw::WindowEvent::Key(Key::PageDown, _, Action::Repeat, _) => {
+                self.control_state.slice_offset -= 1;
+            }
+            glfw::WindowEvent::Key(Key::F5, _, Action::Press, _) => {
+                self.control_state.cycle_perspective = true;
+            }
+            glfw::WindowEvent::Key(Key::N, _, Action::Press, _) => {
+                self.control_state.toggle_spectator = true;
+            }
+            glfw::WindowEvent::Key(Key::J, _, Action::Press, modifiers) => {
+                if modifiers.contains(glfw::Modifiers::Shift) {
+                    self.control_state.remove_keyframe = true;
+                } else if modifiers.contains(glfw::Modifiers::Control) {
+                    self.control_state.save_camera_path = true;
+                } else {
+                    self.control_state.add_keyframe = true;
+                }
+            }
+            glfw::WindowEvent::Key(Key::H, _, Action::Press, modifiers) => {
+                if modifiers.contains(glfw::Modifiers::Shift) {
+                    self.control_state.record_camera_path = true;
+                } else {
+                    self.control_state.toggle_camera_path = true;
+                }
+            }
+            glfw::WindowEvent::Key(Key::F6, _, Action::Press, _) => {
+                self.control_state.toggle_frame_recording = true;
+            }
+            glfw::WindowEvent::Key(Key::Minus, _, Action::Press, _)
+            | glfw::WindowEvent::Key(Key::Minus, _, Action::Repeat, _) => {
+                self.control_state.fov_offset -= 5.0;
+            }
+            glfw::WindowEvent::Key(Key::Equal, _, Action::Press, _)
+            | glfw::WindowEvent::Key(Key::Equal, _, Action::Repeat, _) => {
+                self.control_state.fov_offset += 5.0;
+            }
+            glfw::WindowEvent::Key(Key::F3, _, Action::Press, _) => {
+                self.control_state.memory_report = true;
+            }
+            glfw::WindowEvent::Key(Key::F4, _, Action::Press, _) => {
+                self.control_state.signal_overlay = !self.control_state.signal_overlay;
+            }
+            glfw::WindowEvent::Key(Key::Tab, _, Action::Press, _) => {
+                self.control_state.show_player_list = true;
+            }
+            glfw::WindowEvent::Key(Key::Tab, _, Action::Release, _) => {
+                self.control_state.show_player_list = false;
+            }
+            glfw::WindowEvent::Key(Key::P, _, Action::Press, _) => {
+                self.control_state.replay_pause = true;
+            }
+            glfw::WindowEvent::Key(Key::Left, _, Action::Press, _)
+            | glfw::WindowEvent::Key(Key::Left, _, Action::Repeat, _) => {
+                self.control_state.replay_seek -= 5.0;
+            }
+            glfw::WindowEvent::Key(Key::Right, _, Action::Press, _)
+            | glfw::WindowEvent::Key(Key::Right, _, Action::Repeat, _) => {
+                self.control_state.replay_seek += 5.0;
+            }
+            glfw::WindowEvent::Key(Key::Up, _, Action::Press, _) => {
+                self.control_state.replay_speed += 1;
+            }
+            glfw::WindowEvent::Key(Key::Down, _, Action::Press, _) => {
+                self.control_state.replay_speed -= 1;
+            }
+            glfw::WindowEvent::FramebufferSize(width, height) => {
+                self.width = width.max(0) as u32;
+                self.height = height.max(0) as u32;
+            }
 
             _ => {}
         }
@@ -186,6 +383,40 @@ impl ControlState {
             right: false,
             up: false,
             down: false,
+            sprint: false,
+            scroll: 0.0,
+            memory_report: false,
+            screenshot: false,
+            high_res_screenshot: false,
+            show_player_list: false,
+            signal_overlay: false,
+            use_block: false,
+            trace_network: false,
+            selection_wand: false,
+            select_pos1: false,
+            select_pos2: false,
+            export_selection: false,
+            toggle_ghost: false,
+            cycle_slice: false,
+            toggle_xray: false,
+            xray_hide_type: false,
+            xray_unhide_type: false,
+            slice_offset: 0,
+            move_ghost: false,
+            toggle_probe: false,
+            export_probes: false,
+            cycle_perspective: false,
+            toggle_spectator: false,
+            add_keyframe: false,
+            remove_keyframe: false,
+            save_camera_path: false,
+            toggle_camera_path: false,
+            record_camera_path: false,
+            toggle_frame_recording: false,
+            fov_offset: 0.0,
+            replay_pause: false,
+            replay_seek: 0.0,
+            replay_speed: 0,
             pitch: 0.0,
             yaw: 0.0,
             last_cursor_x: 0.0,
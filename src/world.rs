@@ -0,0 +1,680 @@
+use crate::block::{Block, Direction};
+use crate::block_states::BlockStates;
+use crate::nbt::Tag;
+use crate::packet::ClientboundPacket;
+use nalgebra::Vector3;
+use std::collections::{HashMap, HashSet};
+
+pub const SECTION_SIZE: i32 = 16;
+pub const SECTIONS_PER_CHUNK: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SectionPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub fn new(x: i32, y: i32, z: i32) -> BlockPos {
+        BlockPos { x, y, z }
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32, dz: i32) -> BlockPos {
+        BlockPos::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    /// The neighbouring position in a direction.
+    pub fn relative(&self, direction: Direction) -> BlockPos {
+        let (dx, dy, dz) = direction.offset();
+        self.offset(dx, dy, dz)
+    }
+
+    pub fn chunk_pos(&self) -> ChunkPos {
+        ChunkPos::new(self.x >> 4, self.z >> 4)
+    }
+
+    pub fn section_pos(&self) -> SectionPos {
+        SectionPos::new(self.x >> 4, self.y >> 4, self.z >> 4)
+    }
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, z: i32) -> ChunkPos {
+        ChunkPos { x, z }
+    }
+}
+
+impl SectionPos {
+    pub fn new(x: i32, y: i32, z: i32) -> SectionPos {
+        SectionPos { x, y, z }
+    }
+
+    /// The block position of the section's lowest corner.
+    pub fn origin(&self) -> BlockPos {
+        BlockPos::new(
+            self.x * SECTION_SIZE,
+            self.y * SECTION_SIZE,
+            self.z * SECTION_SIZE,
+        )
+    }
+}
+
+pub struct ChunkSection {
+    blocks: Vec<Block>,
+    block_count: u32,
+}
+
+impl Default for ChunkSection {
+    fn default() -> ChunkSection {
+        ChunkSection::new()
+    }
+}
+
+impl ChunkSection {
+    pub fn new() -> ChunkSection {
+        ChunkSection {
+            blocks: vec![Block::Air; 4096],
+            block_count: 0,
+        }
+    }
+
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        ((y << 8) | (z << 4) | x) as usize
+    }
+
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Block {
+        self.blocks[ChunkSection::index(x, y, z)]
+    }
+
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) {
+        let old = std::mem::replace(&mut self.blocks[ChunkSection::index(x, y, z)], block);
+        if old.is_air() && !block.is_air() {
+            self.block_count += 1;
+        } else if !old.is_air() && block.is_air() {
+            self.block_count -= 1;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_count == 0
+    }
+}
+
+/// Light levels for a section, stored as the nibble arrays sent in the
+/// chunk light data.
+pub struct LightSection {
+    sky_light: Vec<u8>,
+    block_light: Vec<u8>,
+}
+
+impl LightSection {
+    fn new() -> LightSection {
+        LightSection {
+            sky_light: vec![0xff; 2048],
+            block_light: vec![0; 2048],
+        }
+    }
+
+    fn get_nibble(array: &[u8], x: i32, y: i32, z: i32) -> u8 {
+        let index = ChunkSection::index(x, y, z);
+        (array[index >> 1] >> ((index & 1) * 4)) & 0xf
+    }
+
+    pub fn get_sky_light(&self, x: i32, y: i32, z: i32) -> u8 {
+        LightSection::get_nibble(&self.sky_light, x, y, z)
+    }
+
+    pub fn get_block_light(&self, x: i32, y: i32, z: i32) -> u8 {
+        LightSection::get_nibble(&self.block_light, x, y, z)
+    }
+}
+
+pub struct Chunk {
+    sections: Vec<Option<ChunkSection>>,
+    light: Vec<Option<LightSection>>,
+    // Block entity data as it was sent or saved, keyed by the position of
+    // its block within the chunk.
+    block_entities: HashMap<(i32, i32, i32), Tag>,
+}
+
+impl Default for Chunk {
+    fn default() -> Chunk {
+        Chunk::new()
+    }
+}
+
+impl Chunk {
+    pub fn new() -> Chunk {
+        let mut sections = Vec::with_capacity(SECTIONS_PER_CHUNK);
+        sections.resize_with(SECTIONS_PER_CHUNK, || None);
+        let mut light = Vec::with_capacity(SECTIONS_PER_CHUNK);
+        light.resize_with(SECTIONS_PER_CHUNK, || None);
+        Chunk {
+            sections,
+            light,
+            block_entities: HashMap::new(),
+        }
+    }
+
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Block {
+        if y < 0 || y >= SECTIONS_PER_CHUNK as i32 * SECTION_SIZE {
+            return Block::Air;
+        }
+        match &self.sections[(y >> 4) as usize] {
+            Some(section) => section.get_block(x, y & 15, z),
+            None => Block::Air,
+        }
+    }
+
+    /// Returns false if the position lies outside of the chunk's height.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) -> bool {
+        if y < 0 || y >= SECTIONS_PER_CHUNK as i32 * SECTION_SIZE {
+            return false;
+        }
+        let slot = &mut self.sections[(y >> 4) as usize];
+        if slot.is_none() {
+            if block.is_air() {
+                return true;
+            }
+            *slot = Some(ChunkSection::new());
+        }
+        let section = slot.as_mut().unwrap();
+        section.set_block(x, y & 15, z, block);
+        if section.is_empty() {
+            *slot = None;
+        }
+        true
+    }
+
+    /// Returns the sky and block light at a position. Sections we haven't
+    /// received light data for are treated as open to the sky.
+    pub fn get_light(&self, x: i32, y: i32, z: i32) -> (u8, u8) {
+        if y < 0 {
+            return (0, 0);
+        }
+        if y >= SECTIONS_PER_CHUNK as i32 * SECTION_SIZE {
+            return (15, 0);
+        }
+        match &self.light[(y >> 4) as usize] {
+            Some(light) => (
+                light.get_sky_light(x, y & 15, z),
+                light.get_block_light(x, y & 15, z),
+            ),
+            None => (15, 0),
+        }
+    }
+
+    /// Replaces a whole section of blocks. Sections that are all air
+    /// aren't kept.
+    pub fn set_section(&mut self, section_y: i32, section: ChunkSection) {
+        if section_y < 0 || section_y >= SECTIONS_PER_CHUNK as i32 {
+            return;
+        }
+        self.sections[section_y as usize] = Some(section).filter(|section| !section.is_empty());
+    }
+
+    /// Replaces the light of a section with 2048 byte nibble arrays, as
+    /// found in the chunk light data. A missing array leaves that kind of
+    /// light as it was. Returns false, changing nothing, if an array is the
+    /// wrong length.
+    pub fn set_section_light(
+        &mut self,
+        section_y: i32,
+        sky_light: Option<&[u8]>,
+        block_light: Option<&[u8]>,
+    ) -> bool {
+        let wrong_length =
+            |light: Option<&[u8]>| light.filter(|light| light.len() != 2048).is_some();
+        if wrong_length(sky_light) || wrong_length(block_light) {
+            return false;
+        }
+        if section_y < 0 || section_y >= SECTIONS_PER_CHUNK as i32 {
+            return true;
+        }
+        let light = self.light[section_y as usize].get_or_insert_with(LightSection::new);
+        if let Some(sky_light) = sky_light {
+            light.sky_light.copy_from_slice(sky_light);
+        }
+        if let Some(block_light) = block_light {
+            light.block_light.copy_from_slice(block_light);
+        }
+        true
+    }
+
+    /// Keeps the block entity data of a block, such as the items in a
+    /// container or the output of a comparator. The data is a compound
+    /// holding the block's world position in `x`, `y` and `z`, the way
+    /// chunk packets and saves store it, and is dropped without one.
+    pub fn insert_block_entity(&mut self, data: Tag) {
+        let coordinate = |name| data.get(name).and_then(Tag::as_i32);
+        if let (Some(x), Some(y), Some(z)) = (coordinate("x"), coordinate("y"), coordinate("z")) {
+            self.block_entities.insert((x & 15, y, z & 15), data);
+        }
+    }
+
+    pub fn get_block_entity(&self, x: i32, y: i32, z: i32) -> Option<&Tag> {
+        self.block_entities.get(&(x, y, z))
+    }
+
+    fn remove_block_entity(&mut self, x: i32, y: i32, z: i32) {
+        self.block_entities.remove(&(x, y, z));
+    }
+
+    /// Drops the block entities of a section, when its blocks are
+    /// replaced.
+    fn clear_section_block_entities(&mut self, section_y: i32) {
+        self.block_entities
+            .retain(|(_, y, _), _| y >> 4 != section_y);
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        let section_count = self.sections.iter().filter(|s| s.is_some()).count();
+        let light_count = self.light.iter().filter(|l| l.is_some()).count();
+        std::mem::size_of::<Chunk>()
+            + self.sections.len() * std::mem::size_of::<Option<ChunkSection>>()
+            + self.light.len() * std::mem::size_of::<Option<LightSection>>()
+            + section_count * 4096 * std::mem::size_of::<Block>()
+            + light_count * 4096
+    }
+
+    #[allow(dead_code)]
+    pub fn get_section(&self, section_y: i32) -> Option<&ChunkSection> {
+        if section_y < 0 || section_y >= SECTIONS_PER_CHUNK as i32 {
+            return None;
+        }
+        self.sections[section_y as usize].as_ref()
+    }
+}
+
+pub struct World {
+    chunks: HashMap<ChunkPos, Chunk>,
+    dirty_sections: HashSet<SectionPos>,
+    // Chunks whose blocks have changed since they were inserted, so that
+    // they no longer match wherever they were loaded from.
+    edited_chunks: HashSet<ChunkPos>,
+}
+
+impl Default for World {
+    fn default() -> World {
+        World::new()
+    }
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            chunks: HashMap::new(),
+            dirty_sections: HashSet::new(),
+            edited_chunks: HashSet::new(),
+        }
+    }
+
+    pub fn get_block(&self, pos: BlockPos) -> Block {
+        match self.chunks.get(&pos.chunk_pos()) {
+            Some(chunk) => chunk.get_block(pos.x & 15, pos.y, pos.z & 15),
+            None => Block::Air,
+        }
+    }
+
+    pub fn get_light(&self, pos: BlockPos) -> (u8, u8) {
+        match self.chunks.get(&pos.chunk_pos()) {
+            Some(chunk) => chunk.get_light(pos.x & 15, pos.y, pos.z & 15),
+            None => (15, 0),
+        }
+    }
+
+    /// Updates the light of a section from nibble arrays. Servers send a
+    /// chunk's light before its blocks, so the chunk is created if it isn't
+    /// loaded yet. Light spills into the meshes of neighbouring sections
+    /// through smooth lighting, so they are flagged for meshing too.
+    /// Returns false if an array is the wrong length.
+    pub fn set_section_light(
+        &mut self,
+        pos: SectionPos,
+        sky_light: Option<&[u8]>,
+        block_light: Option<&[u8]>,
+    ) -> bool {
+        let chunk = self.chunks.entry(ChunkPos::new(pos.x, pos.z)).or_default();
+        if !chunk.set_section_light(pos.y, sky_light, block_light) {
+            return false;
+        }
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    self.dirty_sections
+                        .insert(SectionPos::new(pos.x + dx, pos.y + dy, pos.z + dz));
+                }
+            }
+        }
+        true
+    }
+
+    /// Applies the chunks, light and block changes sent by the server,
+    /// looking blocks up by their global state ids.
+    pub fn handle_packet(&mut self, packet: &ClientboundPacket, states: &BlockStates) {
+        match packet {
+            ClientboundPacket::ChunkData {
+                x,
+                z,
+                full,
+                sections,
+                block_entities,
+            } => {
+                let pos = ChunkPos::new(*x, *z);
+                let chunk = self.chunks.entry(pos).or_default();
+                if *full {
+                    for y in 0..SECTIONS_PER_CHUNK as i32 {
+                        chunk.set_section(y, ChunkSection::new());
+                    }
+                    chunk.block_entities.clear();
+                }
+                for data in sections {
+                    chunk.clear_section_block_entities(data.y);
+                    let mut section = ChunkSection::new();
+                    for (i, state) in data.states.iter().enumerate() {
+                        let i = i as i32;
+                        section.set_block(i & 15, i >> 8, (i >> 4) & 15, states.get(*state));
+                    }
+                    chunk.set_section(data.y, section);
+                }
+                for data in block_entities {
+                    chunk.insert_block_entity(data.clone());
+                }
+                self.mark_chunk_dirty(pos);
+            }
+            ClientboundPacket::UnloadChunk { x, z } => {
+                self.remove_chunk(ChunkPos::new(*x, *z));
+            }
+            ClientboundPacket::UpdateLight {
+                x,
+                z,
+                sky_light,
+                block_light,
+            } => {
+                let mut valid = true;
+                for section in sky_light {
+                    let pos = SectionPos::new(*x, section.y, *z);
+                    valid &= self.set_section_light(pos, Some(&section.light), None);
+                }
+                for section in block_light {
+                    let pos = SectionPos::new(*x, section.y, *z);
+                    valid &= self.set_section_light(pos, None, Some(&section.light));
+                }
+                if !valid {
+                    eprintln!("Ignored light for chunk {}, {} that isn't 2048 bytes", x, z);
+                }
+            }
+            ClientboundPacket::BlockChange(record) => {
+                self.set_block(
+                    BlockPos::new(record.x, record.y, record.z),
+                    states.get(record.state),
+                );
+            }
+            ClientboundPacket::MultiBlockChange { records } => {
+                for record in records {
+                    self.set_block(
+                        BlockPos::new(record.x, record.y, record.z),
+                        states.get(record.state),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Finds the first block along a ray that `hits` accepts, stepping
+    /// through the grid one cell at a time so that no block is skipped
+    /// over.
+    pub fn raycast<F: Fn(BlockPos, Block) -> bool>(
+        &self,
+        origin: &Vector3<f32>,
+        direction: &Vector3<f32>,
+        max_distance: f32,
+        hits: F,
+    ) -> Option<BlockPos> {
+        let direction = direction.try_normalize(1.0e-6)?;
+        let mut cell = [
+            origin.x.floor() as i32,
+            origin.y.floor() as i32,
+            origin.z.floor() as i32,
+        ];
+        let mut step = [0; 3];
+        // How far along the ray the next boundary on each axis is, and how
+        // far apart those boundaries are.
+        let mut next = [f32::INFINITY; 3];
+        let mut spacing = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                spacing[axis] = 1.0 / direction[axis];
+                next[axis] = (cell[axis] as f32 + 1.0 - origin[axis]) * spacing[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                spacing[axis] = -1.0 / direction[axis];
+                next[axis] = (origin[axis] - cell[axis] as f32) * spacing[axis];
+            }
+        }
+
+        let mut distance = 0.0;
+        while distance <= max_distance {
+            let pos = BlockPos::new(cell[0], cell[1], cell[2]);
+            if hits(pos, self.get_block(pos)) {
+                return Some(pos);
+            }
+            let axis = if next[0] < next[1] && next[0] < next[2] {
+                0
+            } else if next[1] < next[2] {
+                1
+            } else {
+                2
+            };
+            cell[axis] += step[axis];
+            distance = next[axis];
+            next[axis] += spacing[axis];
+        }
+        None
+    }
+
+    /// Sets a block, creating the chunk if it isn't loaded yet. The block
+    /// entity of the block there is dropped unless only its state changed.
+    pub fn set_block(&mut self, pos: BlockPos, block: Block) {
+        let chunk = self.chunks.entry(pos.chunk_pos()).or_default();
+        let (x, z) = (pos.x & 15, pos.z & 15);
+        if chunk.get_block_entity(x, pos.y, z).is_some() {
+            let (old_name, _) = chunk.get_block(x, pos.y, z).name_and_properties();
+            if old_name != block.name_and_properties().0 {
+                chunk.remove_block_entity(x, pos.y, z);
+            }
+        }
+        if chunk.set_block(x, pos.y, z, block) {
+            self.edited_chunks.insert(pos.chunk_pos());
+            self.mark_block_dirty(pos);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn insert_chunk(&mut self, pos: ChunkPos, chunk: Chunk) {
+        self.chunks.insert(pos, chunk);
+        self.edited_chunks.remove(&pos);
+        self.mark_chunk_dirty(pos);
+    }
+
+    /// Removes a chunk from the world, returning it if it was loaded.
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&pos);
+        self.edited_chunks.remove(&pos);
+        if chunk.is_some() {
+            // The neighbouring sections were hiding faces against this
+            // chunk, so they need to be meshed again.
+            self.mark_chunk_dirty(pos);
+        }
+        chunk
+    }
+
+    /// Keeps block entity data in the chunk holding the position in its
+    /// `x`, `y` and `z`, if that chunk is loaded.
+    pub fn insert_block_entity(&mut self, data: Tag) {
+        let coordinate = |name| data.get(name).and_then(Tag::as_i32);
+        if let (Some(x), Some(z)) = (coordinate("x"), coordinate("z")) {
+            let pos = BlockPos::new(x, 0, z).chunk_pos();
+            if let Some(chunk) = self.chunks.get_mut(&pos) {
+                chunk.insert_block_entity(data);
+                self.edited_chunks.insert(pos);
+            }
+        }
+    }
+
+    pub fn get_block_entity(&self, pos: BlockPos) -> Option<&Tag> {
+        self.chunks
+            .get(&pos.chunk_pos())?
+            .get_block_entity(pos.x & 15, pos.y, pos.z & 15)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn chunk_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Whether blocks in a chunk have changed since it was inserted, say by
+    /// the redstone simulation.
+    pub fn is_chunk_edited(&self, pos: ChunkPos) -> bool {
+        self.edited_chunks.contains(&pos)
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// An estimate of the memory used by block data, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.memory_usage()).sum()
+    }
+
+    /// Flags every section of a chunk, along with the sections of the
+    /// eight chunks around it, as needing a new mesh. Meshes sample their
+    /// diagonal neighbours for ambient occlusion and smooth light too.
+    pub fn mark_chunk_dirty(&mut self, pos: ChunkPos) {
+        for y in 0..SECTIONS_PER_CHUNK as i32 {
+            for dx in -1..=1 {
+                for dz in -1..=1 {
+                    self.dirty_sections
+                        .insert(SectionPos::new(pos.x + dx, y, pos.z + dz));
+                }
+            }
+        }
+    }
+
+    /// Flags the section containing the block as needing a new mesh. Blocks
+    /// on the edge of a section also affect the mesh of the neighbouring
+    /// section, so those are flagged as well.
+    fn mark_block_dirty(&mut self, pos: BlockPos) {
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let section = pos.offset(dx, dy, dz).section_pos();
+                    self.dirty_sections.insert(section);
+                }
+            }
+        }
+    }
+
+    /// Returns the sections that need meshing. Sections belonging to
+    /// chunks that aren't loaded are skipped.
+    pub fn take_dirty_sections(&mut self) -> Vec<SectionPos> {
+        let chunks = &self.chunks;
+        self.dirty_sections
+            .drain()
+            .filter(|pos| pos.y >= 0 && pos.y < SECTIONS_PER_CHUNK as i32)
+            .filter(|pos| chunks.contains_key(&ChunkPos::new(pos.x, pos.z)))
+            .collect()
+    }
+
+    /// Copies a section along with a one block border of its neighbours,
+    /// giving the mesher everything it needs without touching the world.
+    pub fn snapshot_section(&self, pos: SectionPos) -> SectionSnapshot {
+        let origin = pos.origin();
+        let mut snapshot = SectionSnapshot {
+            pos,
+            blocks: vec![Block::Air; SNAPSHOT_VOLUME],
+            light: vec![0; SNAPSHOT_VOLUME],
+        };
+
+        for y in -1..=SECTION_SIZE {
+            for z in -1..=SECTION_SIZE {
+                for x in -1..=SECTION_SIZE {
+                    let index = SectionSnapshot::index(x, y, z);
+                    let block_pos = origin.offset(x, y, z);
+                    let (sky_light, block_light) = self.get_light(block_pos);
+                    snapshot.blocks[index] = self.get_block(block_pos);
+                    snapshot.light[index] = (sky_light << 4) | block_light;
+                }
+            }
+        }
+        snapshot
+    }
+}
+
+const SNAPSHOT_SIZE: i32 = SECTION_SIZE + 2;
+const SNAPSHOT_VOLUME: usize = (SNAPSHOT_SIZE * SNAPSHOT_SIZE * SNAPSHOT_SIZE) as usize;
+
+/// A self contained copy of a section and its bordering blocks that can be
+/// handed off to a meshing thread.
+pub struct SectionSnapshot {
+    pub pos: SectionPos,
+    blocks: Vec<Block>,
+    // Sky light in the high nibble, block light in the low nibble.
+    light: Vec<u8>,
+}
+
+impl SectionSnapshot {
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        (((y + 1) * SNAPSHOT_SIZE + (z + 1)) * SNAPSHOT_SIZE + (x + 1)) as usize
+    }
+
+    /// Looks up a block relative to the section origin. Coordinates range
+    /// from -1 to 16 inclusive, covering the neighbouring border.
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Block {
+        self.blocks[SectionSnapshot::index(x, y, z)]
+    }
+
+    /// Returns the sky and block light relative to the section origin.
+    pub fn get_light(&self, x: i32, y: i32, z: i32) -> (u8, u8) {
+        let light = self.light[SectionSnapshot::index(x, y, z)];
+        (light >> 4, light & 0xf)
+    }
+
+    /// Replaces the blocks `hidden` picks out with air, lit as if open to
+    /// the sky so that whatever they uncover can be seen.
+    pub fn hide_blocks<F: FnMut(BlockPos, Block) -> bool>(&mut self, mut hidden: F) {
+        let origin = self.pos.origin();
+        for y in -1..=SECTION_SIZE {
+            for z in -1..=SECTION_SIZE {
+                for x in -1..=SECTION_SIZE {
+                    let index = SectionSnapshot::index(x, y, z);
+                    if hidden(origin.offset(x, y, z), self.blocks[index]) {
+                        self.blocks[index] = Block::Air;
+                        self.light[index] |= 0xf0;
+                    }
+                }
+            }
+        }
+    }
+}